@@ -0,0 +1,341 @@
+// `cleanlog browse`: full-screen menus in the terminal for hands-on cleanup sessions.
+// The first screen lists every configured file with its size, line count and oldest entry. Selecting one
+// shows how old its lines are as a histogram, its retention period and its last lines; entering a cutoff
+// date then reports how many lines before it would be removed and, once confirmed, removes them.
+// A prune keeps the file's keep patterns, sections and line floor like expiry does, but the safety cap
+// doesn't apply, since every prune is confirmed.
+
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+use chrono::{DateTime, Local, Utc};
+use logstamp::TimestampFormat;
+
+use crate::{
+    clean_log_file, compile_patterns, format_size, get_default_encoding, get_default_strategy,
+    get_root_directory, parse_window_date, read_ignore_patterns, resolve_paths, Encoding,
+    LogConfig, LogRoot, Outcome, PrunePolicy, Strategy, LOG_ROOTS,
+};
+
+/// Escape sequence that clears the terminal and moves the cursor to its top left corner
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+
+/// Number of last lines shown as a file's preview
+const PREVIEW_LINES: usize = 8;
+
+/// Width of the longest bar of the age histogram, in characters
+const HISTOGRAM_WIDTH: usize = 40;
+
+/// Age buckets of the histogram: a label and the age in days up to which a line falls into it
+const AGE_BUCKETS: &[(&str, i64)] = &[
+    ("today", 1),
+    ("1-7 days", 7),
+    ("1-4 weeks", 28),
+    ("1-3 months", 91),
+    ("3-6 months", 182),
+    ("6-12 months", 365),
+    ("older", i64::MAX),
+];
+
+/// A configured file resolved to its path, with the settings of its root filled in
+struct Entry {
+    path: PathBuf,
+    config: &'static LogConfig,
+    encoding: Encoding,
+    strategy: Strategy,
+    retention_days: u32,
+}
+
+/// What a file's lines tell about its age
+struct Summary {
+    lines: usize,                          // The number of lines
+    bytes: usize,                          // The size of the file
+    oldest: Option<String>, // The oldest timestamp in local time, if any line has one
+    newest: Option<String>, // The newest timestamp in local time, if any line has one
+    histogram: [usize; AGE_BUCKETS.len()], // Timestamped lines per age bucket
+    preview: Vec<String>,   // The last lines of the file
+}
+
+/// Lists every configured file that exists, in configuration order
+/// Roots that are missing and wildcard paths that can't be resolved are left out
+fn collect_entries() -> Vec<Entry> {
+    let mut entries = Vec::new();
+    for root in LOG_ROOTS {
+        let root_directory = get_root_directory(root);
+        if !root_directory.is_dir() {
+            continue;
+        }
+        let ignore = read_ignore_patterns(&root_directory);
+        for config in root.files {
+            let paths =
+                resolve_paths(&root_directory, config.relative_path, &ignore).unwrap_or_default();
+            entries.extend(
+                paths
+                    .into_iter()
+                    .filter(|path| path.is_file())
+                    .map(|path| entry(path, root, config)),
+            );
+        }
+    }
+    entries
+}
+
+/// Resolves the settings of a file that fall back to its root's defaults
+fn entry(path: PathBuf, root: &'static LogRoot, config: &'static LogConfig) -> Entry {
+    Entry {
+        path,
+        config,
+        encoding: config
+            .encoding
+            .unwrap_or_else(|| get_default_encoding(root)),
+        strategy: config
+            .strategy
+            .unwrap_or_else(|| get_default_strategy(root)),
+        retention_days: config.retention_days.unwrap_or(root.retention_days),
+    }
+}
+
+/// Reads a file and summarizes the age of its lines
+fn summarize(entry: &Entry) -> io::Result<Summary> {
+    let data = fs::read(&entry.path)?;
+    let format = TimestampFormat::for_log(
+        &entry
+            .path
+            .file_name()
+            .map_or_else(String::new, |name| name.to_string_lossy().into_owned()),
+    );
+    let lines = entry.encoding.split_lines(&data);
+    let now = Utc::now();
+
+    let mut histogram = [0; AGE_BUCKETS.len()];
+    let (mut oldest, mut newest) = (None, None);
+    for line in &lines {
+        let Some(timestamp) = format.parse_line(&entry.encoding.decode(line)) else {
+            continue;
+        };
+        let age = (now - timestamp).num_days();
+        let bucket = AGE_BUCKETS
+            .iter()
+            .position(|(_, days)| age < *days)
+            .unwrap_or(AGE_BUCKETS.len() - 1);
+        histogram[bucket] += 1;
+        oldest = Some(oldest.map_or(timestamp, |oldest: DateTime<Utc>| oldest.min(timestamp)));
+        newest = newest.max(Some(timestamp));
+    }
+
+    let local = |time: Option<DateTime<Utc>>| {
+        time.map(|time| {
+            time.with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()
+        })
+    };
+    let preview = lines[lines.len().saturating_sub(PREVIEW_LINES)..]
+        .iter()
+        .map(|line| entry.encoding.decode(line).trim_end().to_string())
+        .collect();
+    Ok(Summary {
+        lines: lines.len(),
+        bytes: data.len(),
+        oldest: local(oldest),
+        newest: local(newest),
+        histogram,
+        preview,
+    })
+}
+
+/// Prints a prompt and reads the answer, trimmed
+/// Returns None once standard input is closed, which ends the session
+fn prompt(input: &mut impl BufRead, text: &str) -> io::Result<Option<String>> {
+    print!("{}", text);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    if input.read_line(&mut answer)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(answer.trim().to_string()))
+}
+
+/// Shows the list of configured files and lets the user pick one
+/// Returns the index of the selected file, or None to quit
+fn select_file(entries: &[Entry], input: &mut impl BufRead) -> io::Result<Option<usize>> {
+    let mut message = String::new();
+    loop {
+        print!("{}", CLEAR_SCREEN);
+        println!("cleanlog browse - {} files\n", entries.len());
+        println!(
+            "{:>4}  {:>10}  {:>9}  {:<19}  file",
+            "#", "size", "lines", "oldest"
+        );
+        for (index, entry) in entries.iter().enumerate() {
+            let (size, lines, oldest) = match summarize(entry) {
+                Ok(summary) => (
+                    format_size(summary.bytes as u64),
+                    summary.lines.to_string(),
+                    summary.oldest.unwrap_or_else(|| String::from("-")),
+                ),
+                Err(_) => (
+                    String::from("?"),
+                    String::from("?"),
+                    String::from("unreadable"),
+                ),
+            };
+            println!(
+                "{:>4}  {:>10}  {:>9}  {:<19}  {}",
+                index + 1,
+                size,
+                lines,
+                oldest,
+                entry.path.display()
+            );
+        }
+        if !message.is_empty() {
+            println!("\n{}", message);
+        }
+
+        let Some(answer) = prompt(input, "\nSelect a file by number, or q to quit: ")? else {
+            return Ok(None);
+        };
+        match answer.as_str() {
+            "q" => return Ok(None),
+            _ => match answer.parse::<usize>() {
+                Ok(number) if (1..=entries.len()).contains(&number) => return Ok(Some(number - 1)),
+                _ => message = format!("No file numbered '{}'.", answer),
+            },
+        }
+    }
+}
+
+/// Shows a file's age histogram and preview, and prunes it up to the cutoff dates the user enters
+/// Returns false once standard input is closed
+fn browse_file(entry: &Entry, input: &mut impl BufRead) -> io::Result<bool> {
+    let mut message = String::new();
+    loop {
+        print!("{}", CLEAR_SCREEN);
+        println!("{}\n", entry.path.display());
+        match summarize(entry) {
+            Ok(summary) => print_summary(entry, &summary),
+            Err(error) => println!("Can't read the file: {}", error),
+        }
+        if !message.is_empty() {
+            println!("\n{}", message);
+        }
+
+        let Some(answer) = prompt(
+            input,
+            "\nEnter a cutoff date (YYYY-MM-DD) to remove older lines, or b to go back: ",
+        )?
+        else {
+            return Ok(false);
+        };
+        if answer == "b" {
+            return Ok(true);
+        }
+        let Some(cutoff) = parse_window_date(&answer) else {
+            message = format!("Invalid date: {}", answer);
+            continue;
+        };
+
+        // Count the lines first, and only remove them once the user confirmed
+        let policy = PrunePolicy::Cutoff(cutoff);
+        let removed = match prune(entry, policy, true)? {
+            0 => {
+                message = format!("No line before {} can be removed.", answer);
+                continue;
+            }
+            removed => removed,
+        };
+        let Some(confirmation) = prompt(
+            input,
+            &format!(
+                "Remove {} lines before {} from {}? [y/N] ",
+                removed,
+                answer,
+                entry.path.display()
+            ),
+        )?
+        else {
+            return Ok(false);
+        };
+        message = if confirmation.eq_ignore_ascii_case("y") {
+            format!(
+                "Removed {} lines before {}.",
+                prune(entry, policy, false)?,
+                answer
+            )
+        } else {
+            String::from("Nothing removed.")
+        };
+    }
+}
+
+/// Prints a file's size, age range, retention period, age histogram and last lines
+fn print_summary(entry: &Entry, summary: &Summary) {
+    println!(
+        "{} lines, {}, retention {} days",
+        summary.lines,
+        format_size(summary.bytes as u64),
+        entry.retention_days
+    );
+    if let (Some(oldest), Some(newest)) = (&summary.oldest, &summary.newest) {
+        println!("From {} to {}", oldest, newest);
+    }
+
+    println!("\nLines by age:");
+    let max = summary.histogram.iter().copied().max().unwrap_or(0).max(1);
+    for ((label, _), count) in AGE_BUCKETS.iter().zip(summary.histogram) {
+        // Every bucket with lines gets at least one mark, so small ones don't look empty
+        let width = (count * HISTOGRAM_WIDTH).div_ceil(max);
+        println!("  {:>11}  {:>9}  {}", label, count, "#".repeat(width));
+    }
+
+    println!("\nLast lines:");
+    for line in &summary.preview {
+        println!("  {}", line);
+    }
+}
+
+/// Applies a prune policy to a file with its keep patterns, section marker and line floor
+/// Returns the number of lines removed, or that would be removed in a dry run
+fn prune(entry: &Entry, policy: PrunePolicy, dry_run: bool) -> io::Result<usize> {
+    let format = TimestampFormat::for_log(
+        &entry
+            .path
+            .file_name()
+            .map_or_else(String::new, |name| name.to_string_lossy().into_owned()),
+    );
+    let keep = compile_patterns(entry.config.keep_patterns)?;
+    let outcome = clean_log_file(
+        &entry.path,
+        entry.encoding,
+        &format,
+        entry.strategy,
+        &keep,
+        entry.config.section_marker,
+        entry.config.min_keep_lines,
+        policy,
+        None,
+        dry_run,
+    )?;
+    Ok(match outcome {
+        Outcome::Cleaned { removed, .. } | Outcome::Capped { removed, .. } => removed,
+    })
+}
+
+/// Runs the browse session until the user quits or standard input is closed
+pub fn run() -> io::Result<()> {
+    let entries = collect_entries();
+    if entries.is_empty() {
+        println!("No configured log file exists.");
+        return Ok(());
+    }
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    while let Some(index) = select_file(&entries, &mut input)? {
+        if !browse_file(&entries[index], &mut input)? {
+            break;
+        }
+    }
+    Ok(())
+}
@@ -1,150 +1,1891 @@
 // This program manages log file retention by removing entries older than specified retention periods.
-// It processes log files that contain timestamps in the format [YYYY-MM-DD HH:MM:SS] at the start
-// of each line. Lines without timestamps are preserved.
+// It processes log files that contain a bracketed timestamp at the start of each line, by default
+// [YYYY-MM-DD HH:MM:SS] in local time. Per-file formats and time zones are read from the same
+// LOG_TIMESTAMP_FORMAT[_<LOG>] and LOG_TIMESTAMP_ZONE[_<LOG>] variables the writing helpers use (see the
+// logstamp crate), so set them for cleanlog as well. Lines without timestamps are preserved. Each file can declare its character encoding;
+// lines are only decoded to read their timestamp, and kept lines are written back byte for byte.
+// Log files are grouped by root directory (LOG_HOME and any others configured in LOG_ROOTS), and each
+// root provides the default retention period and encoding for its files. CLEANLOG_ENCODING (utf-8,
+// latin-1, utf-16le or utf-16be) overrides the roots' default encoding.
+//
+// Usage: cleanlog [--since <DATE>] [--before <DATE>] [--dry-run] [--force] [--stats]
+//        cleanlog install-agent [--interval <SCHEDULE>]
+//        cleanlog browse
+// Without a window the configured retention periods apply. With --since and/or --before, lines
+// whose timestamp falls within [since, before) are removed from every configured file instead.
+// DATE is either YYYY-MM-DD (midnight) or "YYYY-MM-DD HH:MM:SS", in local time. --dry-run only reports counts.
+//
+// Files are cleaned by rewriting every line that is kept. Large files whose expired lines are all at the
+// head can use the truncate-head strategy instead, which finds the first retained line by binary search
+// on its timestamps and copies only the rest of the file. CLEANLOG_STRATEGY (rewrite or truncate-head)
+// overrides the roots' default strategy.
+//
+// A file's relative path may contain "*" and "?" wildcards in its file name, e.g. "mytool.*.log", to
+// clean every matching file in that directory with the same settings. Files matching a glob pattern in
+// the root's .cleanlogignore (one per line, against the path relative to the root; "#" starts a comment)
+// are left out, so temporary or externally managed logs can be protected without editing this list.
+//
+// Lines matching one of a file's keep patterns (regular expressions, e.g. "ERROR" or "failed") are never
+// removed, whatever their age, so a permanent failure history survives while routine output expires.
+// Files with keep patterns are always cleaned by rewriting.
+//
+// A file can declare a section marker, such as the run start marker the helpers log (see the logstamp
+// crate). Each line containing it starts a section that is removed as a whole: when the section's first
+// timestamp expires and none of its lines matches a keep pattern. Lines before the first marker are
+// pruned one by one. Files with a section marker are always cleaned by rewriting.
+//
+// A file can set a floor of lines that survive its retention period: the last min_keep_lines lines (and
+// the rest of any section they start in) are kept even if they expired, so a log of a Mac that was off
+// for a month still shows what happened before the gap. The floor doesn't apply to --since/--before
+// windows. Files with a floor are always cleaned by rewriting.
+// --stats reports the size of every configured file and of its sections instead of cleaning.
+//
+// As a guard against clock skew or timezone bugs, a file is skipped with a warning if a run would remove
+// more than CLEANLOG_MAX_REMOVAL_PERCENT (default 90) percent of its lines. A file can set its own
+// limit, and --force removes the limit for a run.
+//
+// `cleanlog install-agent [--interval <SCHEDULE>]` writes a LaunchAgent that runs this binary on a
+// schedule to ~/Library/LaunchAgents/gg.hw.cleanlog.plist and (re)loads it with launchctl. SCHEDULE is
+// daily@HH:MM, hourly@MM, or an interval such as 30m or 6h (default daily@03:04). The LOG_HOME,
+// CLEANLOG_* and LOG_TIMESTAMP_* variables set when installing are copied into the agent.
+//
+// `cleanlog browse` lists the configured files with their sizes in the terminal; for a selected file it
+// shows an age histogram and its last lines, and prunes it up to a cutoff date after confirmation (see
+// browse.rs).
+//
+// After a notable run a summary can be sent by email (CLEANLOG_EMAIL, via sendmail) and/or to a
+// webhook (CLEANLOG_WEBHOOK_URL, via curl, as a JSON object with a "text" field). It lists the lines
+// and bytes removed per file. A run is notable if a file failed or was capped by the safety cap, a
+// root directory was missing, or at least CLEANLOG_NOTIFY_MIN_LINES (default 1000, 0 for every run)
+// lines were removed.
+
+// The interactive browse session
+mod browse;
 
 // Standard library imports
 use std::env; // For reading environment variables
-use std::fs::File; // File system operations
-use std::io::{self, BufRead, BufReader, Write}; // Input/Output operations
+use std::fs::{self, File}; // File system operations
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write}; // Input/Output operations
 use std::path::{Path, PathBuf}; // Path manipulation utilities
-use std::process; // For exiting the program
+use std::process::{self, Command, Stdio}; // For exiting the program and sending the summary
 
 // External crate imports
-use chrono::{Duration, NaiveDateTime, Utc}; // DateTime handling and calculations
+use chrono::{Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Utc}; // DateTime handling and calculations
+use logstamp::{TimestampFormat, RUN_START_MARKER}; // The timestamp format and section marker of each log file
+use regex::Regex; // Keep patterns of lines exempt from pruning
 use tempfile::NamedTempFile; // Temporary file operations for safe file writing
 
+// Character encodings a log file can be written in
+#[derive(Debug, Clone, Copy)]
+enum Encoding {
+    Utf8,    // UTF-8; invalid sequences are tolerated
+    Latin1,  // ISO-8859-1, one byte per character
+    Utf16Le, // UTF-16, little endian
+    Utf16Be, // UTF-16, big endian
+}
+
+impl Encoding {
+    /// Parses an encoding name: utf-8, latin-1, utf-16le or utf-16be (case-insensitive)
+    fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Some(Encoding::Utf8),
+            "latin-1" | "latin1" | "iso-8859-1" => Some(Encoding::Latin1),
+            "utf-16le" | "utf16le" => Some(Encoding::Utf16Le),
+            "utf-16be" | "utf16be" => Some(Encoding::Utf16Be),
+            _ => None,
+        }
+    }
+
+    /// Returns the encoded newline character
+    /// Lines are split after it, and its length is the size of a code unit
+    fn newline(self) -> &'static [u8] {
+        match self {
+            Encoding::Utf8 | Encoding::Latin1 => b"\n",
+            Encoding::Utf16Le => &[0x0A, 0x00],
+            Encoding::Utf16Be => &[0x00, 0x0A],
+        }
+    }
+
+    /// Returns the byte order mark files in this encoding may start with
+    fn byte_order_mark(self) -> &'static [u8] {
+        match self {
+            Encoding::Utf8 => &[0xEF, 0xBB, 0xBF],
+            Encoding::Latin1 => &[],
+            Encoding::Utf16Le => &[0xFF, 0xFE],
+            Encoding::Utf16Be => &[0xFE, 0xFF],
+        }
+    }
+
+    /// Decodes a raw line so its timestamp can be parsed
+    /// Invalid sequences become replacement characters
+    fn decode(self, bytes: &[u8]) -> String {
+        match self {
+            Encoding::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            Encoding::Latin1 => bytes.iter().map(|&byte| byte as char).collect(),
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let units = bytes.as_chunks::<2>().0.iter().map(|&pair| match self {
+                    Encoding::Utf16Le => u16::from_le_bytes(pair),
+                    _ => u16::from_be_bytes(pair),
+                });
+                char::decode_utf16(units)
+                    .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                    .collect()
+            }
+        }
+    }
+
+    /// Splits raw file content into lines, each including its newline
+    fn split_lines(self, data: &[u8]) -> Vec<&[u8]> {
+        let newline = self.newline();
+        let mut lines = Vec::new();
+        let mut start = 0;
+        let mut position = 0;
+
+        // Walk the content one code unit at a time so multi-byte units are never split
+        while position + newline.len() <= data.len() {
+            position += newline.len();
+            if &data[position - newline.len()..position] == newline {
+                lines.push(&data[start..position]);
+                start = position;
+            }
+        }
+
+        // Keep a final line without a trailing newline (and any stray trailing byte)
+        if start < data.len() {
+            lines.push(&data[start..]);
+        }
+        lines
+    }
+}
+
+// How a log file is cleaned
+#[derive(Debug, Clone, Copy)]
+enum Strategy {
+    Rewrite, // Check every line and write back the ones that are kept
+    // Drop the expired head and copy the rest unchanged. Assumes the timestamps are in order, and also
+    // drops lines without a timestamp that precede the first retained line. Falls back to rewriting
+    // when the prune policy doesn't only remove a head, i.e. for a --since window.
+    TruncateHead,
+}
+
+impl Strategy {
+    /// Parses a strategy name: rewrite or truncate-head (case-insensitive)
+    fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "rewrite" => Some(Strategy::Rewrite),
+            "truncate-head" => Some(Strategy::TruncateHead),
+            _ => None,
+        }
+    }
+}
+
 // Configuration structure to define each log file's settings
+// Settings left as None fall back to the defaults of the file's root directory
 #[derive(Debug)]
 struct LogConfig {
-    relative_path: &'static str, // The path relative to LOG_HOME
-    retention_days: u32,         // How many days of logs to keep
+    relative_path: &'static str, // The path relative to the root directory
+    retention_days: Option<u32>, // How many days of logs to keep
+    encoding: Option<Encoding>,  // The character encoding of the file
+    max_removal_percent: Option<u32>, // Overrides the safety cap for this file; 100 disables it
+    strategy: Option<Strategy>,  // How the file is cleaned
+    keep_patterns: &'static [&'static str], // Regular expressions of lines that are never removed
+    section_marker: Option<&'static str>, // Text of the lines that start a section, if any
+    min_keep_lines: usize, // How many of the last lines survive the retention period; 0 for none
+}
+
+// Configuration structure to define a root directory and the log files inside it
+#[derive(Debug)]
+struct LogRoot {
+    directory: &'static str, // The directory; a leading "~/" is expanded to the home directory
+    env_var: Option<&'static str>, // An environment variable that overrides the directory when set
+    retention_days: u32,     // The retention period of files that don't set their own
+    encoding: Encoding,      // The encoding of files that don't set their own
+    strategy: Strategy,      // The cleaning strategy of files that don't set their own
+    files: &'static [LogConfig], // The log files in this directory
 }
 
 // Static configuration array - modify this to set up your log files
-// Each entry defines a log file path (relative to LOG_HOME) and its retention period
-const LOG_CONFIGS: &[LogConfig] = &[
-    LogConfig {
-        relative_path: "cronup.brew.log",
-        retention_days: 7,
-    },
-    LogConfig {
-        relative_path: "cronup.cargo.log",
-        retention_days: 3,
-    },
-    LogConfig {
-        relative_path: "cronup.nvim.log",
-        retention_days: 1,
-    },
-    LogConfig {
-        relative_path: "cronup.rustup.log",
-        retention_days: 5,
-    },
-    LogConfig {
-        relative_path: "snitchprot.log",
-        retention_days: 1,
+// Each root lists its log files by path relative to the root, with an optional retention period,
+// encoding, and patterns of lines to keep. To clean logs elsewhere (e.g. ~/Library/Logs/mytools), add another root.
+const LOG_ROOTS: &[LogRoot] = &[LogRoot {
+    directory: "/var/log",
+    env_var: Some("LOG_HOME"),
+    retention_days: 7,
+    encoding: Encoding::Utf8,
+    strategy: Strategy::Rewrite,
+    files: &[
+        LogConfig {
+            relative_path: "cronup.asdf.log",
+            retention_days: None,
+            encoding: None,
+            max_removal_percent: None,
+            strategy: None,
+            keep_patterns: &[],
+            section_marker: Some(RUN_START_MARKER),
+            min_keep_lines: 0,
+        },
+        LogConfig {
+            relative_path: "cronup.bootstrap.log",
+            retention_days: None,
+            encoding: None,
+            max_removal_percent: None,
+            strategy: None,
+            keep_patterns: &[],
+            section_marker: Some(RUN_START_MARKER),
+            min_keep_lines: 0,
+        },
+        LogConfig {
+            relative_path: "cronup.brew.log",
+            retention_days: None,
+            encoding: None,
+            max_removal_percent: None,
+            strategy: None,
+            keep_patterns: &[],
+            section_marker: Some(RUN_START_MARKER),
+            min_keep_lines: 0,
+        },
+        LogConfig {
+            relative_path: "cronup.cargo.log",
+            retention_days: Some(3),
+            encoding: None,
+            max_removal_percent: None,
+            strategy: None,
+            keep_patterns: &[],
+            section_marker: Some(RUN_START_MARKER),
+            min_keep_lines: 0,
+        },
+        LogConfig {
+            relative_path: "cronup.checks.log",
+            retention_days: None,
+            encoding: None,
+            max_removal_percent: None,
+            strategy: None,
+            keep_patterns: &[],
+            section_marker: Some(RUN_START_MARKER),
+            min_keep_lines: 0,
+        },
+        LogConfig {
+            relative_path: "cronup.composer.log",
+            retention_days: None,
+            encoding: None,
+            max_removal_percent: None,
+            strategy: None,
+            keep_patterns: &[],
+            section_marker: Some(RUN_START_MARKER),
+            min_keep_lines: 0,
+        },
+        LogConfig {
+            relative_path: "cronup.docker.log",
+            retention_days: None,
+            encoding: None,
+            max_removal_percent: None,
+            strategy: None,
+            keep_patterns: &[],
+            section_marker: Some(RUN_START_MARKER),
+            min_keep_lines: 0,
+        },
+        LogConfig {
+            relative_path: "cronup.drift.log",
+            retention_days: Some(180), // Weekly drift reports, kept to attribute changes months later
+            encoding: None,
+            max_removal_percent: None,
+            strategy: None,
+            keep_patterns: &[],
+            section_marker: Some(RUN_START_MARKER),
+            min_keep_lines: 0,
+        },
+        LogConfig {
+            relative_path: "cronup.errors.log",
+            retention_days: Some(90), // The failures of every updater, kept for the long view
+            encoding: None,
+            max_removal_percent: None,
+            strategy: None,
+            keep_patterns: &[],
+            section_marker: Some(RUN_START_MARKER),
+            min_keep_lines: 0,
+        },
+        LogConfig {
+            relative_path: "cronup.gem.log",
+            retention_days: None,
+            encoding: None,
+            max_removal_percent: None,
+            strategy: None,
+            keep_patterns: &[],
+            section_marker: Some(RUN_START_MARKER),
+            min_keep_lines: 0,
+        },
+        LogConfig {
+            relative_path: "cronup.go.log",
+            retention_days: None,
+            encoding: None,
+            max_removal_percent: None,
+            strategy: None,
+            keep_patterns: &[],
+            section_marker: Some(RUN_START_MARKER),
+            min_keep_lines: 0,
+        },
+        LogConfig {
+            relative_path: "cronup.macports.log",
+            retention_days: None,
+            encoding: None,
+            max_removal_percent: None,
+            strategy: None,
+            keep_patterns: &[],
+            section_marker: Some(RUN_START_MARKER),
+            min_keep_lines: 0,
+        },
+        LogConfig {
+            relative_path: "cronup.mas.log",
+            retention_days: None,
+            encoding: None,
+            max_removal_percent: None,
+            strategy: None,
+            keep_patterns: &[],
+            section_marker: Some(RUN_START_MARKER),
+            min_keep_lines: 0,
+        },
+        LogConfig {
+            relative_path: "cronup.mise.log",
+            retention_days: None,
+            encoding: None,
+            max_removal_percent: None,
+            strategy: None,
+            keep_patterns: &[],
+            section_marker: Some(RUN_START_MARKER),
+            min_keep_lines: 0,
+        },
+        LogConfig {
+            relative_path: "cronup.nix.log",
+            retention_days: None,
+            encoding: None,
+            max_removal_percent: None,
+            strategy: None,
+            keep_patterns: &[],
+            section_marker: Some(RUN_START_MARKER),
+            min_keep_lines: 0,
+        },
+        LogConfig {
+            relative_path: "cronup.node.log",
+            retention_days: None,
+            encoding: None,
+            max_removal_percent: None,
+            strategy: None,
+            keep_patterns: &[],
+            section_marker: Some(RUN_START_MARKER),
+            min_keep_lines: 0,
+        },
+        LogConfig {
+            relative_path: "cronup.nvim.log",
+            retention_days: Some(1),
+            encoding: None,
+            max_removal_percent: None,
+            strategy: None,
+            keep_patterns: &[],
+            section_marker: Some(RUN_START_MARKER),
+            min_keep_lines: 0,
+        },
+        LogConfig {
+            relative_path: "cronup.pipx.log",
+            retention_days: None,
+            encoding: None,
+            max_removal_percent: None,
+            strategy: None,
+            keep_patterns: &[],
+            section_marker: Some(RUN_START_MARKER),
+            min_keep_lines: 0,
+        },
+        LogConfig {
+            relative_path: "cronup.run.log",
+            retention_days: None,
+            encoding: None,
+            max_removal_percent: None,
+            strategy: None,
+            keep_patterns: &["WARNING"], // Runs with a skewed clock
+            section_marker: Some(RUN_START_MARKER),
+            min_keep_lines: 0,
+        },
+        LogConfig {
+            relative_path: "cronup.rustup.log",
+            retention_days: Some(5),
+            encoding: None,
+            max_removal_percent: None,
+            strategy: None,
+            keep_patterns: &[],
+            section_marker: Some(RUN_START_MARKER),
+            min_keep_lines: 0,
+        },
+        LogConfig {
+            relative_path: "cronup.softwareupdate.log",
+            retention_days: None,
+            encoding: None,
+            max_removal_percent: None,
+            strategy: None,
+            keep_patterns: &[],
+            section_marker: Some(RUN_START_MARKER),
+            min_keep_lines: 0,
+        },
+        LogConfig {
+            relative_path: "cronup.tlmgr.log",
+            retention_days: None,
+            encoding: None,
+            max_removal_percent: None,
+            strategy: None,
+            keep_patterns: &[],
+            section_marker: Some(RUN_START_MARKER),
+            min_keep_lines: 0,
+        },
+        LogConfig {
+            relative_path: "snitchprot.log",
+            retention_days: Some(1),
+            encoding: None,
+            max_removal_percent: None,
+            strategy: None,
+            keep_patterns: &[],
+            section_marker: None,
+            min_keep_lines: 20, // Context before a gap, as it only logs transitions
+        },
+    ],
+}];
+
+/// The label of the LaunchAgent written by install-agent
+const AGENT_LABEL: &str = "gg.hw.cleanlog";
+
+/// The schedule install-agent uses unless --interval is given
+const DEFAULT_SCHEDULE: &str = "daily@03:04";
+
+/// The file in a root directory listing the files that wildcard paths leave out
+const IGNORE_FILE: &str = ".cleanlogignore";
+
+/// Default share of a file's lines a single run may remove, in percent
+const DEFAULT_MAX_REMOVAL_PERCENT: u32 = 90;
+
+/// Default number of removed lines from which a run's summary is sent
+const DEFAULT_NOTIFY_MIN_LINES: usize = 1000;
+
+/// Decides which timestamped lines get removed from a log file
+#[derive(Debug, Clone, Copy)]
+enum PrunePolicy {
+    Retention(u32), // Remove lines older than this many days
+    Window {
+        since: Option<NaiveDateTime>, // Inclusive lower bound in UTC, unbounded if None
+        before: Option<NaiveDateTime>, // Exclusive upper bound in UTC, unbounded if None
     },
-];
+    Cutoff(NaiveDateTime), // Remove lines before this time in UTC, keeping the floor like expiry does
+}
+
+impl PrunePolicy {
+    /// Returns true if a line with the given timestamp should be removed
+    /// Both times are in UTC
+    fn removes(&self, timestamp: NaiveDateTime, current_time: NaiveDateTime) -> bool {
+        match *self {
+            PrunePolicy::Retention(retention_days) => {
+                // Remove if the line is older than the retention period
+                current_time - timestamp > Duration::days(retention_days as i64)
+            }
+            PrunePolicy::Window { since, before } => {
+                // Remove if the line falls inside the window
+                since.is_none_or(|since| timestamp >= since)
+                    && before.is_none_or(|before| timestamp < before)
+            }
+            PrunePolicy::Cutoff(cutoff) => timestamp < cutoff,
+        }
+    }
+
+    /// Returns true if the policy only removes lines before some point in time, i.e. a file's head
+    fn removes_head_only(&self) -> bool {
+        match *self {
+            PrunePolicy::Retention(_) | PrunePolicy::Cutoff(_) => true,
+            PrunePolicy::Window { since, .. } => since.is_none(),
+        }
+    }
+}
+
+/// Command line options
+#[derive(Debug, Default)]
+struct Options {
+    since: Option<NaiveDateTime>, // --since: start of the manual pruning window
+    before: Option<NaiveDateTime>, // --before: end of the manual pruning window
+    dry_run: bool,                // --dry-run: report what would be removed without writing
+    force: bool,                  // --force: ignore the safety cap on how much of a file is removed
+    stats: bool,                  // --stats: report file and section sizes without cleaning
+}
+
+/// Parses a window boundary given on the command line
+/// Accepts YYYY-MM-DD (interpreted as midnight) or YYYY-MM-DD HH:MM:SS in local time
+/// Returns the boundary in UTC, the time zone log timestamps are compared in
+fn parse_window_date(value: &str) -> Option<NaiveDateTime> {
+    let local = NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S")
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })?;
+    Local
+        .from_local_datetime(&local)
+        .earliest()
+        .map(|time| time.naive_utc())
+}
+
+/// Parses the command line arguments into Options
+/// Returns a message describing the problem if the arguments are invalid
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--since" | "--before" => {
+                // Both flags take a date as their value
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("{} requires a date", arg))?;
+                let date = parse_window_date(&value)
+                    .ok_or_else(|| format!("invalid date for {}: {}", arg, value))?;
+                if arg == "--since" {
+                    options.since = Some(date);
+                } else {
+                    options.before = Some(date);
+                }
+            }
+            "--dry-run" => options.dry_run = true,
+            "--force" => options.force = true,
+            "--stats" => options.stats = true,
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+
+    // An empty window would silently do nothing, which is almost certainly a typo
+    if let (Some(since), Some(before)) = (options.since, options.before) {
+        if since >= before {
+            return Err(String::from("--since must be earlier than --before"));
+        }
+    }
 
-/// Gets the LOG_HOME directory from environment variable or returns default
-fn get_log_home() -> PathBuf {
-    // Try to get LOG_HOME from environment, default to /var/log if not set
-    env::var("LOG_HOME")
-        .map(PathBuf::from)
-        .unwrap_or_else(|_| PathBuf::from("/var/log"))
+    Ok(options)
 }
 
-/// Attempts to parse a timestamp from a log line
-/// Expected format: [YYYY-MM-DD HH:MM:SS]
-/// Returns None if the line doesn't match the expected format
-fn parse_timestamp(line: &str) -> Option<NaiveDateTime> {
-    // Check if the line is long enough to contain a timestamp
-    if line.len() < 21 {
-        return None;
+/// Gets a root's directory from its environment variable or returns its configured default
+fn get_root_directory(root: &LogRoot) -> PathBuf {
+    // Try to get the directory from the environment, e.g. LOG_HOME
+    if let Some(path) = root.env_var.and_then(|name| env::var(name).ok()) {
+        return PathBuf::from(path);
     }
 
-    // Extract the timestamp portion (excluding the brackets)
-    let timestamp_str = &line[1..20]; // Slice containing "YYYY-MM-DD HH:MM:SS"
+    // Expand a leading "~/" to the home directory
+    match (root.directory.strip_prefix("~/"), env::var("HOME")) {
+        (Some(relative), Ok(home)) => Path::new(&home).join(relative),
+        _ => PathBuf::from(root.directory),
+    }
+}
 
-    // Attempt to parse the timestamp string into a NaiveDateTime
-    NaiveDateTime::parse_from_str(timestamp_str, "%Y-%m-%d %H:%M:%S").ok()
+/// Gets the encoding of a root's files that don't set their own, from CLEANLOG_ENCODING or the root
+fn get_default_encoding(root: &LogRoot) -> Encoding {
+    env::var("CLEANLOG_ENCODING")
+        .ok()
+        .and_then(|name| Encoding::from_name(&name))
+        .unwrap_or(root.encoding)
 }
 
-/// Processes a single log file according to its retention configuration
-/// Takes the full path to the log file and its retention configuration
-/// Returns the number of lines removed or an IO error if something goes wrong
-fn clean_log_file(full_path: &Path, retention_days: u32) -> io::Result<usize> {
+/// Gets the strategy of a root's files that don't set their own, from CLEANLOG_STRATEGY or the root
+fn get_default_strategy(root: &LogRoot) -> Strategy {
+    env::var("CLEANLOG_STRATEGY")
+        .ok()
+        .and_then(|name| Strategy::from_name(&name))
+        .unwrap_or(root.strategy)
+}
+
+/// Gets the global safety cap from CLEANLOG_MAX_REMOVAL_PERCENT or returns the default
+fn get_max_removal_percent() -> u32 {
+    env::var("CLEANLOG_MAX_REMOVAL_PERCENT")
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(DEFAULT_MAX_REMOVAL_PERCENT)
+}
+
+/// Reads the glob patterns of the files to leave alone from a root's .cleanlogignore
+/// Blank lines and lines starting with "#" are skipped; a missing file ignores nothing
+fn read_ignore_patterns(root_directory: &Path) -> Vec<String> {
+    fs::read_to_string(root_directory.join(IGNORE_FILE))
+        .unwrap_or_default()
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect()
+}
+
+/// Matches text against a glob pattern, where "*" matches any run of characters and "?" any one
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // The position after the last "*" and the text position it matched up to, to backtrack to
+    let mut star = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p + 1, t));
+            p += 1;
+        } else if let Some((after_star, matched)) = star {
+            p = after_star;
+            t = matched + 1;
+            star = Some((after_star, t));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Resolves a file's relative path to the files it names in the root directory
+/// A path whose file name contains a wildcard names every matching file in its directory that no ignore
+/// pattern matches, in name order; any other path names exactly one file
+fn resolve_paths(
+    root_directory: &Path,
+    relative_path: &str,
+    ignore: &[String],
+) -> io::Result<Vec<PathBuf>> {
+    let full_path = root_directory.join(relative_path);
+    let pattern = match full_path.file_name() {
+        Some(name) if name.to_string_lossy().contains(['*', '?']) => {
+            name.to_string_lossy().into_owned()
+        }
+        _ => return Ok(vec![full_path]),
+    };
+    let directory = full_path.parent().unwrap_or(root_directory);
+
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        let name = path
+            .file_name()
+            .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+        let relative = path.strip_prefix(root_directory).unwrap_or(&path);
+        if path.is_file()
+            && glob_match(&pattern, &name)
+            && !ignore
+                .iter()
+                .any(|ignored| glob_match(ignored, &relative.to_string_lossy()))
+        {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Compiles a file's keep patterns
+/// Returns an error naming the first invalid pattern
+fn compile_patterns(patterns: &[&str]) -> io::Result<Vec<Regex>> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            Regex::new(pattern).map_err(|error| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid keep pattern '{}': {}", pattern, error),
+                )
+            })
+        })
+        .collect()
+}
+
+/// The result of processing a single log file
+#[derive(Debug)]
+enum Outcome {
+    Cleaned {
+        removed: usize, // The number of lines removed (or that would be removed)
+        bytes: u64,     // The number of bytes they took up
+    },
+    Capped {
+        removed: usize, // The number of lines the run would have removed
+        total: usize,   // The number of lines in the file
+    },
+}
+
+/// Processes a single log file according to its prune policy and cleaning strategy
+/// Takes the full path to the log file, its encoding, timestamp format, strategy, keep patterns, section marker and line floor, the policy to apply,
+/// the safety cap in percent (None for no cap), and whether to leave the file untouched
+/// Returns the number of lines removed (or that would be removed), whether the file was left alone
+/// because of the safety cap, or an IO error if something goes wrong
+#[allow(clippy::too_many_arguments)] // The caller resolves every setting of the file
+fn clean_log_file(
+    full_path: &Path,
+    encoding: Encoding,
+    format: &TimestampFormat,
+    strategy: Strategy,
+    keep: &[Regex],
+    section_marker: Option<&str>,
+    min_keep_lines: usize,
+    policy: PrunePolicy,
+    max_removal_percent: Option<u32>,
+    dry_run: bool,
+) -> io::Result<Outcome> {
     // Check if the file exists before attempting to process it
     if !full_path.exists() {
-        return Ok(0);
+        return Ok(Outcome::Cleaned {
+            removed: 0,
+            bytes: 0,
+        });
+    }
+
+    // Only a policy that removes a head can be applied by truncating the head, and only if no line
+    // in the head has to be kept, no section would be cut in two, and no floor has to be counted
+    if matches!(strategy, Strategy::TruncateHead)
+        && policy.removes_head_only()
+        && keep.is_empty()
+        && section_marker.is_none()
+        && min_keep_lines == 0
+    {
+        return truncate_head(
+            full_path,
+            encoding,
+            format,
+            policy,
+            max_removal_percent,
+            dry_run,
+        );
     }
 
-    // Open the original file for reading
-    let file = File::open(full_path)?;
-    let reader = BufReader::new(file);
+    // Read the original file as raw bytes
+    let data = fs::read(full_path)?;
 
     // Create a temporary file to write the filtered content
     let mut temp_file = NamedTempFile::new()?;
 
+    // A byte order mark belongs to the file rather than its first line, so it is always kept
+    let bom = encoding.byte_order_mark();
+    let data = match data.strip_prefix(bom) {
+        Some(rest) if !bom.is_empty() => {
+            temp_file.write_all(bom)?;
+            rest
+        }
+        _ => &data[..],
+    };
+
     // Get current time for comparison
     let current_time = Utc::now().naive_utc();
 
-    // Calculate the cutoff time based on retention period
-    let retention_period = Duration::days(retention_days as i64);
-
-    // Counter for removed lines
+    // Counters for removed lines and their bytes
     let mut lines_removed = 0;
+    let mut bytes_removed = 0;
 
-    // Process the file line by line
-    for line in reader.lines() {
-        let line = line?;
-
-        // Determine if we should keep this line
-        // We keep the line if:
-        // 1. It doesn't have a valid timestamp (preserve non-log lines)
-        // 2. Its timestamp is within the retention period
-        let should_keep = match parse_timestamp(&line) {
-            Some(timestamp) => {
-                // Keep if the difference between current time and timestamp
-                // is less than or equal to the retention period
-                current_time - timestamp <= retention_period
-            }
-            None => true, // Keep lines without valid timestamps
-        };
+    // Process the file section by section; without a section marker every line is its own section
+    let lines = encoding.split_lines(data);
+    let total_lines = lines.len();
+    let (preamble, sections) = split_sections(lines, encoding, section_marker);
+    let sections = preamble.into_iter().map(|line| vec![line]).chain(sections);
+
+    // The floor only protects against expiry, not against a window removed on purpose
+    let floor = match policy {
+        PrunePolicy::Retention(_) | PrunePolicy::Cutoff(_) => min_keep_lines,
+        PrunePolicy::Window { .. } => 0,
+    };
+    let mut lines_after = total_lines;
+    for section in sections {
+        lines_after -= section.len();
 
-        // Write the line to the temporary file if we're keeping it
+        // Determine if we should keep this section
+        // We keep the section if:
+        // 1. Some of its lines are among the last lines of the file kept by the floor
+        // 2. It doesn't have a valid timestamp (preserve non-log lines)
+        // 3. The policy doesn't remove its first timestamp
+        // 4. One of its lines matches a keep pattern
+        let texts: Vec<String> = section.iter().map(|line| encoding.decode(line)).collect();
+        let should_keep = lines_after < floor
+            || match texts.iter().find_map(|text| format.parse_line(text)) {
+                Some(timestamp) => {
+                    !policy.removes(timestamp.naive_utc(), current_time)
+                        || texts
+                            .iter()
+                            .any(|text| keep.iter().any(|pattern| pattern.is_match(text)))
+                }
+                None => true, // Keep sections without valid timestamps
+            };
+
+        // Write the section to the temporary file unchanged if we're keeping it
         if should_keep {
-            writeln!(temp_file, "{}", line)?;
+            for line in section {
+                temp_file.write_all(line)?;
+            }
         } else {
-            lines_removed += 1;
+            lines_removed += section.len();
+            bytes_removed += section.iter().map(|line| line.len() as u64).sum::<u64>();
         }
     }
 
+    // Leave the file untouched if more of it would be removed than the safety cap allows
+    if exceeds_cap(lines_removed, total_lines, max_removal_percent) {
+        return Ok(Outcome::Capped {
+            removed: lines_removed,
+            total: total_lines,
+        });
+    }
+
+    // In dry-run mode the original file stays untouched and the temporary file is discarded
+    let outcome = Outcome::Cleaned {
+        removed: lines_removed,
+        bytes: bytes_removed,
+    };
+    if dry_run {
+        return Ok(outcome);
+    }
+
     // Replace the original file with the cleaned version
     // This is an atomic operation on most filesystems
     temp_file.persist(full_path)?;
 
-    Ok(lines_removed)
+    Ok(outcome)
+}
+
+/// Groups a file's lines into sections, each starting with a line that contains the marker
+/// Returns the lines before the first marker and the sections; without a marker every line is in the first part
+fn split_sections<'a>(
+    lines: Vec<&'a [u8]>,
+    encoding: Encoding,
+    marker: Option<&str>,
+) -> (Vec<&'a [u8]>, Vec<Vec<&'a [u8]>>) {
+    let mut preamble = Vec::new();
+    let mut sections: Vec<Vec<&[u8]>> = Vec::new();
+    for line in lines {
+        let starts_section = marker.is_some_and(|marker| encoding.decode(line).contains(marker));
+        match sections.last_mut() {
+            _ if starts_section => sections.push(vec![line]),
+            Some(section) => section.push(line),
+            None => preamble.push(line),
+        }
+    }
+    (preamble, sections)
+}
+
+/// Prints the size of a log file and, if it has a section marker, of each of its sections
+/// Sections are labeled with their first timestamp in local time
+fn print_stats(
+    full_path: &Path,
+    encoding: Encoding,
+    format: &TimestampFormat,
+    section_marker: Option<&str>,
+) -> io::Result<()> {
+    // A missing file is reported as empty, like it counts as clean
+    let data = match fs::read(full_path) {
+        Ok(data) => data,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(error) => return Err(error),
+    };
+    let lines = encoding.split_lines(&data);
+    let total_lines = lines.len();
+    let (preamble, sections) = split_sections(lines, encoding, section_marker);
+    println!(
+        "{}: {} lines, {} bytes, {} sections",
+        full_path.display(),
+        total_lines,
+        data.len(),
+        sections.len()
+    );
+
+    // The lines before the first marker are only listed if the file has sections at all
+    let size = |lines: &[&[u8]]| lines.iter().map(|line| line.len()).sum::<usize>();
+    if section_marker.is_some() && !preamble.is_empty() {
+        println!(
+            "  before the first section: {} lines, {} bytes",
+            preamble.len(),
+            size(&preamble)
+        );
+    }
+    for section in &sections {
+        let label = section
+            .iter()
+            .find_map(|line| format.parse_line(&encoding.decode(line)))
+            .map_or_else(
+                || String::from("without timestamp"),
+                |timestamp| {
+                    timestamp
+                        .with_timezone(&Local)
+                        .format("%Y-%m-%d %H:%M:%S")
+                        .to_string()
+                },
+            );
+        println!(
+            "  {}: {} lines, {} bytes",
+            label,
+            section.len(),
+            size(section)
+        );
+    }
+    Ok(())
+}
+
+/// Processes a single log file by dropping its expired head, for the truncate-head strategy
+/// Only the lines probed by the binary search are decoded, and the retained tail is copied unchanged
+/// Takes the same arguments as clean_log_file and returns the same outcomes
+fn truncate_head(
+    full_path: &Path,
+    encoding: Encoding,
+    format: &TimestampFormat,
+    policy: PrunePolicy,
+    max_removal_percent: Option<u32>,
+    dry_run: bool,
+) -> io::Result<Outcome> {
+    let mut reader = BufReader::new(File::open(full_path)?);
+    let length = reader.get_ref().metadata()?.len();
+    let unit = encoding.newline().len() as u64;
+
+    // A byte order mark belongs to the file rather than its first line, so it is always kept
+    let bom = encoding.byte_order_mark();
+    let mut head = vec![0; bom.len()];
+    let has_bom = !bom.is_empty() && reader.read_exact(&mut head).is_ok() && head == bom;
+    let data_start = if has_bom { bom.len() as u64 } else { 0 };
+
+    // Get current time for comparison
+    let current_time = Utc::now().naive_utc();
+
+    // Binary search for the first code unit from which the next timestamped line is retained
+    // Positions stay aligned to code units so multi-byte units are never split
+    let mut low = 0;
+    let mut high = (length - data_start) / unit;
+    while low < high {
+        let middle = low + (high - low) / 2;
+        let retained = match next_timestamped_line(
+            &mut reader,
+            encoding,
+            format,
+            data_start,
+            data_start + middle * unit,
+        )? {
+            Some((_, timestamp)) => !policy.removes(timestamp, current_time),
+            None => true,
+        };
+        if retained {
+            high = middle;
+        } else {
+            low = middle + 1;
+        }
+    }
+
+    // The file is cut at the start of the first retained line, or at its end if no line is retained
+    // A file without any timestamp has nothing that expired, so it is left whole
+    let cut = match next_timestamped_line(
+        &mut reader,
+        encoding,
+        format,
+        data_start,
+        data_start + low * unit,
+    )? {
+        Some((start, _)) => start,
+        None if next_timestamped_line(&mut reader, encoding, format, data_start, data_start)?
+            .is_none() =>
+        {
+            data_start
+        }
+        None => length,
+    };
+
+    // Count the lines in the head and, only as far as the safety cap needs it, in the tail
+    reader.seek(SeekFrom::Start(data_start))?;
+    let lines_removed = count_lines(&mut reader, encoding, cut - data_start, usize::MAX)?;
+    if lines_removed == 0 {
+        return Ok(Outcome::Cleaned {
+            removed: 0,
+            bytes: 0,
+        });
+    }
+    let tail_lines = count_lines(
+        &mut reader,
+        encoding,
+        length - cut,
+        lines_removed.saturating_mul(100),
+    )?;
+    let total_lines = lines_removed + tail_lines;
+
+    // Leave the file untouched if more of it would be removed than the safety cap allows
+    if exceeds_cap(lines_removed, total_lines, max_removal_percent) {
+        return Ok(Outcome::Capped {
+            removed: lines_removed,
+            total: total_lines,
+        });
+    }
+
+    // In dry-run mode the original file stays untouched
+    let outcome = Outcome::Cleaned {
+        removed: lines_removed,
+        bytes: cut - data_start,
+    };
+    if dry_run {
+        return Ok(outcome);
+    }
+
+    // Copy the byte order mark and the tail to a temporary file and replace the original with it
+    // Punching a hole into the head instead would keep the file's length and leave NUL bytes behind
+    let mut temp_file = NamedTempFile::new()?;
+    if has_bom {
+        temp_file.write_all(bom)?;
+    }
+    reader.seek(SeekFrom::Start(cut))?;
+    io::copy(&mut reader, &mut temp_file)?;
+    temp_file.persist(full_path)?;
+
+    Ok(outcome)
+}
+
+/// Finds the first line with a timestamp that starts at or after the given position
+/// Positions must be aligned to code units; data_start is where the first line starts
+/// Returns the line's start and timestamp in UTC, or None if no such line follows
+fn next_timestamped_line(
+    reader: &mut BufReader<File>,
+    encoding: Encoding,
+    format: &TimestampFormat,
+    data_start: u64,
+    position: u64,
+) -> io::Result<Option<(u64, NaiveDateTime)>> {
+    let unit = encoding.newline().len() as u64;
+
+    // Unless the position is the start of the data, skip to the end of the line containing the code
+    // unit before it; that line may end right at the position
+    let mut start = position;
+    if position > data_start {
+        reader.seek(SeekFrom::Start(position - unit))?;
+        start = position - unit + read_line(reader, encoding)?.len() as u64;
+    }
+    reader.seek(SeekFrom::Start(start))?;
+
+    // Read lines until one has a timestamp
+    loop {
+        let line = read_line(reader, encoding)?;
+        if line.is_empty() {
+            return Ok(None);
+        }
+        if let Some(timestamp) = format.parse_line(&encoding.decode(&line)) {
+            return Ok(Some((start, timestamp.naive_utc())));
+        }
+        start += line.len() as u64;
+    }
+}
+
+/// Reads the raw line at the reader's position, including its newline
+/// Returns an empty line at the end of the file; a stray trailing byte is ignored
+fn read_line(reader: &mut BufReader<File>, encoding: Encoding) -> io::Result<Vec<u8>> {
+    let newline = encoding.newline();
+    let mut line = Vec::new();
+    let mut unit = vec![0; newline.len()];
+    loop {
+        match reader.read_exact(&mut unit) {
+            Ok(()) => {
+                line.extend_from_slice(&unit);
+                if unit == newline {
+                    return Ok(line);
+                }
+            }
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(line),
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+/// Counts the lines in the next `bytes` bytes of the reader, as split_lines would split them
+/// Stops counting once `limit` lines have been found
+fn count_lines(
+    reader: &mut BufReader<File>,
+    encoding: Encoding,
+    bytes: u64,
+    limit: usize,
+) -> io::Result<usize> {
+    let newline = encoding.newline();
+    let mut remaining = reader.by_ref().take(bytes);
+    let mut unit = vec![0; newline.len()];
+    let mut lines = 0;
+    let mut open_line = false;
+    while lines < limit {
+        match remaining.read_exact(&mut unit) {
+            Ok(()) => {
+                open_line = unit != newline;
+                if !open_line {
+                    lines += 1;
+                }
+            }
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(error) => return Err(error),
+        }
+    }
+
+    // A final line without a trailing newline counts as well
+    Ok(if open_line { lines + 1 } else { lines })
+}
+
+/// Returns true if removing this many of a file's lines exceeds the safety cap (None for no cap)
+fn exceeds_cap(removed: usize, total: usize, max_removal_percent: Option<u32>) -> bool {
+    max_removal_percent
+        .is_some_and(|max_removal_percent| removed * 100 > total * max_removal_percent as usize)
+}
+
+/// Formats a size in bytes for a human reader, e.g. "12.3 MB"
+fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// Gets the number of removed lines from which a run's summary is sent, from CLEANLOG_NOTIFY_MIN_LINES
+/// Runs with errors or capped files are always reported; 0 reports every run
+fn get_notify_min_lines() -> usize {
+    env::var("CLEANLOG_NOTIFY_MIN_LINES")
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(DEFAULT_NOTIFY_MIN_LINES)
+}
+
+/// Returns the total number of lines and bytes removed from the files that were cleaned
+fn removed_totals(results: &[(String, io::Result<Outcome>)]) -> (usize, u64) {
+    results
+        .iter()
+        .fold((0, 0), |(lines, total_bytes), (_, result)| match result {
+            Ok(Outcome::Cleaned { removed, bytes }) => (lines + removed, total_bytes + bytes),
+            _ => (lines, total_bytes),
+        })
+}
+
+/// Returns true if a run is worth a summary: a file failed or was capped, a root directory was
+/// missing, or at least min_lines lines were removed
+fn is_notable(
+    results: &[(String, io::Result<Outcome>)],
+    missing_root: bool,
+    min_lines: usize,
+) -> bool {
+    missing_root
+        || results
+            .iter()
+            .any(|(_, result)| !matches!(result, Ok(Outcome::Cleaned { .. })))
+        || removed_totals(results).0 >= min_lines
+}
+
+/// Builds the plain text run summary from the per-file results
+/// Each result is the file's path and either its outcome or an error
+fn build_summary(results: &[(String, io::Result<Outcome>)]) -> String {
+    let (lines, bytes) = removed_totals(results);
+    let mut summary = format!(
+        "cleanlog removed {} lines ({} reclaimed) from {} files",
+        lines,
+        format_size(bytes),
+        results.len()
+    );
+    for (path, result) in results {
+        match result {
+            Ok(Outcome::Cleaned { removed, bytes }) => summary.push_str(&format!(
+                "\n{}: {} ({})",
+                path,
+                removed,
+                format_size(*bytes)
+            )),
+            Ok(Outcome::Capped { removed, total }) => summary.push_str(&format!(
+                "\n{}: skipped, would remove {} of {} lines",
+                path, removed, total
+            )),
+            Err(error) => summary.push_str(&format!("\n{}: failed ({})", path, error)),
+        }
+    }
+    summary
+}
+
+/// Quotes a string as a JSON string literal
+fn json_string(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Runs a command with the given standard input, returning whether it succeeded
+fn run_with_input(command: &mut Command, input: &str) -> io::Result<bool> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    // Write the input and close the pipe so the command sees end of file
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input.as_bytes())?;
+    }
+
+    Ok(child.wait()?.success())
+}
+
+/// Sends the run summary to every configured destination
+/// Delivery failures are ignored since they must not affect the cleanup itself
+fn send_summary(summary: &str) {
+    // Email the summary through the local mail transfer agent
+    if let Ok(address) = env::var("CLEANLOG_EMAIL") {
+        let message = format!(
+            "To: {}\nSubject: cleanlog summary\n\n{}\n",
+            address, summary
+        );
+        let _ = run_with_input(Command::new("/usr/sbin/sendmail").arg("-t"), &message);
+    }
+
+    // Post the summary to the webhook; the body is passed on stdin to keep it out of the process list
+    if let Ok(url) = env::var("CLEANLOG_WEBHOOK_URL") {
+        let body = format!("{{\"text\":{}}}", json_string(summary));
+        let _ = run_with_input(
+            Command::new("/usr/bin/curl")
+                .args(["-fsS", "-m", "30", "-X", "POST"])
+                .args(["-H", "Content-Type: application/json"])
+                .args(["--data-binary", "@-"])
+                .arg(url),
+            &body,
+        );
+    }
+}
+
+/// When a LaunchAgent runs
+#[derive(Debug, Clone, Copy)]
+enum Schedule {
+    Calendar {
+        hour: Option<u32>, // The hour of the day, every hour if None
+        minute: u32,       // The minute of the hour
+    },
+    Interval(u64), // Every this many seconds
+}
+
+/// Parses a schedule given to install-agent: daily@HH:MM, hourly@MM, or a number with s, m or h
+fn parse_schedule(value: &str) -> Option<Schedule> {
+    if let Some(time) = value.strip_prefix("daily@") {
+        let (hour, minute) = time.split_once(':')?;
+        let (hour, minute) = (hour.parse().ok()?, minute.parse().ok()?);
+        return (hour < 24 && minute < 60).then_some(Schedule::Calendar {
+            hour: Some(hour),
+            minute,
+        });
+    }
+    if let Some(minute) = value.strip_prefix("hourly@") {
+        let minute = minute.parse().ok()?;
+        return (minute < 60).then_some(Schedule::Calendar { hour: None, minute });
+    }
+    let unit = match value.chars().last()? {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        _ => return None,
+    };
+    let number: u64 = value[..value.len() - 1].parse().ok()?;
+    (number > 0).then_some(Schedule::Interval(number * unit))
+}
+
+/// Escapes text for use in a property list
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Builds the LaunchAgent property list that runs the program on the schedule
+/// Takes the program's path and the environment variables to set for it
+fn agent_plist(program: &Path, schedule: Schedule, variables: &[(String, String)]) -> String {
+    let mut plist = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+         <plist version=\"1.0\">\n<dict>\n",
+    );
+    plist.push_str(&format!(
+        "    <key>Label</key>\n    <string>{}</string>\n",
+        AGENT_LABEL
+    ));
+    plist.push_str(&format!(
+        "    <key>ProgramArguments</key>\n    <array>\n        <string>{}</string>\n    </array>\n",
+        xml_escape(&program.to_string_lossy())
+    ));
+    if !variables.is_empty() {
+        plist.push_str("    <key>EnvironmentVariables</key>\n    <dict>\n");
+        for (name, value) in variables {
+            plist.push_str(&format!(
+                "        <key>{}</key>\n        <string>{}</string>\n",
+                xml_escape(name),
+                xml_escape(value)
+            ));
+        }
+        plist.push_str("    </dict>\n");
+    }
+    match schedule {
+        Schedule::Calendar { hour, minute } => {
+            plist.push_str("    <key>StartCalendarInterval</key>\n    <dict>\n");
+            if let Some(hour) = hour {
+                plist.push_str(&format!(
+                    "        <key>Hour</key>\n        <integer>{}</integer>\n",
+                    hour
+                ));
+            }
+            plist.push_str(&format!(
+                "        <key>Minute</key>\n        <integer>{}</integer>\n    </dict>\n",
+                minute
+            ));
+        }
+        Schedule::Interval(seconds) => plist.push_str(&format!(
+            "    <key>StartInterval</key>\n    <integer>{}</integer>\n",
+            seconds
+        )),
+    }
+    plist.push_str(
+        "    <key>StartOnMount</key>\n    <true/>\n    <key>RunAtLoad</key>\n    <true/>\n</dict>\n</plist>\n",
+    );
+    plist
+}
+
+/// Writes the LaunchAgent for this binary and loads it, replacing one loaded before
+/// Returns a message describing the problem if the arguments are invalid or installing fails
+fn install_agent(args: &[String]) -> Result<PathBuf, String> {
+    let value = match args {
+        [] => DEFAULT_SCHEDULE,
+        [flag, value] if flag == "--interval" => value.as_str(),
+        _ => {
+            return Err(String::from(
+                "usage: cleanlog install-agent [--interval <SCHEDULE>]",
+            ))
+        }
+    };
+    let schedule = parse_schedule(value).ok_or_else(|| {
+        format!(
+            "invalid schedule: {} (expected daily@HH:MM, hourly@MM, or e.g. 30m)",
+            value
+        )
+    })?;
+
+    // The agent runs this very binary with the settings cleanlog was installed with
+    let program = env::current_exe().map_err(|error| error.to_string())?;
+    let mut variables: Vec<(String, String)> = env::vars()
+        .filter(|(name, _)| {
+            name == "LOG_HOME"
+                || name.starts_with("CLEANLOG_")
+                || name.starts_with("LOG_TIMESTAMP_")
+        })
+        .collect();
+    variables.sort();
+
+    let home = env::var("HOME").map_err(|_| String::from("HOME is not set"))?;
+    let directory = Path::new(&home).join("Library/LaunchAgents");
+    let path = directory.join(format!("{}.plist", AGENT_LABEL));
+    fs::create_dir_all(&directory)
+        .and_then(|()| fs::write(&path, agent_plist(&program, schedule, &variables)))
+        .map_err(|error| format!("writing {} failed: {}", path.display(), error))?;
+
+    // Unload an agent loaded before, which fails harmlessly if there is none, and load the new one
+    let uid = Command::new("/usr/bin/id")
+        .arg("-u")
+        .output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .map_err(|error| error.to_string())?;
+    let domain = format!("gui/{}", uid);
+    let launchctl = |action: &str| {
+        Command::new("/bin/launchctl")
+            .arg(action)
+            .arg(&domain)
+            .arg(&path)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+    };
+    let _ = launchctl("bootout");
+    match launchctl("bootstrap") {
+        Ok(status) if status.success() => Ok(path),
+        Ok(status) => Err(format!("launchctl bootstrap failed with {}", status)),
+        Err(error) => Err(format!("launchctl bootstrap failed: {}", error)),
+    }
 }
 
 /// Main program entry point
-/// Processes all configured log files and exits on any error
+/// Processes all configured log files, sends the run summary, and exits on any error
 fn main() {
-    // Get the LOG_HOME directory (defaults to /var/log)
-    let log_home = get_log_home();
+    // install-agent sets up the schedule instead of cleaning
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "install-agent") {
+        match install_agent(&args[1..]) {
+            Ok(path) => println!("Installed and loaded {}", path.display()),
+            Err(message) => {
+                eprintln!("cleanlog: {}", message);
+                process::exit(1);
+            }
+        }
+        return;
+    }
 
-    // Exit if log_home doesn't exist or isn't a directory
-    if !log_home.is_dir() {
+    // browse prunes interactively instead of applying the configuration
+    if args.first().is_some_and(|arg| arg == "browse") {
+        if let Err(error) = browse::run() {
+            eprintln!("cleanlog: {}", error);
+            process::exit(1);
+        }
+        return;
+    }
+
+    // Parse command line arguments and exit with a message if they're invalid
+    let options = match parse_args(args.into_iter()) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("cleanlog: {}", message);
+            process::exit(1);
+        }
+    };
+
+    // A manual window, if given, replaces the configured retention for this run
+    let window = if options.since.is_some() || options.before.is_some() {
+        Some(PrunePolicy::Window {
+            since: options.since,
+            before: options.before,
+        })
+    } else {
+        None
+    };
+
+    // Results of every processed file for the summary
+    let mut results = Vec::new();
+
+    // The safety cap applied to files without their own, unless the run is forced
+    let max_removal_percent = get_max_removal_percent();
+
+    // Whether a root directory was missing, which fails the run without affecting the other roots
+    let mut missing_root = false;
+
+    // Process each root directory and the log files configured in it
+    'roots: for root in LOG_ROOTS {
+        let root_directory = get_root_directory(root);
+
+        // Skip the root if it doesn't exist or isn't a directory
+        if !root_directory.is_dir() {
+            eprintln!("cleanlog: {} is not a directory", root_directory.display());
+            missing_root = true;
+            continue;
+        }
+
+        // Files that wildcard paths leave out
+        let ignore = read_ignore_patterns(&root_directory);
+
+        for config in root.files {
+            // Resolve the relative path to the files it names, stopping at the first error
+            let full_paths = match resolve_paths(&root_directory, config.relative_path, &ignore) {
+                Ok(full_paths) => full_paths,
+                Err(error) => {
+                    results.push((
+                        root_directory
+                            .join(config.relative_path)
+                            .display()
+                            .to_string(),
+                        Err(error),
+                    ));
+                    break 'roots;
+                }
+            };
+
+            for full_path in full_paths {
+                // Use the manual window if one was given, otherwise the file's retention period
+                let retention_days = config.retention_days.unwrap_or(root.retention_days);
+                let policy = window.unwrap_or(PrunePolicy::Retention(retention_days));
+                let encoding = config
+                    .encoding
+                    .unwrap_or_else(|| get_default_encoding(root));
+                let strategy = config
+                    .strategy
+                    .unwrap_or_else(|| get_default_strategy(root));
+                let format = TimestampFormat::for_log(
+                    &full_path
+                        .file_name()
+                        .map_or_else(String::new, |name| name.to_string_lossy().into_owned()),
+                );
+                let cap = (!options.force)
+                    .then(|| config.max_removal_percent.unwrap_or(max_removal_percent));
+
+                // With --stats only report the file's size, stopping at the first error
+                if options.stats {
+                    if let Err(error) =
+                        print_stats(&full_path, encoding, &format, config.section_marker)
+                    {
+                        eprintln!("cleanlog: {}: {}", full_path.display(), error);
+                        process::exit(1);
+                    }
+                    continue;
+                }
+
+                // Process the file and stop at the first error, including an invalid keep pattern
+                let result = compile_patterns(config.keep_patterns).and_then(|keep| {
+                    clean_log_file(
+                        &full_path,
+                        encoding,
+                        &format,
+                        strategy,
+                        &keep,
+                        config.section_marker,
+                        config.min_keep_lines,
+                        policy,
+                        cap,
+                        options.dry_run,
+                    )
+                });
+                match &result {
+                    Ok(Outcome::Cleaned { removed, bytes }) if options.dry_run => println!(
+                        "{}: {} lines ({}) would be removed",
+                        full_path.display(),
+                        removed,
+                        format_size(*bytes)
+                    ),
+                    Ok(Outcome::Capped { removed, total }) => eprintln!(
+                        "cleanlog: warning: skipped {}, would remove {} of {} lines (more than {}%); use --force to remove them",
+                        full_path.display(),
+                        removed,
+                        total,
+                        cap.unwrap_or(100)
+                    ),
+                    _ => {}
+                }
+                let failed = result.is_err();
+                results.push((full_path.display().to_string(), result));
+                if failed {
+                    break 'roots;
+                }
+            }
+        }
+    }
+
+    // Dry runs and stats are interactive, so what was printed above is the whole report
+    // Quiet runs aren't reported, so a nightly run doesn't post a summary every day
+    if !options.dry_run
+        && !options.stats
+        && is_notable(&results, missing_root, get_notify_min_lines())
+    {
+        send_summary(&build_summary(&results));
+    }
+
+    // Exit with an error if any file or root directory failed
+    if missing_root || results.iter().any(|(_, result)| result.is_err()) {
         process::exit(1);
     }
+}
 
-    // Process each log file configuration
-    for config in LOG_CONFIGS {
-        // Construct the full path by joining LOG_HOME with the relative path
-        let full_path = log_home.join(config.relative_path);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        // Process the file and exit on error
-        if clean_log_file(&full_path, config.retention_days).is_err() {
-            process::exit(1);
+    /// Encodes text in the given encoding, without a byte order mark
+    fn encode(encoding: Encoding, text: &str) -> Vec<u8> {
+        match encoding {
+            Encoding::Utf8 => text.as_bytes().to_vec(),
+            Encoding::Latin1 => text.chars().map(|c| c as u8).collect(),
+            Encoding::Utf16Le => text.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+            Encoding::Utf16Be => text.encode_utf16().flat_map(u16::to_be_bytes).collect(),
         }
     }
+
+    /// Writes a log file with a byte order mark and the given text to a temporary directory
+    fn write_log(dir: &Path, encoding: Encoding, text: &str) -> PathBuf {
+        let path = dir.join("test.log");
+        let mut data = encoding.byte_order_mark().to_vec();
+        data.extend(encode(encoding, text));
+        fs::write(&path, data).unwrap();
+        path
+    }
+
+    /// Returns the start of 2025, the cutoff the tests prune up to
+    fn cutoff() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2025, 1, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+    }
+
+    /// Truncates a file's head up to the start of 2025
+    fn truncate(path: &Path, encoding: Encoding, max_removal_percent: Option<u32>) -> Outcome {
+        truncate_head(
+            path,
+            encoding,
+            &TimestampFormat::for_log("test.log"),
+            PrunePolicy::Cutoff(cutoff()),
+            max_removal_percent,
+            false,
+        )
+        .unwrap()
+    }
+
+    /// Cleans a UTF-8 file up to the start of 2025 with the given strategy, keep patterns,
+    /// section marker and line floor
+    fn clean(
+        path: &Path,
+        strategy: Strategy,
+        keep: &[&str],
+        section_marker: Option<&str>,
+        min_keep_lines: usize,
+    ) -> Outcome {
+        clean_log_file(
+            path,
+            Encoding::Utf8,
+            &TimestampFormat::for_log("test.log"),
+            strategy,
+            &compile_patterns(keep).unwrap(),
+            section_marker,
+            min_keep_lines,
+            PrunePolicy::Cutoff(cutoff()),
+            None,
+            false,
+        )
+        .unwrap()
+    }
+
+    /// Reads a UTF-8 log file back without its byte order mark
+    fn read_log(path: &Path) -> String {
+        let data = fs::read(path).unwrap();
+        String::from_utf8(data[3..].to_vec()).unwrap()
+    }
+
+    const LOG: &str = "[2020-01-01 00:00:00] old\n\
+                       continued\n\
+                       [2020-06-01 00:00:00] old\n\
+                       no timestamp before the tail\n\
+                       [2030-01-01 00:00:00] new\n\
+                       no timestamp in the tail\n\
+                       [2030-06-01 00:00:00] new\n";
+
+    const TAIL: &str = "[2030-01-01 00:00:00] new\n\
+                        no timestamp in the tail\n\
+                        [2030-06-01 00:00:00] new\n";
+
+    #[test]
+    fn truncate_head_keeps_bom_and_tail() {
+        for encoding in [Encoding::Utf8, Encoding::Utf16Le, Encoding::Utf16Be] {
+            let dir = tempfile::tempdir().unwrap();
+            let path = write_log(dir.path(), encoding, LOG);
+            let head_bytes = (encode(encoding, LOG).len() - encode(encoding, TAIL).len()) as u64;
+            assert!(matches!(
+                truncate(&path, encoding, None),
+                Outcome::Cleaned { removed: 4, bytes } if bytes == head_bytes
+            ));
+            let mut expected = encoding.byte_order_mark().to_vec();
+            expected.extend(encode(encoding, TAIL));
+            assert_eq!(fs::read(&path).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn truncate_head_keeps_untimestamped_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_log(dir.path(), Encoding::Utf8, "no\ntimestamps\n");
+        assert!(matches!(
+            truncate(&path, Encoding::Utf8, None),
+            Outcome::Cleaned { removed: 0, .. }
+        ));
+        assert_eq!(
+            fs::read(&path).unwrap(),
+            b"\xEF\xBB\xBFno\ntimestamps\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn truncate_head_respects_cap() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_log(dir.path(), Encoding::Utf16Le, LOG);
+        assert!(matches!(
+            truncate(&path, Encoding::Utf16Le, Some(50)),
+            Outcome::Capped {
+                removed: 4,
+                total: 7
+            }
+        ));
+        let mut expected = Encoding::Utf16Le.byte_order_mark().to_vec();
+        expected.extend(encode(Encoding::Utf16Le, LOG));
+        assert_eq!(fs::read(&path).unwrap(), expected);
+    }
+
+    #[test]
+    fn keep_pattern_survives_expiry() {
+        for strategy in [Strategy::Rewrite, Strategy::TruncateHead] {
+            let dir = tempfile::tempdir().unwrap();
+            let path = write_log(
+                dir.path(),
+                Encoding::Utf8,
+                "[2020-01-01 00:00:00] old\n\
+                 [2020-03-01 00:00:00] ERROR disk full\n\
+                 [2020-06-01 00:00:00] old\n\
+                 [2030-01-01 00:00:00] new\n",
+            );
+            assert!(matches!(
+                clean(&path, strategy, &["ERROR"], None, 0),
+                Outcome::Cleaned { removed: 2, .. }
+            ));
+            assert_eq!(
+                read_log(&path),
+                "[2020-03-01 00:00:00] ERROR disk full\n[2030-01-01 00:00:00] new\n"
+            );
+        }
+    }
+
+    #[test]
+    fn old_sections_are_removed_whole() {
+        let dir = tempfile::tempdir().unwrap();
+        let run = |time: &str| format!("[{}] {}\n", time, RUN_START_MARKER);
+        let recent = format!("{}[2030-06-01 00:01:00] npm\n", run("2030-06-01 00:00:00"));
+        let path = write_log(
+            dir.path(),
+            Encoding::Utf8,
+            &format!(
+                "header\n{}[2020-01-01 00:01:00] brew\nno timestamp\n{}[2030-01-01 00:00:00] late\n{}",
+                run("2020-01-01 00:00:00"),
+                run("2020-06-01 00:00:00"),
+                recent
+            ),
+        );
+        assert!(matches!(
+            clean(&path, Strategy::Rewrite, &[], Some(RUN_START_MARKER), 0),
+            Outcome::Cleaned { removed: 5, .. }
+        ));
+        assert_eq!(read_log(&path), format!("header\n{}", recent));
+    }
+
+    #[test]
+    fn file_without_marker_is_pruned_by_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_log(dir.path(), Encoding::Utf8, LOG);
+        assert!(matches!(
+            clean(&path, Strategy::Rewrite, &[], Some(RUN_START_MARKER), 0),
+            Outcome::Cleaned { removed: 2, .. }
+        ));
+        assert_eq!(
+            read_log(&path),
+            format!("continued\nno timestamp before the tail\n{}", TAIL)
+        );
+    }
+
+    #[test]
+    fn floor_keeps_the_last_lines() {
+        // Pruning up to the cutoff would leave 5 of the 7 lines, fewer than a floor of 6
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_log(dir.path(), Encoding::Utf8, LOG);
+        assert!(matches!(
+            clean(&path, Strategy::Rewrite, &[], None, 6),
+            Outcome::Cleaned { removed: 1, .. }
+        ));
+        assert_eq!(read_log(&path), LOG.split_once('\n').unwrap().1);
+
+        // A floor above the file's length keeps all of it
+        assert!(matches!(
+            clean(&path, Strategy::TruncateHead, &[], None, 10),
+            Outcome::Cleaned { removed: 0, .. }
+        ));
+        assert_eq!(read_log(&path), LOG.split_once('\n').unwrap().1);
+    }
+
+    /// Returns the results of a run in which one file was cleaned, with or without a capped and a failed file
+    fn results(removed: usize, capped: bool, failed: bool) -> Vec<(String, io::Result<Outcome>)> {
+        let mut results = vec![(
+            String::from("a.log"),
+            Ok(Outcome::Cleaned {
+                removed,
+                bytes: 2048,
+            }),
+        )];
+        if capped {
+            results.push((
+                String::from("b.log"),
+                Ok(Outcome::Capped {
+                    removed: 95,
+                    total: 100,
+                }),
+            ));
+        }
+        if failed {
+            results.push((
+                String::from("c.log"),
+                Err(io::Error::other("permission denied")),
+            ));
+        }
+        results
+    }
+
+    #[test]
+    fn notable_runs_are_detected() {
+        assert!(!is_notable(&results(10, false, false), false, 1000));
+        assert!(is_notable(&results(1000, false, false), false, 1000));
+        assert!(is_notable(&results(10, false, false), true, 1000));
+        assert!(is_notable(&results(10, true, false), false, 1000));
+        assert!(is_notable(&results(10, false, true), false, 1000));
+        assert!(is_notable(&results(0, false, false), false, 0));
+    }
+
+    #[test]
+    fn summary_lists_every_file() {
+        assert_eq!(
+            build_summary(&results(10, true, true)),
+            "cleanlog removed 10 lines (2.0 KB reclaimed) from 3 files\n\
+             a.log: 10 (2.0 KB)\n\
+             b.log: skipped, would remove 95 of 100 lines\n\
+             c.log: failed (permission denied)"
+        );
+    }
+
+    #[test]
+    fn split_lines_keeps_code_units_whole() {
+        // "\u{0A0A}" is a single UTF-16 code unit whose bytes both look like a UTF-8 newline
+        for encoding in [Encoding::Utf16Le, Encoding::Utf16Be] {
+            let mut data = encode(encoding, "first \u{0A0A}\nsecond\nlast");
+            data.push(0x41);
+            let lines = encoding.split_lines(&data);
+            assert_eq!(lines.len(), 3);
+            assert_eq!(encoding.decode(lines[0]), "first \u{0A0A}\n");
+            assert_eq!(encoding.decode(lines[1]), "second\n");
+            assert_eq!(lines[2].len(), 9);
+            assert_eq!(encoding.decode(&lines[2][..8]), "last");
+        }
+    }
+
+    #[test]
+    fn decode_replaces_invalid_sequences() {
+        assert_eq!(Encoding::Latin1.decode(b"caf\xE9"), "café");
+        assert_eq!(Encoding::Utf8.decode(b"caf\xE9"), "caf\u{FFFD}");
+        // An unpaired surrogate
+        assert_eq!(
+            Encoding::Utf16Le.decode(&[0x00, 0xD8, 0x41, 0x00]),
+            "\u{FFFD}A"
+        );
+        assert_eq!(Encoding::Utf16Be.decode(&[0xD8, 0x3D, 0xDE, 0x00]), "😀");
+    }
+
+    #[test]
+    fn encoding_names_are_parsed() {
+        assert!(matches!(
+            Encoding::from_name(" UTF-16LE "),
+            Some(Encoding::Utf16Le)
+        ));
+        assert!(matches!(
+            Encoding::from_name("latin-1"),
+            Some(Encoding::Latin1)
+        ));
+        assert!(Encoding::from_name("utf-32").is_none());
+    }
+
+    #[test]
+    fn glob_match_handles_wildcards() {
+        assert!(glob_match("mytool.*.log", "mytool.2024-05-01.log"));
+        assert!(glob_match("mytool.*.log", "mytool..log"));
+        assert!(!glob_match("mytool.*.log", "mytool.log"));
+        assert!(glob_match("run-?.log", "run-1.log"));
+        assert!(!glob_match("run-?.log", "run-12.log"));
+        // A "*" that first matched too little is backtracked
+        assert!(glob_match("*.tmp.log", "a.tmp.b.tmp.log"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*é*", "café.log"));
+        assert!(!glob_match("", "file.log"));
+    }
 }
@@ -0,0 +1,5 @@
+// Compiles the C shim the unified log mirror calls into (see src/oslog.rs).
+fn main() {
+    println!("cargo:rerun-if-changed=src/oslog.c");
+    cc::Build::new().file("src/oslog.c").compile("cronup_oslog");
+}
@@ -0,0 +1,93 @@
+// The installers `cronup bootstrap` runs on a fresh Mac before the regular updates, so a single
+// command sets up the package managers that the updaters then keep current.
+// An installer is skipped if its tool is already installed, so bootstrapping twice is harmless.
+// Packages listed in the Brewfile named by `CRONUP_BREWFILE_BREW` are installed by the brew updater
+// that runs afterwards. The Homebrew installer asks for administrator access through sudo, so
+// bootstrap is meant to be run from a terminal rather than by launchd.
+
+// Import various modules from the Rust standard library.
+use std::{
+    error::Error,
+    time::{Duration, Instant},
+};
+
+// Shared helpers for settings, logging, and running commands, the tool search of the updaters, and the
+// engine's summary line.
+use crate::{config, engine, logging, process, updaters};
+
+// A package manager and how it is installed.
+struct Installer {
+    name: &'static str,    // The name used in the log and the notification summary.
+    tool: &'static str,    // The executable whose presence means it is installed.
+    command: &'static str, // The shell command that installs it.
+}
+
+// The package managers installed by `cronup bootstrap`, in order.
+const INSTALLERS: &[Installer] = &[
+    Installer {
+        name: "homebrew",
+        tool: "brew",
+        command: "NONINTERACTIVE=1 /bin/bash -c \"$(/usr/bin/curl -fsSL https://raw.githubusercontent.com/Homebrew/install/HEAD/install.sh)\"",
+    },
+    Installer {
+        name: "rustup",
+        tool: "rustup",
+        command: "/usr/bin/curl --proto '=https' --tlsv1.2 -sSf https://sh.rustup.rs | /bin/sh -s -- -y --no-modify-path",
+    },
+];
+
+// The outcome of an installer: its name and the signature of its failure, or `None` if it succeeded.
+pub type Outcome = (&'static str, Option<String>);
+
+// Function to install every package manager that is missing, logging to `cronup.bootstrap.log`.
+// Returns the outcome of every installer that ran.
+pub fn run(log_home: &str) -> Result<Vec<Outcome>, Box<dyn Error>> {
+    let mut log_file = logging::open_log(log_home, "bootstrap")?;
+    let mut results = Vec::new();
+
+    for installer in INSTALLERS {
+        if let Some(path) = updaters::locate(installer.tool) {
+            logging::log_line(
+                &mut log_file,
+                &format!(
+                    "{} already installed at {} - skipped.",
+                    installer.name, path
+                ),
+            )?;
+            logging::log_line(
+                &mut log_file,
+                &engine::result_line(installer.name, "skipped", Duration::ZERO, Some(0)),
+            )?;
+            continue;
+        }
+
+        logging::log_line(
+            &mut log_file,
+            &format!("Installing {}: {}", installer.name, installer.command),
+        )?;
+        let start = Instant::now();
+        let output = process::run_with_timeout(
+            process::shell(installer.command),
+            config::get_timeout("bootstrap"),
+            None,
+        )?;
+        logging::write_output(&mut log_file, &output)?;
+
+        let failure = output.failure_signature();
+        match &failure {
+            None => logging::log_line(&mut log_file, &format!("Installed {}.", installer.name))?,
+            Some(signature) => logging::log_line(
+                &mut log_file,
+                &format!("Installing {} failed: {}", installer.name, signature),
+            )?,
+        }
+        let status = if failure.is_none() { "ok" } else { "failed" };
+        logging::log_line(
+            &mut log_file,
+            &engine::result_line(installer.name, status, start.elapsed(), None),
+        )?;
+        results.push((installer.name, failure));
+    }
+
+    Ok(results)
+}
@@ -0,0 +1,183 @@
+// Check-only tasks that monitor the system rather than update it.
+// Each check runs a shell command and compares its output against a threshold or pattern;
+// violations are logged to `cronup.checks.log` and reported in the run summary.
+
+// Import various modules from the Rust standard library.
+use std::error::Error;
+
+// The `regex` crate is used for pattern conditions.
+use regex::Regex;
+
+// Shared helpers for configuration, logging, and running commands.
+use crate::{config, logging, process};
+
+// How a check's output is judged. Numeric conditions use the first number found in the output.
+pub enum Condition {
+    // The value must not exceed this maximum.
+    Max(f64),
+    // The value must not fall below this minimum.
+    Min(f64),
+    // The output must contain this text.
+    Contains(&'static str),
+    // The output must not contain this text.
+    Excludes(&'static str),
+    // The output must match this regular expression.
+    Matches(&'static str),
+}
+
+impl Condition {
+    // Judges a check's output, returning whether it passed and a description of what was observed.
+    fn evaluate(&self, output: &str) -> (bool, String) {
+        match *self {
+            Condition::Max(max) => match parse_first_number(output) {
+                Some(value) => (value <= max, format!("value {}, max {}", value, max)),
+                None => (false, String::from("no numeric output")),
+            },
+            Condition::Min(min) => match parse_first_number(output) {
+                Some(value) => (value >= min, format!("value {}, min {}", value, min)),
+                None => (false, String::from("no numeric output")),
+            },
+            Condition::Contains(pattern) => {
+                (output.contains(pattern), format!("expected '{}'", pattern))
+            }
+            Condition::Excludes(pattern) => (
+                !output.contains(pattern),
+                format!("unexpected '{}'", pattern),
+            ),
+            Condition::Matches(pattern) => match Regex::new(pattern) {
+                Ok(regex) => (regex.is_match(output), format!("expected /{}/", pattern)),
+                Err(error) => (false, format!("invalid pattern /{}/: {}", pattern, error)),
+            },
+        }
+    }
+}
+
+// Configuration structure to define a check-only task that monitors rather than updates.
+pub struct Check {
+    pub name: &'static str, // The name used in the checks log and the notification summary.
+    command: &'static str,  // The shell command whose output is inspected.
+    condition: Condition,   // The condition the output must satisfy.
+}
+
+// Static configuration array - modify this to set up your checks.
+// Each entry runs a shell command and compares its output against a threshold or pattern.
+// For example, a Time Machine backup age check in hours could look like this:
+//     Check {
+//         name: "backup",
+//         command: "echo $(( ($(date +%s) - $(stat -f %m \"$(tmutil latestbackup)\")) / 3600 ))",
+//         condition: Condition::Max(48.0),
+//     },
+pub const CHECKS: &[Check] = &[
+    Check {
+        name: "disk",
+        command: "/bin/df -P / | /usr/bin/awk 'NR == 2 { print $5 }'",
+        condition: Condition::Max(90.0),
+    },
+    Check {
+        name: "smart",
+        command: "/usr/sbin/diskutil info disk0 | /usr/bin/grep 'SMART Status'",
+        condition: Condition::Matches(r"SMART Status:\s+Verified"),
+    },
+    Check {
+        name: "memory",
+        command: "/usr/bin/memory_pressure | /usr/bin/grep 'free percentage'",
+        condition: Condition::Min(10.0),
+    },
+    Check {
+        name: "gatekeeper",
+        command: "/usr/sbin/spctl --status",
+        condition: Condition::Excludes("disabled"),
+    },
+    Check {
+        name: "filevault",
+        command: "/usr/bin/fdesetup status",
+        condition: Condition::Contains("FileVault is On."),
+    },
+];
+
+// Function to run a check-only task and log whether its output satisfies its condition.
+// A check whose command fails or times out counts as a violation.
+// Returns whether the check passed.
+pub fn run_check(check: &Check, log_home: &str) -> Result<bool, Box<dyn Error>> {
+    // All checks share one log file.
+    let mut log_file = logging::open_log(log_home, "checks")?;
+
+    // Run the check without retries; a flaky check should be visible as such.
+    let output = process::run_with_timeout(
+        process::shell(check.command),
+        config::get_timeout(check.name),
+        None,
+    )?;
+
+    // Evaluate the condition and describe the observed value for the log.
+    let (passed, observed) = if output.succeeded() {
+        check
+            .condition
+            .evaluate(&String::from_utf8_lossy(&output.stdout))
+    } else {
+        (false, String::from("command failed"))
+    };
+
+    // Log the verdict; violations include the raw output to help diagnose them.
+    logging::log_line(
+        &mut log_file,
+        &format!(
+            "Check '{}' {} ({})",
+            check.name,
+            if passed { "passed" } else { "VIOLATED" },
+            observed
+        ),
+    )?;
+    if !passed {
+        logging::write_output(&mut log_file, &output)?;
+    }
+
+    Ok(passed)
+}
+
+// Function to find the first number in a command's output, ignoring units such as '%' or 'G'.
+fn parse_first_number(text: &str) -> Option<f64> {
+    text.split_whitespace().find_map(|word| {
+        word.trim_matches(|c: char| !c.is_ascii_digit() && c != '.' && c != '-')
+            .parse::<f64>()
+            .ok()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conditions_judge_output() {
+        assert!(Condition::Max(90.0).evaluate("85%").0);
+        assert!(!Condition::Max(90.0).evaluate("95%").0);
+        assert!(
+            Condition::Min(10.0)
+                .evaluate("System-wide memory free percentage: 45%")
+                .0
+        );
+        assert!(!Condition::Min(10.0).evaluate("no number").0);
+        assert!(
+            Condition::Contains("Verified")
+                .evaluate("SMART Status: Verified")
+                .0
+        );
+        assert!(
+            !Condition::Excludes("disabled")
+                .evaluate("assessments disabled")
+                .0
+        );
+        assert!(
+            Condition::Matches(r"SMART Status:\s+Verified")
+                .evaluate("   SMART Status:              Verified")
+                .0
+        );
+        assert!(
+            !Condition::Matches(r"SMART Status:\s+Verified")
+                .evaluate("   SMART Status:              Failing")
+                .0
+        );
+        assert!(!Condition::Matches("(").evaluate("anything").0);
+    }
+}
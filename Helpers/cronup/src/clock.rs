@@ -0,0 +1,59 @@
+// Sanity checks of the system clock, run before anything is logged.
+// If the newest log file or the last run in the history lies further in the future than the
+// tolerance allows, the clock has jumped backwards (e.g. after a dead RTC battery or an NTP
+// misconfiguration). The run then still updates, but skips actions that depend on comparing
+// times, such as pruning old package versions and recording the run history.
+
+// The `Local` and `NaiveDateTime` types from the `chrono` crate are used for reading history timestamps.
+use chrono::{Local, NaiveDateTime};
+
+// Import various modules from the Rust standard library.
+use std::{
+    fs,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+// The skew the current run's `check` found, if any, so every updater can consult it.
+static SKEW: Mutex<Option<Duration>> = Mutex::new(None);
+
+// Function to check the system clock against the files in `log_home`.
+// Returns how far the clock is behind the newest of them if that exceeds `tolerance`.
+pub fn check(log_home: &str, tolerance: Duration) -> Option<Duration> {
+    let latest = [latest_modification(log_home), last_history_entry(log_home)]
+        .into_iter()
+        .flatten()
+        .max();
+    let skew = latest
+        .and_then(|latest| latest.duration_since(SystemTime::now()).ok())
+        .filter(|skew| *skew > tolerance);
+    if let Ok(mut found) = SKEW.lock() {
+        *found = skew;
+    }
+    skew
+}
+
+// Function to determine whether `check` found the clock to be skewed.
+pub fn is_skewed() -> bool {
+    SKEW.lock().is_ok_and(|found| found.is_some())
+}
+
+// Function to find the newest modification time of cronup's files in `log_home`.
+fn latest_modification(log_home: &str) -> Option<SystemTime> {
+    fs::read_dir(log_home)
+        .ok()?
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("cronup."))
+        .filter_map(|entry| entry.metadata().ok()?.modified().ok())
+        .max()
+}
+
+// Function to read the start time of the last run recorded in the history.
+// History timestamps are local times, so they are converted with the current offset.
+fn last_history_entry(log_home: &str) -> Option<SystemTime> {
+    let history = fs::read_to_string(format!("{}/cronup.history", log_home)).ok()?;
+    let started = history.lines().last()?.split('\t').next()?;
+    let started = NaiveDateTime::parse_from_str(started, "%Y-%m-%d %H:%M:%S").ok()?;
+    let started = started.and_local_timezone(Local).earliest()?;
+    Some(SystemTime::from(started))
+}
@@ -0,0 +1,587 @@
+// Settings read from the environment, so they can be configured in the LaunchAgent plist.
+
+// The types from the `chrono` crate used for the quiet hours and the daemon's schedules.
+use chrono::{Datelike, Days, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, Timelike};
+
+// Import various modules from the Rust standard library.
+use std::{env, time::Duration};
+
+// The output sampling and prompt handling settings.
+use crate::process::{PromptAction, PromptHandling, Sampling};
+
+// The timeout applied to an updater when neither `CRONUP_TIMEOUT_<NAME>` nor `CRONUP_TIMEOUT` is set.
+const DEFAULT_TIMEOUT_SECS: u64 = 1800;
+
+// How far the clock may lag behind the newest log file when `CRONUP_CLOCK_TOLERANCE` is not set.
+const DEFAULT_CLOCK_TOLERANCE_SECS: u64 = 300;
+
+// Every how many lines one is kept in the middle of sampled output when `CRONUP_SAMPLE_EVERY` is not set.
+const DEFAULT_SAMPLE_EVERY: u64 = 100;
+
+// How many times its median output size a run must produce to be flagged when `CRONUP_VOLUME_FACTOR`
+// is not set.
+const DEFAULT_VOLUME_FACTOR: u64 = 5;
+
+// How many times its average duration a run must take to be flagged when `CRONUP_DURATION_FACTOR`
+// is not set.
+const DEFAULT_DURATION_FACTOR: u64 = 3;
+
+// How long an updater's output must stall at a prompt before it counts as waiting for input when
+// `CRONUP_PROMPT_IDLE` is not set.
+const DEFAULT_PROMPT_IDLE_SECS: u64 = 60;
+
+// The answer given to prompts when `CRONUP_PROMPT_ANSWER` is not set.
+const DEFAULT_PROMPT_ANSWER: &str = "y";
+
+// The schedule of an updater in daemon mode when `CRONUP_SCHEDULE` is not set: hourly, like the
+// LaunchAgent's `StartInterval`.
+const DEFAULT_SCHEDULE: &str = "0 * * * *";
+
+// How long the daemon waits for the network after the Mac woke from sleep when `CRONUP_WAKE_DELAY` is
+// not set.
+const DEFAULT_WAKE_DELAY_SECS: u64 = 60;
+
+// The locale tools run in when `CRONUP_LOCALE` is not set.
+const DEFAULT_LOCALE: &str = "C";
+
+// How many days a drift baseline is kept when `CRONUP_DRIFT_DAYS` is not set.
+const DEFAULT_DRIFT_DAYS: u64 = 7;
+
+// The tools whose versions drift detection tracks when `CRONUP_DRIFT_TOOLS` is not set.
+const DEFAULT_DRIFT_TOOLS: &str =
+    "node; npm; python3; ruby; go version; rustc; cargo; git; nvim; brew";
+
+// The files whose contents drift detection tracks when `CRONUP_DRIFT_FILES` is not set.
+const DEFAULT_DRIFT_FILES: &str =
+    "~/.zshrc; ~/.zprofile; ~/.gitconfig; ~/.npmrc; ~/.cargo/config.toml; /etc/paths";
+
+// Function to retrieve the log directory path from the environment variable `LOG_HOME`.
+// If `LOG_HOME` is not set, default to `"/var/logs"`. `~` and variables are expanded.
+pub fn log_home() -> String {
+    env::var("LOG_HOME").map_or_else(|_| String::from("/var/logs"), |path| expand_path(&path))
+}
+
+// Function to expand a configured path the way the shell would: a leading `~` becomes the home
+// directory, and `$VAR` or `${VAR}` the variable's value (nothing if it is unset). `~user` is kept.
+// Paths are quoted when they are passed to the shell, so it can't expand them itself.
+pub fn expand_path(path: &str) -> String {
+    let (home, rest) = match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+            (env::var("HOME").unwrap_or_default(), rest)
+        }
+        _ => (String::new(), path),
+    };
+
+    let mut expanded = home;
+    let mut rest = rest;
+    while let Some(index) = rest.find('$') {
+        expanded.push_str(&rest[..index]);
+        let after = &rest[index + 1..];
+        let (name, remainder) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], &braced[end + 1..]),
+                None => ("", after),
+            },
+            None => {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..end], &after[end..])
+            }
+        };
+        if name.is_empty() {
+            // A `$` that doesn't start a variable name is kept as it is.
+            expanded.push('$');
+            rest = after;
+        } else {
+            expanded.push_str(&env::var(name).unwrap_or_default());
+            rest = remainder;
+        }
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+// Function to determine the locale every tool runs in, so the output cronup parses (e.g. brew's list
+// of upgrades or a check's decimal separator) doesn't change with the user's language settings.
+// Configured through `CRONUP_LOCALE`, e.g. `en_US.UTF-8` for a tool that needs UTF-8; set to an empty
+// value, tools inherit cronup's locale.
+pub fn get_locale() -> Option<String> {
+    match env::var("CRONUP_LOCALE") {
+        Ok(locale) => Some(locale.trim().to_string()).filter(|locale| !locale.is_empty()),
+        Err(_) => Some(String::from(DEFAULT_LOCALE)),
+    }
+}
+
+// Function to determine how long an updater may run before it is killed.
+// Configured through `CRONUP_TIMEOUT_<NAME>` or `CRONUP_TIMEOUT`, in seconds.
+pub fn get_timeout(name: &str) -> Duration {
+    Duration::from_secs(get_setting("TIMEOUT", name, DEFAULT_TIMEOUT_SECS))
+}
+
+// Function to determine how an updater's output is sampled.
+// `CRONUP_SAMPLE_LINES_<NAME>` or `CRONUP_SAMPLE_LINES` sets how many of the first and of the last lines
+// are kept, and `CRONUP_SAMPLE_EVERY_<NAME>` or `CRONUP_SAMPLE_EVERY` every how many lines one is kept
+// in between. Output is kept in full when no line limit is set or it is 0.
+pub fn get_sampling(name: &str) -> Option<Sampling> {
+    let lines = get_setting("SAMPLE_LINES", name, 0);
+    let every = get_setting("SAMPLE_EVERY", name, DEFAULT_SAMPLE_EVERY);
+    (lines > 0).then(|| Sampling {
+        lines: lines as usize,
+        every: every.max(1) as usize,
+    })
+}
+
+// Function to determine what happens when an updater waits for input, e.g. at "Proceed? [y/N]".
+// `CRONUP_ON_PROMPT_<NAME>` or `CRONUP_ON_PROMPT` selects the action: `answer` writes the answer in
+// `CRONUP_PROMPT_ANSWER[_<NAME>]` (by default "y"), `notify` posts a notification and leaves it
+// waiting until its timeout, `ignore` just waits, and anything else (or none) kills it.
+// `CRONUP_PROMPT_IDLE_<NAME>` or `CRONUP_PROMPT_IDLE` sets how many seconds its output must stall
+// first; 0 disables the detection.
+pub fn get_prompt_handling(name: &str) -> Option<PromptHandling> {
+    let idle = get_setting("PROMPT_IDLE", name, DEFAULT_PROMPT_IDLE_SECS);
+    let action = match get_text_setting("ON_PROMPT", name).as_deref() {
+        Some("ignore") => return None,
+        Some("answer") => PromptAction::Answer(
+            get_text_setting("PROMPT_ANSWER", name)
+                .unwrap_or_else(|| String::from(DEFAULT_PROMPT_ANSWER)),
+        ),
+        Some("notify") => PromptAction::Notify(name.to_string()),
+        _ => PromptAction::Kill,
+    };
+    (idle > 0).then(|| PromptHandling {
+        idle: Duration::from_secs(idle),
+        action,
+    })
+}
+
+// Function to determine how long an updater's command may go without any output before it counts as
+// hung and is killed. `CRONUP_INACTIVITY_<NAME>` or `CRONUP_INACTIVITY` sets it in seconds, overriding
+// the updater's own `default`; 0 disables the detection.
+pub fn get_inactivity(name: &str, default: u64) -> Option<Duration> {
+    let limit = get_setting("INACTIVITY", name, default);
+    (limit > 0).then(|| Duration::from_secs(limit))
+}
+
+// Function to determine whether progress notifications are posted when a run starts and at its halfway
+// point. Enabled with `CRONUP_PROGRESS=1`.
+pub fn get_progress_enabled() -> bool {
+    env::var("CRONUP_PROGRESS").is_ok_and(|value| value.trim() == "1")
+}
+
+// Function to determine how far the system clock may lag behind the newest log file before it is
+// considered to have jumped backwards. Configured through `CRONUP_CLOCK_TOLERANCE`, in seconds.
+pub fn get_clock_tolerance() -> Duration {
+    Duration::from_secs(
+        env::var("CRONUP_CLOCK_TOLERANCE")
+            .ok()
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(DEFAULT_CLOCK_TOLERANCE_SECS),
+    )
+}
+
+// Function to determine how many times its median output size an updater's run must produce to be
+// flagged as a log volume anomaly. Configured through `CRONUP_VOLUME_FACTOR_<NAME>` or
+// `CRONUP_VOLUME_FACTOR`; 0 disables the check.
+pub fn get_volume_factor(name: &str) -> u64 {
+    get_setting("VOLUME_FACTOR", name, DEFAULT_VOLUME_FACTOR)
+}
+
+// Function to determine how many times the average duration of its recent runs an updater's run must
+// take to be flagged as a duration regression. Configured through `CRONUP_DURATION_FACTOR_<NAME>` or
+// `CRONUP_DURATION_FACTOR`; 0 disables the check.
+pub fn get_duration_factor(name: &str) -> u64 {
+    get_setting("DURATION_FACTOR", name, DEFAULT_DURATION_FACTOR)
+}
+
+// Function to determine how long after its last successful run an updater is skipped, e.g. when
+// cronup runs from both launchd and the command line. Configured through `CRONUP_MIN_INTERVAL_<NAME>`
+// or `CRONUP_MIN_INTERVAL`, in hours; unset or 0 runs the updater every time.
+pub fn get_min_interval(name: &str) -> Option<Duration> {
+    let hours = get_setting("MIN_INTERVAL", name, 0);
+    (hours > 0).then(|| Duration::from_secs(hours * 3600))
+}
+
+// Function to determine whether an updater only runs on AC power, e.g. `brew` with its large downloads
+// and builds. `CRONUP_REQUIRE_AC_<NAME>` or `CRONUP_REQUIRE_AC` set to 1 defers it while on battery.
+pub fn requires_ac_power(name: &str) -> bool {
+    get_setting("REQUIRE_AC", name, 0) > 0
+}
+
+// Function to determine whether an updater is deferred while on a metered network, e.g. `brew` whose
+// downloads would use up a phone's data plan. `CRONUP_ON_METERED_<NAME>` or `CRONUP_ON_METERED` set to
+// `skip` defers it; anything else runs it.
+pub fn skip_on_metered(name: &str) -> bool {
+    get_text_setting("ON_METERED", name).as_deref() == Some("skip")
+}
+
+// A daily time window, e.g. 09:00-18:00, which may span midnight, e.g. 22:00-06:00.
+pub struct QuietHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl QuietHours {
+    // Returns `true` if the window contains the given time of day. The end is not included.
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+// Function to determine the quiet hours during which an updater doesn't run, e.g. `brew` whose builds
+// would slow the machine down during work hours. Configured through `CRONUP_QUIET_HOURS_<NAME>` or
+// `CRONUP_QUIET_HOURS` as `HH:MM-HH:MM` in local time; unset, empty, or unparsable means none.
+pub fn get_quiet_hours(name: &str) -> Option<QuietHours> {
+    let window = get_text_setting("QUIET_HOURS", name)?;
+    let (start, end) = window.split_once(['-', '\u{2013}'])?;
+    let parse = |time: &str| NaiveTime::parse_from_str(time.trim(), "%H:%M").ok();
+    Some(QuietHours {
+        start: parse(start)?,
+        end: parse(end)?,
+    })
+}
+
+// A cron-like schedule of five fields: minute, hour, day of month, month, and day of week (0 or 7 is
+// Sunday), e.g. "30 3 * * 1-5". Each field is `*`, a number, a range like `1-5`, a step like `*/15` or
+// `0-30/10`, or a comma-separated list of those. As in cron, if both day fields are restricted, a
+// day matching either of them matches.
+pub struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Schedule {
+    // Parses a schedule. Returns `None` if it doesn't have five valid fields.
+    pub fn parse(expression: &str) -> Option<Schedule> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return None;
+        };
+        let mut weekdays = parse_field(weekday, 0, 7)?;
+        if weekdays & 1 << 7 != 0 {
+            weekdays |= 1;
+        }
+        Some(Schedule {
+            minutes: parse_field(minute, 0, 59)?,
+            hours: parse_field(hour, 0, 23)?,
+            days: parse_field(day, 1, 31)?,
+            months: parse_field(month, 1, 12)?,
+            weekdays,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+
+    // Returns `true` if the schedule is due at the minute of the given local time.
+    pub fn matches(&self, time: NaiveDateTime) -> bool {
+        let contains = |set: u64, value: u32| set & 1 << value != 0;
+        contains(self.minutes, time.minute())
+            && contains(self.hours, time.hour())
+            && self.matches_day(time.date())
+    }
+
+    // Returns `true` if the schedule is due at some minute of the given day.
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        let contains = |set: u64, value: u32| set & 1 << value != 0;
+        let day = contains(self.days, date.day());
+        let weekday = contains(self.weekdays, date.weekday().num_days_from_sunday());
+        let day_matches = if self.any_day || self.any_weekday {
+            day && weekday
+        } else {
+            day || weekday
+        };
+        contains(self.months, date.month()) && day_matches
+    }
+
+    // Returns the first minute after the given local time at which the schedule is due. It looks up to
+    // four years ahead, so a schedule on February 29 is found; `None` if it's never due, e.g. on April 31.
+    pub fn next_after(&self, time: NaiveDateTime) -> Option<NaiveDateTime> {
+        let start = (time + TimeDelta::minutes(1))
+            .with_second(0)?
+            .with_nanosecond(0)?;
+        (0..=4 * 366)
+            .filter_map(|offset| start.date().checked_add_days(Days::new(offset)))
+            .filter(|date| self.matches_day(*date))
+            .find_map(|date| {
+                (0..24 * 60)
+                    .filter_map(|minute| date.and_hms_opt(minute / 60, minute % 60, 0))
+                    .find(|candidate| *candidate >= start && self.matches(*candidate))
+            })
+    }
+}
+
+// Function to parse a field of a schedule into the set of values it matches, one bit per value.
+// A bare number with a step, e.g. `5/15`, runs from that number to the maximum, as in cron.
+fn parse_field(field: &str, min: u32, max: u32) -> Option<u64> {
+    let mut set = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|step| *step > 0)?),
+            None => (part, 1),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (start.parse().ok()?, end.parse().ok()?)
+        } else {
+            let value = range.parse().ok()?;
+            (value, if step > 1 { max } else { value })
+        };
+        if start < min || end > max || start > end {
+            return None;
+        }
+        for value in (start..=end).step_by(step as usize) {
+            set |= 1 << value;
+        }
+    }
+    Some(set)
+}
+
+// Function to get the schedule an updater runs on in daemon mode, from `CRONUP_SCHEDULE_<NAME>` or
+// `CRONUP_SCHEDULE`, hourly if neither is set. "off" means the updater isn't scheduled. Returns the
+// expression as configured; `Schedule::parse` validates it.
+pub fn get_schedule(name: &str) -> String {
+    get_text_setting("SCHEDULE", name).unwrap_or_else(|| DEFAULT_SCHEDULE.to_string())
+}
+
+// Function to read the settings file of the daemon, `CRONUP_CONFIG`: `NAME=value` lines like the
+// LaunchAgent's `EnvironmentVariables`, where blank lines and lines starting with `#` are ignored.
+// Returns the settings in file order, or the first malformed line.
+pub fn read_settings_file(path: &str) -> Result<Vec<(String, String)>, String> {
+    let contents = std::fs::read_to_string(path).map_err(|error| error.to_string())?;
+    let mut settings = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let setting = line.split_once('=').and_then(|(name, value)| {
+            let name = name.trim();
+            let valid = !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_');
+            valid.then(|| (name.to_string(), value.trim().to_string()))
+        });
+        match setting {
+            Some(setting) => settings.push(setting),
+            None => return Err(format!("line {}: expected NAME=value", index + 1)),
+        }
+    }
+    Ok(settings)
+}
+
+// Function to determine how many updaters run at the same time, from `CRONUP_MAX_PARALLEL`.
+// Unset, unparsable, or 0 means one at a time.
+pub fn get_max_parallel() -> usize {
+    env::var("CRONUP_MAX_PARALLEL")
+        .ok()
+        .and_then(|value| value.trim().parse::<usize>().ok())
+        .unwrap_or(1)
+        .max(1)
+}
+
+// Function to determine how long the daemon waits for the network to come back after the Mac woke
+// from sleep before it catches up on the missed runs. Configured through `CRONUP_WAKE_DELAY`, in seconds.
+pub fn get_wake_delay() -> Duration {
+    Duration::from_secs(
+        env::var("CRONUP_WAKE_DELAY")
+            .ok()
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(DEFAULT_WAKE_DELAY_SECS),
+    )
+}
+
+// Function to determine how many days a drift baseline is kept, from `CRONUP_DRIFT_DAYS`.
+// Returns `None` if it is 0, which turns drift detection off.
+pub fn get_drift_days() -> Option<u64> {
+    let days = env::var("CRONUP_DRIFT_DAYS")
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(DEFAULT_DRIFT_DAYS);
+    (days > 0).then_some(days)
+}
+
+// Function to get the tools whose versions drift detection tracks, from `CRONUP_DRIFT_TOOLS`:
+// separated by semicolons like the smoke tests, e.g. "node; go version".
+pub fn get_drift_tools() -> Vec<String> {
+    get_list("CRONUP_DRIFT_TOOLS", DEFAULT_DRIFT_TOOLS)
+}
+
+// Function to get the files whose contents drift detection tracks, from `CRONUP_DRIFT_FILES`:
+// paths separated by semicolons, which may start with `~`.
+pub fn get_drift_files() -> Vec<String> {
+    get_list("CRONUP_DRIFT_FILES", DEFAULT_DRIFT_FILES)
+}
+
+// Function to split a semicolon-separated list setting into its entries, or the default's.
+fn get_list(key: &str, default: &str) -> Vec<String> {
+    env::var(key)
+        .unwrap_or_else(|_| String::from(default))
+        .split(';')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+// Function to determine whether an updater's remaining commands still run after one of them failed.
+// `CRONUP_ON_FAILURE_<NAME>` or `CRONUP_ON_FAILURE` set to `continue` runs them; anything else aborts.
+pub fn continue_on_failure(name: &str) -> bool {
+    get_text_setting("ON_FAILURE", name).as_deref() == Some("continue")
+}
+
+// Function to determine whether an updater's commands run as root through sudo.
+// `CRONUP_SUDO_<NAME>` set to 1 or 0 overrides the updater's own answer.
+pub fn needs_sudo(name: &str, default: bool) -> bool {
+    env::var(format!("CRONUP_SUDO_{}", name.to_uppercase()))
+        .ok()
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .map_or(default, |value| value != 0)
+}
+
+// Function to read a numeric per-updater setting from the environment.
+// `CRONUP_<SETTING>_<NAME>` (e.g. `CRONUP_RETRIES_BREW`) takes precedence over the global
+// `CRONUP_<SETTING>`. Unset or unparsable values fall back to the next option.
+pub fn get_setting(setting: &str, name: &str, default: u64) -> u64 {
+    let per_updater = format!("CRONUP_{}_{}", setting, name.to_uppercase());
+    let global = format!("CRONUP_{}", setting);
+    [per_updater, global]
+        .iter()
+        .find_map(|key| env::var(key).ok()?.trim().parse::<u64>().ok())
+        .unwrap_or(default)
+}
+
+// Function to get the smoke tests run before and after an updater's commands, from
+// `CRONUP_SMOKE_TESTS_<NAME>` or `CRONUP_SMOKE_TESTS`: shell commands separated by semicolons, where a
+// bare tool name stands for `<tool> --version`, e.g. "nvim; rg; gh auth status".
+pub fn get_smoke_tests(name: &str) -> Vec<String> {
+    get_text_setting("SMOKE_TESTS", name)
+        .unwrap_or_default()
+        .split(';')
+        .map(str::trim)
+        .filter(|test| !test.is_empty())
+        .map(|test| {
+            if test.contains(char::is_whitespace) {
+                test.to_string()
+            } else {
+                format!("{} --version", test)
+            }
+        })
+        .collect()
+}
+
+// Function to read a textual per-updater setting from the environment, with the same precedence
+// as `get_setting`. Returns `None` if neither variable is set.
+pub fn get_text_setting(setting: &str, name: &str) -> Option<String> {
+    let per_updater = format!("CRONUP_{}_{}", setting, name.to_uppercase());
+    let global = format!("CRONUP_{}", setting);
+    [per_updater, global]
+        .iter()
+        .find_map(|key| env::var(key).ok())
+        .map(|value| value.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn quiet_hours_contain_times_within_the_window() {
+        let work_hours = QuietHours {
+            start: time(9, 0),
+            end: time(18, 0),
+        };
+        assert!(work_hours.contains(time(9, 0)));
+        assert!(work_hours.contains(time(17, 59)));
+        assert!(!work_hours.contains(time(18, 0)));
+        assert!(!work_hours.contains(time(3, 0)));
+
+        // A window spanning midnight contains the evening and the morning.
+        let night = QuietHours {
+            start: time(22, 0),
+            end: time(6, 0),
+        };
+        assert!(night.contains(time(23, 30)));
+        assert!(night.contains(time(5, 0)));
+        assert!(!night.contains(time(12, 0)));
+    }
+
+    fn at(date: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn schedules_match_like_cron() {
+        // 2024-05-06 is a Monday.
+        let weekday_mornings = Schedule::parse("30 3 * * 1-5").unwrap();
+        assert!(weekday_mornings.matches(at("2024-05-06 03:30")));
+        assert!(!weekday_mornings.matches(at("2024-05-06 03:31")));
+        assert!(!weekday_mornings.matches(at("2024-05-05 03:30")));
+
+        let quarter_hours = Schedule::parse("*/15 * * * *").unwrap();
+        assert!(quarter_hours.matches(at("2024-05-06 12:45")));
+        assert!(!quarter_hours.matches(at("2024-05-06 12:50")));
+
+        // Sunday is 7 as well as 0.
+        assert!(Schedule::parse("0 12 * * 7")
+            .unwrap()
+            .matches(at("2024-05-05 12:00")));
+
+        // With both day fields restricted, either one matches.
+        let first_or_sunday = Schedule::parse("0 0 1 * 0").unwrap();
+        assert!(first_or_sunday.matches(at("2024-05-01 00:00")));
+        assert!(first_or_sunday.matches(at("2024-05-05 00:00")));
+        assert!(!first_or_sunday.matches(at("2024-05-06 00:00")));
+
+        // The next run is the first matching minute after the given time.
+        assert_eq!(
+            weekday_mornings.next_after(at("2024-05-06 03:30")),
+            Some(at("2024-05-07 03:30"))
+        );
+        assert_eq!(
+            weekday_mornings.next_after(at("2024-05-03 04:00")),
+            Some(at("2024-05-06 03:30"))
+        );
+        assert_eq!(
+            quarter_hours.next_after(at("2024-05-06 12:44")),
+            Some(at("2024-05-06 12:45"))
+        );
+        assert_eq!(
+            Schedule::parse("0 0 29 2 *")
+                .unwrap()
+                .next_after(at("2024-03-01 00:00")),
+            Some(at("2028-02-29 00:00"))
+        );
+        assert_eq!(
+            Schedule::parse("0 0 31 4 *")
+                .unwrap()
+                .next_after(at("2024-03-01 00:00")),
+            None
+        );
+
+        for invalid in [
+            "",
+            "* * * *",
+            "60 * * * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "a * * * *",
+        ] {
+            assert!(Schedule::parse(invalid).is_none(), "{}", invalid);
+        }
+    }
+}
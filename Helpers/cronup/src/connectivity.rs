@@ -0,0 +1,94 @@
+// The network check run before the updaters, which tells three states apart:
+// - offline: none of the probe addresses in `CRONUP_PROBES` (host:port, separated by spaces or
+//   commas; by default Quad9's and Cloudflare's DNS servers) accepts a TCP connection, and a HEAD
+//   request to the URL in `CRONUP_FALLBACK_URL` gets no response either (networks that block
+//   outbound DNS often still allow HTTPS),
+// - portal: the network is reachable, but the URL in `CRONUP_PORTAL_URL` doesn't answer with HTTP 204, as on
+//   Wi-Fi whose captive portal intercepts web traffic until a login page is accepted, and
+// - online: both succeed.
+// An empty `CRONUP_FALLBACK_URL` disables the fallback, and an empty `CRONUP_PORTAL_URL` the portal
+// check.
+
+// Import various modules from the Rust standard library.
+use std::{
+    env,
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+// Running curl for the fallback and portal checks.
+use crate::process;
+
+// The probe addresses used when `CRONUP_PROBES` is not set.
+const DEFAULT_PROBES: &str = "9.9.9.9:53 1.1.1.1:53";
+
+// The URL requested when no probe address is reachable and `CRONUP_FALLBACK_URL` is not set.
+const DEFAULT_FALLBACK_URL: &str = "https://www.apple.com/";
+
+// The URL of the portal check when `CRONUP_PORTAL_URL` is not set. It answers with an empty
+// HTTP 204 response, which a captive portal replaces with a redirect or its login page.
+const DEFAULT_PORTAL_URL: &str = "http://connectivitycheck.gstatic.com/generate_204";
+
+// How long a single probe, the fallback request, or the portal check may take.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// The result of the network check.
+pub enum Connectivity {
+    Online,
+    // A captive portal intercepts web traffic; describes what the portal check got instead.
+    Portal(String),
+    Offline,
+}
+
+// Function to check whether the network is usable for downloads.
+pub fn check() -> Connectivity {
+    let probes = env::var("CRONUP_PROBES").unwrap_or_else(|_| String::from(DEFAULT_PROBES));
+    let reachable = probes
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|probe| !probe.is_empty())
+        .any(probe);
+    if !reachable && !fallback_reachable() {
+        return Connectivity::Offline;
+    }
+
+    let url = env::var("CRONUP_PORTAL_URL").unwrap_or_else(|_| String::from(DEFAULT_PORTAL_URL));
+    let url = url.trim();
+    if url.is_empty() {
+        return Connectivity::Online;
+    }
+    match http_status(url, false).as_deref() {
+        Some("204") => Connectivity::Online,
+        Some(status) => Connectivity::Portal(format!("HTTP {} from {}", status, url)),
+        None => Connectivity::Portal(format!("no HTTP response from {}", url)),
+    }
+}
+
+// Function to try a TCP connection to a probe address, resolving its host name if needed.
+fn probe(address: &str) -> bool {
+    address.to_socket_addrs().is_ok_and(|mut addresses| {
+        addresses.any(|address| TcpStream::connect_timeout(&address, PROBE_TIMEOUT).is_ok())
+    })
+}
+
+// Function to check whether the fallback URL answers a HEAD request with any status at all.
+fn fallback_reachable() -> bool {
+    let url =
+        env::var("CRONUP_FALLBACK_URL").unwrap_or_else(|_| String::from(DEFAULT_FALLBACK_URL));
+    let url = url.trim();
+    !url.is_empty() && http_status(url, true).is_some()
+}
+
+// Function to request a URL without following redirects, with a HEAD request if `head` is set.
+// Returns the HTTP status code, or `None` if there was no response at all.
+fn http_status(url: &str, head: bool) -> Option<String> {
+    let command = process::shell(&format!(
+        "/usr/bin/curl -s{} -o /dev/null -w '%{{http_code}}' -m {} '{}'",
+        if head { "I" } else { "" },
+        PROBE_TIMEOUT.as_secs(),
+        url.replace('\'', "'\\''")
+    ));
+    let output = process::run_with_timeout(command, PROBE_TIMEOUT * 2, None).ok()?;
+    let status = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    // curl reports "000" when it got no response.
+    (!status.is_empty() && status != "000").then_some(status)
+}
@@ -0,0 +1,318 @@
+// `cronup daemon` stays resident and starts the runs itself, as an alternative to launchd's
+// `StartInterval` or cron. Every updater runs on the cron-like schedule in `CRONUP_SCHEDULE_<NAME>` or
+// `CRONUP_SCHEDULE` (hourly by default, "off" for never), and updaters due at the same minute share a
+// run. Every run is a separate `cronup --only <names>` process, so it is locked, logged, and notified
+// like any other.
+// When the Mac wakes from sleep, the wall clock has moved on while the monotonic clock stood still.
+// The daemon then waits `CRONUP_WAKE_DELAY` seconds for the network to come back and catches up on the
+// runs that were due while it slept, once per updater. Its own messages go to `cronup.daemon.log`.
+// With `CRONUP_CONFIG` naming a settings file of `NAME=value` lines, the daemon loads it on start and
+// again whenever it changes, checked every minute, so settings and schedules can be edited without a
+// restart. The daemon reads its schedules and wake delay from them and passes them to the runs it
+// starts in their environment; its own environment is never changed, since the status page's thread
+// reads it. A file that can't be read or holds an invalid schedule is
+// logged and the previous settings are kept; otherwise every added, removed, or changed schedule is logged.
+// With `CRONUP_STATUS_PORT` set, the daemon serves the status page (see status.rs) with the next run of
+// every updater, the progress of the run in progress, and the results of the last one.
+
+// The `chrono` types used to step through the scheduled minutes.
+use chrono::{Local, NaiveDateTime, TimeDelta, Timelike};
+
+// Import various modules from the Rust standard library.
+use std::{
+    collections::HashMap,
+    env,
+    error::Error,
+    fs,
+    process::Command,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+// The schedules and settings file, the daemon's log, the status page, and the names of the updaters.
+use macpaw_tasks::{config, logging, status, updater_names};
+
+// How much more the wall clock must have advanced than the monotonic clock to count as a wake from
+// sleep, so small clock adjustments don't.
+const WAKE_THRESHOLD: Duration = Duration::from_secs(120);
+
+// An updater's schedule, together with the expression it was parsed from.
+struct Scheduled {
+    name: &'static str,
+    expression: String,
+    schedule: config::Schedule,
+}
+
+// The settings loaded from `CRONUP_CONFIG`.
+struct SettingsFile {
+    // The path of the file, if `CRONUP_CONFIG` names one.
+    path: Option<String>,
+    // The modification time of the file when it was last read, if it could be read.
+    modified: Option<SystemTime>,
+    // The settings loaded from the file, which override the daemon's environment.
+    loaded: HashMap<String, String>,
+}
+
+// Function to run the daemon. It only returns if the daemon's log can't be written.
+pub fn run(log_home: &str) -> Result<(), Box<dyn Error>> {
+    let mut settings = SettingsFile {
+        path: env::var("CRONUP_CONFIG")
+            .ok()
+            .filter(|path| !path.trim().is_empty())
+            .map(|path| config::expand_path(path.trim())),
+        modified: None,
+        loaded: HashMap::new(),
+    };
+    reload(log_home, &mut settings)?;
+
+    // Updaters with an invalid schedule are left out until it's fixed.
+    let (mut schedules, invalid) = read_schedules(&settings.loaded);
+    for message in invalid {
+        log(log_home, &format!("WARNING: {}", message))?;
+    }
+    log(log_home, "Daemon started.")?;
+    let status = status::Status::start_daemon(log_home);
+    status.set_next_runs(next_runs(&schedules, Local::now().naive_local()));
+
+    let mut last = Local::now().naive_local();
+    let mut last_instant = Instant::now();
+    loop {
+        // Wake up at the start of every minute.
+        let second = u64::from(Local::now().second().min(59));
+        thread::sleep(Duration::from_secs(60 - second));
+        let now = Local::now().naive_local();
+        let now_instant = Instant::now();
+
+        // Neither clock advances during sleep on macOS, only the wall clock does.
+        let slept = (now - last).to_std().unwrap_or_default();
+        let awake = now_instant - last_instant;
+        if slept > awake + WAKE_THRESHOLD {
+            log(
+                log_home,
+                &format!(
+                    "Woke from sleep after {} minutes.",
+                    (slept - awake).as_secs() / 60
+                ),
+            )?;
+            thread::sleep(wake_delay(&settings.loaded));
+        }
+
+        // Pick up an edited settings file before deciding what's due.
+        if let Some(new_schedules) = reload(log_home, &mut settings)? {
+            report_changes(log_home, &schedules, &new_schedules)?;
+            schedules = new_schedules;
+        }
+
+        // Start a run of every updater that was due since the last check. A clock that jumped
+        // backwards leaves nothing due, so no run repeats.
+        let due = due_updaters(&schedules, last, now);
+        last = now;
+        last_instant = now_instant;
+        status.set_next_runs(next_runs(&schedules, now));
+        if !due.is_empty() {
+            status.set_running(true);
+            start_run(log_home, &due, &settings.loaded)?;
+            status.set_running(false);
+        }
+    }
+}
+
+// Function to get how long to wait after a wake from sleep, from the loaded settings or the environment.
+fn wake_delay(loaded: &HashMap<String, String>) -> Duration {
+    loaded
+        .get("CRONUP_WAKE_DELAY")
+        .and_then(|value| value.trim().parse().ok())
+        .map_or_else(config::get_wake_delay, Duration::from_secs)
+}
+
+// Function to read the schedule of every updater as the runs see it, in registry order: a loaded
+// setting replaces the environment variable of the same name. Returns the updaters that are scheduled,
+// and a message for every invalid schedule.
+fn read_schedules(loaded: &HashMap<String, String>) -> (Vec<Scheduled>, Vec<String>) {
+    let mut schedules = Vec::new();
+    let mut invalid = Vec::new();
+    for name in updater_names() {
+        let expression = [
+            format!("CRONUP_SCHEDULE_{}", name.to_uppercase()),
+            String::from("CRONUP_SCHEDULE"),
+        ]
+        .iter()
+        .find_map(|key| loaded.get(key).cloned().or_else(|| env::var(key).ok()))
+        .map(|value| value.trim().to_string())
+        .unwrap_or_else(|| config::get_schedule(name));
+        if expression == "off" {
+            continue;
+        }
+        match config::Schedule::parse(&expression) {
+            Some(schedule) => schedules.push(Scheduled {
+                name,
+                expression,
+                schedule,
+            }),
+            None => invalid.push(format!(
+                "{} has an invalid schedule '{}'.",
+                name, expression
+            )),
+        }
+    }
+    (schedules, invalid)
+}
+
+// Function to reload the settings file if it changed since it was last read. Returns the schedules
+// of the new settings, or `None` if the file didn't change or its settings can't be read or hold an
+// invalid schedule, which is logged and leaves the previous settings in place. Without a settings
+// file, nothing is loaded.
+fn reload(
+    log_home: &str,
+    settings: &mut SettingsFile,
+) -> Result<Option<Vec<Scheduled>>, Box<dyn Error>> {
+    let Some(path) = settings.path.clone() else {
+        return Ok(None);
+    };
+    let modified = fs::metadata(&path)
+        .and_then(|metadata| metadata.modified())
+        .ok();
+    if modified == settings.modified {
+        return Ok(None);
+    }
+    settings.modified = modified;
+
+    let loaded = match config::read_settings_file(&path) {
+        Ok(loaded) => loaded,
+        Err(error) => {
+            let message = format!(
+                "WARNING: the settings in {} can't be loaded ({}); keeping the previous settings.",
+                path, error
+            );
+            log(log_home, &message)?;
+            return Ok(None);
+        }
+    };
+
+    // Only take the new settings if their schedules are valid.
+    let count = loaded.len();
+    let loaded: HashMap<String, String> = loaded.into_iter().collect();
+    let (new_schedules, invalid) = read_schedules(&loaded);
+    if !invalid.is_empty() {
+        let message = format!(
+            "WARNING: the settings in {} are invalid: {} Keeping the previous settings.",
+            path,
+            invalid.join(" ")
+        );
+        log(log_home, &message)?;
+        return Ok(None);
+    }
+    settings.loaded = loaded;
+    log(
+        log_home,
+        &format!("Loaded {} settings from {}.", count, path),
+    )?;
+    Ok(Some(new_schedules))
+}
+
+// Function to log every updater whose schedule was added, removed, or changed by a reload.
+fn report_changes(
+    log_home: &str,
+    schedules: &[Scheduled],
+    new_schedules: &[Scheduled],
+) -> Result<(), Box<dyn Error>> {
+    for name in updater_names() {
+        let find = |schedules: &[Scheduled]| {
+            schedules
+                .iter()
+                .find(|scheduled| scheduled.name == name)
+                .map(|scheduled| scheduled.expression.clone())
+        };
+        let message = match (find(schedules), find(new_schedules)) {
+            (None, Some(new)) => format!("Scheduled {} at '{}'.", name, new),
+            (Some(_), None) => format!("Unscheduled {}.", name),
+            (Some(old), Some(new)) if old != new => {
+                format!("Rescheduled {} from '{}' to '{}'.", name, old, new)
+            }
+            _ => continue,
+        };
+        log(log_home, &message)?;
+    }
+    Ok(())
+}
+
+// Function to list the updaters whose schedule was due at a minute after `from`, up to and including
+// `to`, in registry order and without duplicates.
+fn due_updaters(
+    schedules: &[Scheduled],
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+) -> Vec<&'static str> {
+    let mut due = Vec::new();
+    let Some(mut minute) = from.with_second(0).and_then(|time| time.with_nanosecond(0)) else {
+        return due;
+    };
+    minute += TimeDelta::minutes(1);
+    while minute <= to && due.len() < schedules.len() {
+        for scheduled in schedules {
+            if !due.contains(&scheduled.name) && scheduled.schedule.matches(minute) {
+                due.push(scheduled.name);
+            }
+        }
+        minute += TimeDelta::minutes(1);
+    }
+    due.sort_by_key(|name| {
+        schedules
+            .iter()
+            .position(|scheduled| scheduled.name == *name)
+    });
+    due
+}
+
+// Function to list the next scheduled run of every updater after `now`, as local time, for the status page.
+fn next_runs(schedules: &[Scheduled], now: NaiveDateTime) -> Vec<(String, String)> {
+    schedules
+        .iter()
+        .map(|scheduled| {
+            let at = scheduled.schedule.next_after(now).map_or_else(
+                || String::from("never"),
+                |at| at.format("%Y-%m-%d %H:%M").to_string(),
+            );
+            (scheduled.name.to_string(), at)
+        })
+        .collect()
+}
+
+// Function to run `cronup --only <names>` and log how it ended. A run that can't be started is logged
+// and doesn't stop the daemon. The run writes its progress for the daemon's status page instead of
+// serving a page of its own, and the previous run's progress is removed first. The loaded settings are
+// added to its environment.
+fn start_run(
+    log_home: &str,
+    names: &[&str],
+    loaded: &HashMap<String, String>,
+) -> Result<(), Box<dyn Error>> {
+    let list = names.join(", ");
+    log(log_home, &format!("Starting a scheduled run of {}.", list))?;
+    let progress = status::progress_path(log_home);
+    let _ = fs::remove_file(&progress);
+    let status = env::current_exe().and_then(|exe| {
+        Command::new(exe)
+            .arg("--only")
+            .arg(names.join(","))
+            .envs(loaded)
+            .env("CRONUP_STATUS_FILE", &progress)
+            .env_remove("CRONUP_STATUS_PORT")
+            .status()
+    });
+    let message = match status {
+        Ok(status) => match status.code() {
+            Some(code) => format!("The run of {} exited with {}.", list, code),
+            None => format!("The run of {} was killed by a signal.", list),
+        },
+        Err(error) => format!("WARNING: the run of {} failed to start: {}", list, error),
+    };
+    log(log_home, &message)
+}
+
+// Function to write a line to `cronup.daemon.log`. The log is opened for every line, so a rotated
+// log moves on to the next day's file.
+fn log(log_home: &str, message: &str) -> Result<(), Box<dyn Error>> {
+    let mut log_file = logging::open_log(log_home, "daemon")?;
+    logging::log_line(&mut log_file, message)
+}
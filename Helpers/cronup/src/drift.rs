@@ -0,0 +1,229 @@
+// Drift detection of the system tools: once every `CRONUP_DRIFT_DAYS` (default 7) days, a baseline of
+// the versions of the tools in `CRONUP_DRIFT_TOOLS` and the hashes of the files in `CRONUP_DRIFT_FILES`
+// is recorded in `cronup.baseline` in `LOG_HOME`. Its first line holds the time it was taken, in the
+// history's format, followed by a tab-separated tool name or file path and its version or hash per line.
+//
+// Every run snapshots the tools and files before and after its updaters, and takes the changes between
+// the two into the baseline, since cronup made them. Whatever still differs from the baseline when the
+// next one is due changed outside cronup (e.g. something else upgraded node), and is reported as drift
+// in `cronup.drift.log` and the run's results before the baseline is recorded anew.
+// With `CRONUP_MAX_PARALLEL` above 1, changes made elsewhere while the updaters run count as cronup's.
+
+// The `Local` and `NaiveDateTime` structs from the `chrono` crate are used for the age of the baseline.
+use chrono::{Local, NaiveDateTime};
+
+// Import various modules from the Rust standard library.
+use std::{error::Error, fs, time::Duration};
+
+// Shared helpers for configuration, the history's time format, logging, and running commands.
+use crate::{config, history, logging, process};
+
+// How long a tool may take to print its version.
+const VERSION_TIMEOUT: Duration = Duration::from_secs(30);
+
+// The value recorded for a tool or file that doesn't exist.
+const MISSING: &str = "missing";
+
+// The version of every tracked tool and the hash of every tracked file, in configuration order.
+pub type Snapshot = Vec<(String, String)>;
+
+// Function to take a snapshot of the tracked tools and files.
+// A bare tool name stands for `<tool> --version`; an entry with arguments is run as given and recorded
+// under its first word, e.g. "go version" as "go". A tool's version is the first line it prints.
+pub fn snapshot() -> Snapshot {
+    let mut snapshot = Vec::new();
+    for entry in config::get_drift_tools() {
+        let (name, command) = match entry.split_once(char::is_whitespace) {
+            Some((name, _)) => (name.to_string(), entry.clone()),
+            None => (entry.clone(), format!("{} --version", entry)),
+        };
+        let version = process::run_with_timeout(process::shell(&command), VERSION_TIMEOUT, None)
+            .ok()
+            .filter(|output| output.succeeded())
+            .and_then(|output| {
+                [&output.stdout, &output.stderr].iter().find_map(|stream| {
+                    String::from_utf8_lossy(stream)
+                        .lines()
+                        .map(str::trim)
+                        .find(|line| !line.is_empty())
+                        .map(str::to_string)
+                })
+            })
+            .unwrap_or_else(|| String::from(MISSING));
+        snapshot.push((name, version));
+    }
+    for path in config::get_drift_files() {
+        let hash = fs::read(config::expand_path(&path))
+            .map_or_else(|_| String::from(MISSING), |contents| hash(&contents));
+        snapshot.push((path, hash));
+    }
+    snapshot
+}
+
+// Function to hash a file's contents with 64-bit FNV-1a, which is stable across Rust versions.
+fn hash(contents: &[u8]) -> String {
+    let hash = contents
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    format!("{:016x}", hash)
+}
+
+// Function to get the path of the baseline file.
+fn baseline_path(log_home: &str) -> String {
+    format!("{}/cronup.baseline", log_home)
+}
+
+// Function to read the baseline and the time it was taken, if there is one.
+fn read_baseline(log_home: &str) -> Option<(String, Snapshot)> {
+    let contents = fs::read_to_string(baseline_path(log_home)).ok()?;
+    let mut lines = contents.lines();
+    let taken = lines.next()?.trim().to_string();
+    let entries = lines
+        .filter_map(|line| line.split_once('\t'))
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    Some((taken, entries))
+}
+
+// Function to write the baseline, taken at `taken`.
+fn write_baseline(log_home: &str, taken: &str, entries: &Snapshot) -> Result<(), Box<dyn Error>> {
+    let mut contents = format!("{}\n", taken);
+    for (name, value) in entries {
+        contents.push_str(&format!("{}\t{}\n", name, value));
+    }
+    fs::write(baseline_path(log_home), contents)?;
+    Ok(())
+}
+
+// Function to list what differs between a baseline and a snapshot, as "name: old -> new".
+// Tools and files that only one of them tracks are left out, so changing the settings isn't drift.
+fn differences(baseline: &Snapshot, current: &Snapshot) -> Vec<String> {
+    current
+        .iter()
+        .filter_map(|(name, value)| {
+            let (_, old) = baseline.iter().find(|(other, _)| other == name)?;
+            (old != value).then(|| format!("{}: {} -> {}", name, old, value))
+        })
+        .collect()
+}
+
+// Function to compare the snapshot taken before the updaters with the baseline when the baseline is
+// due, logging the drift and recording the snapshot as the new baseline. A missing baseline is
+// recorded right away. `may_rebaseline` is false while the clock is skewed, since the baseline's age
+// can't be told then.
+// Returns the drift if the baseline was due, so it can be reported with the run's results.
+pub fn check(
+    log_home: &str,
+    current: &Snapshot,
+    may_rebaseline: bool,
+    days: u64,
+) -> Result<Option<Vec<String>>, Box<dyn Error>> {
+    let now = history::timestamp();
+    let Some((taken, baseline)) = read_baseline(log_home) else {
+        write_baseline(log_home, &now, current)?;
+        let mut log_file = logging::open_log(log_home, "drift")?;
+        logging::log_line(
+            &mut log_file,
+            &format!("Baseline recorded for {} tools and files.", current.len()),
+        )?;
+        return Ok(None);
+    };
+
+    // A baseline whose time can't be read is taken anew, like an expired one.
+    let due = NaiveDateTime::parse_from_str(&taken, "%Y-%m-%d %H:%M:%S").map_or(true, |taken| {
+        Local::now().naive_local() - taken >= chrono::Duration::days(days as i64)
+    });
+    if !may_rebaseline || !due {
+        return Ok(None);
+    }
+
+    let drift = differences(&baseline, current);
+    let mut log_file = logging::open_log(log_home, "drift")?;
+    if drift.is_empty() {
+        logging::log_line(
+            &mut log_file,
+            &format!("No drift since the baseline of {}.", taken),
+        )?;
+    } else {
+        logging::log_line(
+            &mut log_file,
+            &format!("Changed outside cronup since the baseline of {}:", taken),
+        )?;
+        for line in &drift {
+            logging::log_line(&mut log_file, &format!("  {}", line))?;
+        }
+    }
+    write_baseline(log_home, &now, current)?;
+    Ok(Some(drift))
+}
+
+// Function to take the changes the updaters made into the baseline, given the snapshot taken before
+// them. An entry that had already drifted before the run keeps its baseline value, so that drift is
+// still reported.
+pub fn absorb(log_home: &str, before: &Snapshot) -> Result<(), Box<dyn Error>> {
+    let Some((taken, mut baseline)) = read_baseline(log_home) else {
+        return Ok(());
+    };
+    let after = snapshot();
+    let mut changed = Vec::new();
+    for (name, value) in &after {
+        let Some((_, old)) = before.iter().find(|(other, _)| other == name) else {
+            continue;
+        };
+        let Some(entry) = baseline.iter_mut().find(|(other, _)| other == name) else {
+            continue;
+        };
+        if old != value && entry.1 == *old {
+            changed.push(format!("{}: {} -> {}", name, old, value));
+            entry.1 = value.clone();
+        }
+    }
+    if changed.is_empty() {
+        return Ok(());
+    }
+
+    let mut log_file = logging::open_log(log_home, "drift")?;
+    logging::log_line(&mut log_file, "Changed by cronup, taken into the baseline:")?;
+    for line in &changed {
+        logging::log_line(&mut log_file, &format!("  {}", line))?;
+    }
+    write_baseline(log_home, &taken, &baseline)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(pairs: &[(&str, &str)]) -> Snapshot {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn differences_list_changed_entries_tracked_by_both() {
+        let baseline = entries(&[
+            ("node", "v20.1.0"),
+            ("git", "git version 2.44.0"),
+            ("old", "1"),
+        ]);
+        let current = entries(&[
+            ("node", "v20.2.0"),
+            ("git", "git version 2.44.0"),
+            ("~/.zshrc", "0123456789abcdef"),
+        ]);
+        assert_eq!(
+            differences(&baseline, &current),
+            ["node: v20.1.0 -> v20.2.0"]
+        );
+    }
+
+    #[test]
+    fn hashes_are_stable() {
+        assert_eq!(hash(b""), "cbf29ce484222325");
+        assert_eq!(hash(b"a"), "af63dc4c8601ec8c");
+    }
+}
@@ -0,0 +1,78 @@
+// The run report emailed when `CRONUP_EMAIL` is set to an address, so a headless Mac tells how its
+// updates went. It is handed to the local mail transfer agent through `sendmail`, which on macOS is
+// Postfix; relaying through an SMTP server is configured there.
+// The report lists the outcome of every check and updater and, below each updater, its summary of what
+// changed.
+
+// Import various modules from the Rust standard library.
+use std::{
+    env,
+    io::Write,
+    process::{Command, Stdio},
+};
+
+// The executables that deliver the mail and name the machine.
+const SENDMAIL: &str = "/usr/sbin/sendmail";
+const HOSTNAME: &str = "/bin/hostname";
+
+// Function to email the run report if `CRONUP_EMAIL` is set.
+// Each result carries `None` on success or the signature of the failure, and `summaries` the summary
+// lines of the updaters that have any.
+pub fn send_report(results: &[(&str, Option<String>)], summaries: &[(&str, Vec<String>)]) {
+    let Some(address) = env::var("CRONUP_EMAIL")
+        .ok()
+        .map(|address| address.trim().to_string())
+        .filter(|address| !address.is_empty())
+    else {
+        return;
+    };
+
+    let failed = results
+        .iter()
+        .filter(|(_, failure)| failure.is_some())
+        .count();
+    let host = Command::new(HOSTNAME)
+        .arg("-s")
+        .output()
+        .ok()
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .filter(|host| !host.is_empty())
+        .unwrap_or_else(|| String::from("this Mac"));
+    let subject = format!(
+        "cronup on {}: {} ok, {} failed",
+        host,
+        results.len() - failed,
+        failed
+    );
+
+    let mut body = String::new();
+    for (name, failure) in results {
+        match failure {
+            None => body.push_str(&format!("{}: ok\n", name)),
+            Some(signature) => body.push_str(&format!("{}: failed ({})\n", name, signature)),
+        }
+        let lines = summaries
+            .iter()
+            .find(|(summary_name, _)| summary_name == name)
+            .map_or(&[][..], |(_, lines)| &lines[..]);
+        for line in lines {
+            body.push_str(&format!("    {}\n", line));
+        }
+    }
+
+    let message = format!("To: {}\nSubject: {}\n\n{}", address, subject, body);
+    let Ok(mut child) = Command::new(SENDMAIL)
+        .arg("-t")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+    else {
+        return;
+    };
+    // Closing standard input after the message lets sendmail see its end.
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(message.as_bytes());
+    }
+    let _ = child.wait();
+}
@@ -0,0 +1,1237 @@
+// The task engine behind cronup: it takes the run lock, checks the clock, runs the check-only tasks,
+// checks the network, installs missing package managers if asked to, and runs every installed updater
+// that isn't skipped or deferred, logging everything to `LOG_HOME`.
+// A run returns a typed `RunResult` with a `TaskResult` per task, and reports its progress to an
+// `Observer` as it goes. Notifications, the emailed report, metrics, and the status page are left to
+// the caller, so an embedding program can present the results its own way.
+//
+// Every task's log section and `cronup.run.log` end with a summary line in a stable format for
+// healthcheck parsers and other log watchers, e.g.
+//   RESULT task=brew status=ok duration=95s changed=12
+// Updaters, installers and checks report "ok", "failed" or "skipped" as their status, and "changed" is
+// the number of updated packages, or "unknown" if the updater can't tell. The run's line has the task
+// "run", the status "ok", "some_failed", "all_failed", "offline" or "already_running" matching the
+// exit code, the total of the known changes, and the number of failed tasks as "failed".
+// Keys are only ever added at the end of the line, and values never contain spaces.
+
+// Import various modules from the Rust standard library.
+use std::{
+    // `Reverse` orders ready updaters of equal priority by their registry position.
+    cmp::Reverse,
+    // The `Error` trait is used for error handling.
+    error::Error,
+    // A panicking updater thread is caught, so its result is still reported.
+    panic::{self, AssertUnwindSafe},
+    // The `Command` struct is used for running external commands.
+    process::Command,
+    // The channel on which parallel updaters report their results.
+    sync::mpsc,
+    // The `thread` module is used for sleeping between retries and running updaters in parallel.
+    thread,
+    // The `Duration` and `Instant` structs are used for specifying and measuring time intervals.
+    time::{Duration, Instant},
+};
+
+// The modules the engine drives.
+use crate::{
+    bootstrap, checks, clock, config,
+    connectivity::{self, Connectivity},
+    drift, history, lock, logging, metered, power, process, progress, sudo,
+    updaters::{self, Updater},
+};
+
+// The exit codes of a run whose updaters didn't all succeed.
+const EXIT_SOME_FAILED: i32 = 2;
+const EXIT_ALL_FAILED: i32 = 3;
+const EXIT_OFFLINE: i32 = 4;
+
+// The delay before the first retry of a failed updater, doubled after every further attempt.
+const DEFAULT_RETRY_DELAY_SECS: u64 = 30;
+
+// How many of an updater's most recent runs its median output size is taken from, and how many
+// of them must exist before a spike is flagged.
+const VOLUME_WINDOW: usize = 10;
+const VOLUME_MIN_RUNS: usize = 3;
+
+// Output smaller than this is never flagged, so a quiet updater's occasional real update isn't.
+const VOLUME_MIN_BYTES: u64 = 64 * 1024;
+
+// How many of an updater's most recent successful runs its average duration is taken from, and how
+// many of them must exist before a slow run is flagged.
+const DURATION_WINDOW: usize = 10;
+const DURATION_MIN_RUNS: usize = 3;
+
+// Runs shorter than this are never flagged, so a quick updater's jitter isn't.
+const DURATION_MIN_SECS: u64 = 60;
+
+// How long a hook may run when `CRONUP_HOOK_TIMEOUT` is not set.
+const DEFAULT_HOOK_TIMEOUT_SECS: u64 = 300;
+
+// How many days since its last successful run an updater counts as stale while offline, when
+// `CRONUP_STALE_DAYS` is not set.
+const DEFAULT_STALE_DAYS: u64 = 7;
+
+// How many of a failed step's last output lines are copied to `cronup.errors.log`.
+const ERROR_EXCERPT_LINES: usize = 20;
+
+// How long a single smoke test may run.
+const SMOKE_TEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+// The kind of a task, which tells how its result counts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TaskKind {
+    // The clock check, which only fails when the clock is skewed.
+    Clock,
+    // A check-only task, which fails when its threshold is violated.
+    Check,
+    // The network check, which fails when the network is unusable and ends the run.
+    Network,
+    // A package manager installed by `bootstrap`.
+    Installer,
+    // An updater.
+    Updater,
+}
+
+// The result of a single task.
+#[derive(Clone, Debug)]
+pub struct TaskResult {
+    // The name of the task, e.g. "brew" or "disk".
+    pub name: &'static str,
+    // What kind of task it was.
+    pub kind: TaskKind,
+    // `None` if the task succeeded, otherwise the signature of its failure.
+    pub failure: Option<String>,
+    // Whether its output was a log volume anomaly.
+    pub anomaly: bool,
+    // The task's summary of what changed, e.g. "Upgraded 2 packages: ...", or for the network check
+    // how long ago every tool last updated.
+    pub summary: Vec<String>,
+    // How long the task took.
+    pub duration: Duration,
+    // The number of packages it updated, if the updater can tell.
+    pub updated: Option<usize>,
+}
+
+impl TaskResult {
+    // Function to create the result of a task that only passes or fails.
+    fn outcome(name: &'static str, kind: TaskKind, failure: Option<String>) -> Self {
+        TaskResult {
+            name,
+            kind,
+            failure,
+            anomaly: false,
+            summary: Vec::new(),
+            duration: Duration::ZERO,
+            updated: None,
+        }
+    }
+
+    // Function to check whether the task succeeded.
+    pub fn succeeded(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+// How a run ended.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RunStatus {
+    // Every task that was due ran.
+    Completed,
+    // The network was offline or behind a captive portal, so no updater ran.
+    Offline,
+    // Another run holds the lock, with its pid if known, so this one didn't start.
+    AlreadyRunning(Option<u32>),
+}
+
+// The result of a run.
+#[derive(Clone, Debug)]
+pub struct RunResult {
+    // How the run ended.
+    pub status: RunStatus,
+    // The result of every task that ran, in order.
+    pub tasks: Vec<TaskResult>,
+}
+
+impl RunResult {
+    // Function to get the exit code that tells launchd or cron monitoring how the run went:
+    // 0 if every installer and updater succeeded, 2 if some failed, 3 if all of them failed, and 4 if
+    // the network was unusable.
+    pub fn exit_code(&self) -> i32 {
+        if self.status == RunStatus::Offline {
+            return EXIT_OFFLINE;
+        }
+        let (run, failed) = self
+            .tasks
+            .iter()
+            .filter(|task| matches!(task.kind, TaskKind::Installer | TaskKind::Updater))
+            .fold((0, 0), |(run, failed), task| {
+                (run + 1, failed + usize::from(!task.succeeded()))
+            });
+        match failed {
+            0 => 0,
+            failed if failed == run => EXIT_ALL_FAILED,
+            _ => EXIT_SOME_FAILED,
+        }
+    }
+}
+
+// Callbacks that follow a run as it goes, e.g. to show progress in a menu bar app.
+// Every method does nothing by default.
+pub trait Observer {
+    // Called when an updater starts, with its position among the updaters that run.
+    fn updater_started(&mut self, _name: &str, _index: usize, _total: usize) {}
+
+    // Called when an updater is skipped or deferred, with the reason that is logged.
+    fn updater_skipped(&mut self, _name: &str, _reason: &str) {}
+
+    // Called when a task finished, including the clock and network checks.
+    fn task_finished(&mut self, _result: &TaskResult) {}
+}
+
+// An observer that ignores the run.
+impl Observer for () {}
+
+// The options of a run.
+#[derive(Clone, Debug, Default)]
+pub struct RunOptions {
+    // Whether to install the missing package managers before the updates.
+    pub bootstrap: bool,
+    // The names of the updaters to consider, or all of them if empty.
+    pub only: Vec<String>,
+    // Whether to resume an interrupted run of the same day, skipping the updaters it completed.
+    pub resume: bool,
+}
+
+// Function to list the names of every registered updater, in the order they run.
+pub fn updater_names() -> Vec<&'static str> {
+    updaters::registry()
+        .iter()
+        .map(|updater| updater.name())
+        .collect()
+}
+
+// Function to run every task, logging to `log_home`, and report the progress to `observer`.
+// Errors are those of cronup itself, e.g. an unwritable log directory.
+pub fn run(
+    log_home: &str,
+    options: RunOptions,
+    observer: &mut dyn Observer,
+) -> Result<RunResult, Box<dyn Error>> {
+    let start = Instant::now();
+
+    // Take the run lock, which is held until the run ends, or leave it to the run holding it.
+    let _lock = match lock::acquire(log_home)? {
+        lock::Lock::Acquired(file) => file,
+        lock::Lock::Held(pid) => {
+            log_already_running(log_home, pid)?;
+            let result = RunResult {
+                status: RunStatus::AlreadyRunning(pid),
+                tasks: Vec::new(),
+            };
+            log_run_result(log_home, &result, start.elapsed())?;
+            return Ok(result);
+        }
+    };
+
+    // Forget what an earlier run in this process found, since an embedder may run more than once.
+    logging::reset();
+    power::reset();
+    metered::reset();
+
+    let mut tasks = Vec::new();
+    let mut finish = |task: TaskResult, observer: &mut dyn Observer| {
+        observer.task_finished(&task);
+        tasks.push(task);
+    };
+
+    // Check the clock before anything is logged, and note the outcome in the run header.
+    let skew = clock::check(log_home, config::get_clock_tolerance());
+    log_run_header(log_home, skew)?;
+    if skew.is_some() {
+        let failure = Some(String::from("skewed"));
+        finish(
+            TaskResult::outcome("clock", TaskKind::Clock, failure),
+            observer,
+        );
+    }
+
+    // Run the check-only tasks first, since they don't need the network.
+    for check in checks::CHECKS {
+        let check_start = Instant::now();
+        let passed = checks::run_check(check, log_home)?;
+        let mut log_file = logging::open_log(log_home, "checks")?;
+        logging::log_line(
+            &mut log_file,
+            &result_line(
+                check.name,
+                if passed { "ok" } else { "failed" },
+                check_start.elapsed(),
+                Some(0),
+            ),
+        )?;
+        let failure = (!passed).then(|| String::from("violated"));
+        finish(
+            TaskResult::outcome(check.name, TaskKind::Check, failure),
+            observer,
+        );
+    }
+
+    // Snapshot the tracked tools and files before any updater runs, so the changes the updaters make
+    // can be told from the others, and report the drift when the weekly baseline is due.
+    let before = match config::get_drift_days() {
+        Some(days) => {
+            let before = drift::snapshot();
+            if let Some(changes) = drift::check(log_home, &before, skew.is_none(), days)? {
+                let failure = (!changes.is_empty()).then(|| {
+                    let names: Vec<&str> = changes
+                        .iter()
+                        .filter_map(|change| Some(change.split_once(": ")?.0))
+                        .collect();
+                    format!("drifted: {}", names.join(", "))
+                });
+                let mut task = TaskResult::outcome("drift", TaskKind::Check, failure);
+                task.summary = changes;
+                finish(task, observer);
+            }
+            Some(before)
+        }
+        None => None,
+    };
+
+    // Check whether the network is usable; downloads would fail both offline and behind a captive portal.
+    let unusable = match connectivity::check() {
+        Connectivity::Online => None,
+        Connectivity::Portal(detail) => Some((
+            format!("Captive portal detected ({}) - updates aborted.", detail),
+            "captive portal",
+        )),
+        Connectivity::Offline => {
+            Some((String::from("System offline - updates aborted."), "offline"))
+        }
+    };
+    if let Some((message, status)) = unusable {
+        // If the network is not usable, log why and how stale every tool is, and end the run.
+        let staleness = log_offline(log_home, &message)?;
+        let stale: Vec<&str> = staleness
+            .iter()
+            .filter(|(_, stale)| *stale)
+            .map(|(line, _)| line.as_str())
+            .collect();
+        // A tool past its staleness limit changes the signature every day, so it's notified daily.
+        let signature = if stale.is_empty() {
+            String::from(status)
+        } else {
+            format!("{}; {}", status, stale.join(", "))
+        };
+        let mut network = TaskResult::outcome("network", TaskKind::Network, Some(signature));
+        network.summary = staleness.into_iter().map(|(line, _)| line).collect();
+        finish(network, observer);
+        let result = RunResult {
+            status: RunStatus::Offline,
+            tasks,
+        };
+        log_run_result(log_home, &result, start.elapsed())?;
+        return Ok(result);
+    }
+
+    // Install the missing package managers first.
+    if options.bootstrap {
+        for (name, failure) in bootstrap::run(log_home)? {
+            finish(
+                TaskResult::outcome(name, TaskKind::Installer, failure),
+                observer,
+            );
+        }
+    }
+
+    // The history tells when each updater last succeeded, for skipping those that ran recently.
+    let runs = history::load(log_home)?;
+
+    // Note a run that was interrupted, and resume it if asked to and it started today.
+    let today = history::timestamp();
+    let mut previous = progress::interrupted(log_home);
+    if let Some(interrupted) = &previous {
+        let resumed = options.resume && interrupted.started.get(..10) == today.get(..10);
+        log_interrupted(log_home, interrupted, resumed)?;
+        if !resumed {
+            previous = None;
+        }
+    }
+    let (started, completed) = match previous {
+        Some(interrupted) => (interrupted.started, interrupted.completed),
+        None => (today, Vec::new()),
+    };
+    progress::begin(log_home, &started, &completed)?;
+
+    // Run every installed updater, or only those asked for, up to `CRONUP_MAX_PARALLEL` at a time.
+    // Each one runs on its own thread and reports its result back, so the observer is only called
+    // from this one.
+    let registry: Vec<_> = updaters::registry()
+        .into_iter()
+        .filter(|updater| {
+            options.only.is_empty() || options.only.iter().any(|name| name == updater.name())
+        })
+        .collect();
+
+    // Skip the updaters that don't run first, so the others are counted on their own.
+    let mut pending: Vec<&dyn Updater> = Vec::new();
+    for updater in registry.iter().map(|updater| updater.as_ref()) {
+        let reason = if completed.iter().any(|name| name == updater.name()) {
+            Some(String::from(
+                "Already updated by the interrupted run - skipped.",
+            ))
+        } else {
+            deferral(updater, &runs)
+        };
+        let Some(reason) = reason else {
+            pending.push(updater);
+            continue;
+        };
+        // Note the skip in the updater's log so a missing or deferred tool doesn't go unnoticed.
+        let mut log_file = logging::open_log(log_home, updater.name())?;
+        logging::log_line(&mut log_file, &reason)?;
+        logging::log_line(
+            &mut log_file,
+            &result_line(updater.name(), "skipped", Duration::ZERO, Some(0)),
+        )?;
+        observer.updater_skipped(updater.name(), &reason);
+    }
+    let total = pending.len();
+    let max_parallel = config::get_max_parallel();
+    thread::scope(|scope| -> Result<(), Box<dyn Error>> {
+        let (sender, receiver) = mpsc::channel();
+        let mut running: Vec<&str> = Vec::new();
+        let mut index = 0;
+        while !pending.is_empty() || !running.is_empty() {
+            // Start the next updater that is ready while a slot is free.
+            let next = (running.len() < max_parallel)
+                .then(|| next_ready(&pending, &running))
+                .flatten();
+            let Some(position) = next else {
+                // Wait for a running updater to finish.
+                let (name, result): (&str, TaskResult) = receiver.recv()?;
+                running.retain(|other| *other != name);
+                if result.succeeded() {
+                    progress::complete(log_home, name)?;
+                }
+                finish(result, observer);
+                continue;
+            };
+
+            let updater = pending.remove(position);
+            observer.updater_started(updater.name(), index, total);
+            index += 1;
+            running.push(updater.name());
+            let sender = sender.clone();
+            scope.spawn(move || {
+                let start = Instant::now();
+                let result = catch_panic(updater.name(), || {
+                    run_updater(updater, log_home).map_err(|error| error.to_string())
+                })
+                .unwrap_or_else(|error| {
+                    updater_error(updater.name(), &error, start.elapsed(), log_home)
+                });
+                let _ = sender.send((updater.name(), result));
+            });
+        }
+        Ok(())
+    })?;
+    progress::finish(log_home)?;
+
+    // Take the changes the updaters made into the drift baseline.
+    if let Some(before) = &before {
+        drift::absorb(log_home, before)?;
+    }
+
+    let result = RunResult {
+        status: RunStatus::Completed,
+        tasks,
+    };
+    log_run_result(log_home, &result, start.elapsed())?;
+    Ok(result)
+}
+
+// Function to format the stable summary line that ends a task's log section, e.g.
+// "RESULT task=brew status=ok duration=95s changed=12".
+pub(crate) fn result_line(
+    task: &str,
+    status: &str,
+    duration: Duration,
+    changed: Option<usize>,
+) -> String {
+    format!(
+        "RESULT task={} status={} duration={}s changed={}",
+        task,
+        status,
+        duration.as_secs(),
+        changed.map_or_else(|| String::from("unknown"), |changed| changed.to_string())
+    )
+}
+
+// Function to log the stable summary line of the whole run to `cronup.run.log`.
+fn log_run_result(
+    log_home: &str,
+    result: &RunResult,
+    duration: Duration,
+) -> Result<(), Box<dyn Error>> {
+    let status = match result.status {
+        RunStatus::Offline => "offline",
+        RunStatus::AlreadyRunning(_) => "already_running",
+        RunStatus::Completed => match result.exit_code() {
+            EXIT_ALL_FAILED => "all_failed",
+            EXIT_SOME_FAILED => "some_failed",
+            _ => "ok",
+        },
+    };
+    let changed = result.tasks.iter().filter_map(|task| task.updated).sum();
+    let failed = result.tasks.iter().filter(|task| !task.succeeded()).count();
+    let mut file = logging::open_log(log_home, "run")?;
+    logging::log_line(
+        &mut file,
+        &format!(
+            "{} failed={}",
+            result_line("run", status, duration, Some(changed)),
+            failed
+        ),
+    )
+}
+
+// Function to run an updater's task, turning a panic into an error, so its result is always sent and
+// the run doesn't wait forever for an updater thread that died.
+fn catch_panic<T>(name: &str, task: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+    panic::catch_unwind(AssertUnwindSafe(task)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        Err(format!("{} panicked: {}", name, message))
+    })
+}
+
+// Function to turn an error of cronup itself while running an updater, or its panic, into a failure of
+// that updater alone, so the run goes on with the others. The error is its failure signature, and is
+// logged with the result line as far as the updater's log can be written.
+fn updater_error(
+    name: &'static str,
+    error: &str,
+    duration: Duration,
+    log_home: &str,
+) -> TaskResult {
+    if let Ok(mut log_file) = logging::open_log(log_home, name) {
+        let _ = logging::log_line(&mut log_file, &format!("ERROR: {}", error));
+        let _ = logging::log_line(
+            &mut log_file,
+            &result_line(name, "failed", duration, Some(0)),
+        );
+    }
+    TaskResult {
+        duration,
+        ..TaskResult::outcome(name, TaskKind::Updater, Some(error.to_string()))
+    }
+}
+
+// Function to pick the pending updater to start next: of those whose dependencies have all finished,
+// the one with the highest `CRONUP_PRIORITY_<NAME>`, and of equal ones the first in registry order.
+// If nothing is ready and nothing runs, the rest wait on each other in a cycle and start in order.
+fn next_ready(pending: &[&dyn Updater], running: &[&str]) -> Option<usize> {
+    let unfinished = |dependency: &&str| {
+        running.contains(dependency) || pending.iter().any(|other| other.name() == *dependency)
+    };
+    (0..pending.len())
+        .filter(|&position| !pending[position].dependencies().iter().any(unfinished))
+        .max_by_key(|&position| {
+            let priority = config::get_setting("PRIORITY", pending[position].name(), 0);
+            (priority, Reverse(position))
+        })
+        .or_else(|| (running.is_empty() && !pending.is_empty()).then_some(0))
+}
+
+// Function to determine why an updater doesn't run now, if it doesn't: its executable is missing, it
+// succeeded within its minimum interval, or it is deferred by its quiet hours, battery power, or a
+// metered network. Returns the message logged for it.
+fn deferral(updater: &dyn Updater, runs: &[history::Run]) -> Option<String> {
+    let name = updater.name();
+    if !updater.detect() {
+        return Some(String::from("Executable not found - skipped."));
+    }
+    if let Some(last) = recent_success(runs, name) {
+        return Some(format!(
+            "Last succeeded at {}, within the minimum interval - skipped.",
+            last.format("%Y-%m-%d %H:%M:%S")
+        ));
+    }
+    if let Some(quiet) =
+        config::get_quiet_hours(name).filter(|quiet| quiet.contains(chrono::Local::now().time()))
+    {
+        // An unexpected trigger during the window leaves a trace.
+        return Some(format!(
+            "Within the quiet hours ({}-{}) - deferred.",
+            quiet.start.format("%H:%M"),
+            quiet.end.format("%H:%M")
+        ));
+    }
+    if config::requires_ac_power(name) && power::on_battery() {
+        // A laptop that is never plugged in doesn't silently stop updating.
+        return Some(String::from(
+            "On battery power - deferred until on AC power.",
+        ));
+    }
+    config::skip_on_metered(name)
+        .then(metered::metered_network)
+        .flatten()
+        .map(|reason| {
+            format!(
+                "On a metered network ({}) - deferred until on another network.",
+                reason
+            )
+        })
+}
+
+// Function to log the start of a run to `cronup.run.log`, including any clock skew.
+fn log_run_header(log_home: &str, skew: Option<Duration>) -> Result<(), Box<dyn Error>> {
+    let mut file = logging::open_log(log_home, "run")?;
+    match skew {
+        Some(skew) => logging::log_line(
+            &mut file,
+            &format!(
+                "Run started - WARNING: clock is {} seconds behind the newest log entry; \
+                 cleanup and run history are skipped.",
+                skew.as_secs()
+            ),
+        ),
+        None => logging::log_line(&mut file, "Run started."),
+    }
+}
+
+// Function to log to `cronup.run.log` that the previous run was interrupted, and whether this run
+// resumes it.
+fn log_interrupted(
+    log_home: &str,
+    previous: &progress::Interrupted,
+    resumed: bool,
+) -> Result<(), Box<dyn Error>> {
+    let mut file = logging::open_log(log_home, "run")?;
+    let completed = if previous.completed.is_empty() {
+        String::from("none")
+    } else {
+        previous.completed.join(", ")
+    };
+    let message = format!(
+        "The run started at {} was interrupted; it completed: {}.",
+        previous.started, completed
+    );
+    logging::log_line(&mut file, &message)?;
+    if resumed {
+        logging::log_line(&mut file, "Resuming it with the remaining updaters.")?;
+    }
+    Ok(())
+}
+
+// Function to determine whether an updater succeeded within its minimum interval.
+// Returns the local start time of that run if so. A skewed clock can't tell, so it never skips.
+fn recent_success(runs: &[history::Run], name: &str) -> Option<chrono::NaiveDateTime> {
+    let interval = config::get_min_interval(name)?;
+    if clock::is_skewed() {
+        return None;
+    }
+    let last = history::last_success(runs, name)?;
+    let elapsed = (chrono::Local::now().naive_local() - last).to_std().ok()?;
+    (elapsed < interval).then_some(last)
+}
+
+// Function to log to `cronup.run.log` that another run holds the lock, so this one doesn't start.
+fn log_already_running(log_home: &str, pid: Option<u32>) -> Result<(), Box<dyn Error>> {
+    let mut file = logging::open_log(log_home, "run")?;
+    let holder = pid.map_or_else(String::new, |pid| format!(" (pid {})", pid));
+    logging::log_line(
+        &mut file,
+        &format!("Another run{} is already running - exiting.", holder),
+    )
+}
+
+// Function to log that the network is offline or behind a captive portal and updates were aborted,
+// followed by how long ago every installed updater last succeeded, e.g. "brew last updated 5 days ago".
+// Returns those lines, each with whether the updater is past `CRONUP_STALE_DAYS[_<NAME>]`.
+fn log_offline(log_home: &str, message: &str) -> Result<Vec<(String, bool)>, Box<dyn Error>> {
+    // Open the offline log file and write the message with a timestamp.
+    let mut file = logging::open_log(log_home, "offline")?;
+    logging::log_line(&mut file, message)?;
+
+    let runs = history::load(log_home)?;
+    let now = chrono::Local::now().naive_local();
+    let mut staleness = Vec::new();
+    for updater in updaters::registry() {
+        if !updater.detect() {
+            continue;
+        }
+        let name = updater.name();
+        let limit = config::get_setting("STALE_DAYS", name, DEFAULT_STALE_DAYS);
+        let (line, stale) = match history::last_success(&runs, name) {
+            Some(last) => {
+                let days = (now - last).num_days().max(0) as u64;
+                let ago = match days {
+                    0 => String::from("today"),
+                    1 => String::from("1 day ago"),
+                    days => format!("{} days ago", days),
+                };
+                (format!("{} last updated {}", name, ago), days > limit)
+            }
+            None => (format!("{} never updated successfully", name), false),
+        };
+        logging::log_line(&mut file, &line)?;
+        staleness.push((line, stale));
+    }
+    Ok(staleness)
+}
+
+// Function to run an updater and log its output to `cronup.<name>.log`.
+fn run_updater(updater: &dyn Updater, log_home: &str) -> Result<TaskResult, Box<dyn Error>> {
+    let name = updater.name();
+    let started = history::timestamp();
+    let start = Instant::now();
+
+    // Open the updater's log file.
+    let mut log_file = logging::open_log(log_home, name)?;
+
+    // An updater that needs root privileges doesn't run without them.
+    let needs_sudo = config::needs_sudo(name, updater.needs_sudo());
+    if needs_sudo {
+        if let Err(reason) = sudo::check() {
+            logging::log_line(
+                &mut log_file,
+                &format!(
+                    "Root privileges unavailable ({}) - updater skipped.",
+                    reason
+                ),
+            )?;
+            logging::log_line(
+                &mut log_file,
+                &result_line(name, "failed", start.elapsed(), Some(0)),
+            )?;
+            return Ok(TaskResult {
+                name,
+                kind: TaskKind::Updater,
+                failure: Some(String::from("no root privileges")),
+                anomaly: false,
+                summary: Vec::new(),
+                duration: start.elapsed(),
+                updated: None,
+            });
+        }
+    }
+
+    // A pre-hook that fails leaves the updater's tools alone.
+    if let Some(failure) = run_hook("PRE_HOOK", name, None, &mut log_file)? {
+        logging::log_line(&mut log_file, "Pre-hook failed - updater skipped.")?;
+        logging::log_line(
+            &mut log_file,
+            &result_line(name, "failed", start.elapsed(), Some(0)),
+        )?;
+        return Ok(TaskResult {
+            name,
+            kind: TaskKind::Updater,
+            failure: Some(format!("pre-hook {}", failure)),
+            anomaly: false,
+            summary: Vec::new(),
+            duration: start.elapsed(),
+            updated: None,
+        });
+    }
+
+    // Run the smoke tests first, so only the tools the update breaks are blamed on it.
+    let smoke_tests = config::get_smoke_tests(name);
+    let broken_before = run_smoke_tests(&smoke_tests, &mut log_file)?;
+
+    // Run the commands one at a time within the updater's timeout, retrying each one if it fails.
+    let mut commands = updater.commands();
+    if needs_sudo {
+        commands = commands.iter().map(|command| sudo::wrap(command)).collect();
+    }
+    let deadline = start + config::get_timeout(name);
+    let mut timeout = config::get_timeout(name);
+    let inactivity = config::get_inactivity(name, updater.inactivity_limit());
+    let mut output = process::CommandOutput::empty();
+    for (index, command) in commands.iter().enumerate() {
+        // A timeout ends the run, and a failure does too unless the updater is set to continue.
+        if output.status.is_none() || !(output.succeeded() || config::continue_on_failure(name)) {
+            logging::log_line(
+                &mut log_file,
+                &if index + 1 == commands.len() {
+                    format!("Skipped step {} after the failure.", index + 1)
+                } else {
+                    format!(
+                        "Skipped steps {} to {} after the failure.",
+                        index + 1,
+                        commands.len()
+                    )
+                },
+            )?;
+            break;
+        }
+
+        let step_start = Instant::now();
+        // A step with a timeout of its own, e.g. one known to hang at times, may take less.
+        let remaining = deadline.saturating_duration_since(step_start);
+        let limit = updater
+            .step_timeout(index)
+            .filter(|limit| *limit < remaining);
+        timeout = limit.unwrap_or_else(|| config::get_timeout(name));
+        let remaining = limit.unwrap_or(remaining);
+        let step = run_with_retries(
+            name,
+            || process::shell(command),
+            remaining,
+            inactivity,
+            &mut log_file,
+        )?;
+        let outcome = match step.status {
+            Some(status) => match status.code() {
+                Some(code) => format!("exited with status {}", code),
+                None => String::from("was killed by a signal"),
+            },
+            None if step.killed_at_prompt.is_some() => {
+                String::from("was killed at an interactive prompt")
+            }
+            None if step.killed_for_inactivity.is_some() => String::from("hung"),
+            None => String::from("timed out"),
+        };
+        logging::log_line(
+            &mut log_file,
+            &format!(
+                "Step {} of {} {} after {} seconds: {}",
+                index + 1,
+                commands.len(),
+                outcome,
+                step_start.elapsed().as_secs(),
+                command
+            ),
+        )?;
+        if !step.succeeded() {
+            let description = format!(
+                "step {} of {} {}: {}",
+                index + 1,
+                commands.len(),
+                outcome,
+                command
+            );
+            log_error(log_home, name, &description, &step)?;
+        }
+        output.append(step);
+    }
+
+    // Let the updater log the outcome in its own way.
+    updater.post_process(&output, &mut log_file)?;
+
+    // Note the resources the run used, to tell which updater strains the machine.
+    logging::log_line(
+        &mut log_file,
+        &format!(
+            "Used {} seconds of CPU time, peak memory {} MB.",
+            output.usage.cpu.as_secs(),
+            output.usage.peak_rss / (1024 * 1024)
+        ),
+    )?;
+
+    // Follow the raw output with a summary of what changed, where the updater can tell.
+    let summary = updater.summary(&output);
+    for line in &summary {
+        logging::log_line(&mut log_file, line)?;
+    }
+
+    // Record the timeout or prompt so a killed run doesn't look like a silent success.
+    if let Some(prompt) = &output.killed_at_prompt {
+        logging::log_line(
+            &mut log_file,
+            &format!(
+                "Interactive prompt detected - process group killed: {}",
+                prompt
+            ),
+        )?;
+    } else if let Some(limit) = output.killed_for_inactivity {
+        logging::log_line(
+            &mut log_file,
+            &format!(
+                "Hung - no output for {} seconds - process group killed.",
+                limit.as_secs()
+            ),
+        )?;
+    } else if output.status.is_none() {
+        logging::log_line(
+            &mut log_file,
+            &format!(
+                "Timed out after {} seconds - process group killed.",
+                timeout.as_secs()
+            ),
+        )?;
+    }
+
+    // The post-hook learns whether the updater succeeded, e.g. to only restart a service after an upgrade.
+    let result = if output.succeeded() { "ok" } else { "failed" };
+    let post_hook_failure = run_hook("POST_HOOK", name, Some(result), &mut log_file)?
+        .map(|failure| format!("post-hook {}", failure));
+
+    // Check that the updated tools still work.
+    let broken: Vec<String> = run_smoke_tests(&smoke_tests, &mut log_file)?
+        .into_iter()
+        .filter(|test| !broken_before.contains(test))
+        .collect();
+    for test in &broken {
+        logging::log_line(
+            &mut log_file,
+            &format!(
+                "WARNING: broken by the update - smoke test failed: {}",
+                test
+            ),
+        )?;
+    }
+    let smoke_failure = (!broken.is_empty()).then(|| format!("broke {}", broken.join(", ")));
+
+    // Compare the output size to the updater's recent runs before this run joins them.
+    let output_bytes = (output.stdout.len() + output.stderr.len()) as u64;
+    let anomaly = check_log_volume(log_home, name, output_bytes, &mut log_file)?;
+    let duration = start.elapsed();
+    check_duration(log_home, name, duration, &mut log_file)?;
+
+    // Record the run for the trend report, unless its timestamp can't be trusted.
+    let updated = updater.updated_packages(&output);
+    if !clock::is_skewed() {
+        history::record(log_home, &started, name, &output, duration, updated)?;
+    }
+
+    // End the log section with the summary line for log watchers.
+    let failure = output
+        .failure_signature()
+        .or(post_hook_failure)
+        .or(smoke_failure);
+    let status = if failure.is_none() { "ok" } else { "failed" };
+    logging::log_line(&mut log_file, &result_line(name, status, duration, updated))?;
+
+    // Report whether and how the commands or the post-hook failed, and whether the output spiked.
+    Ok(TaskResult {
+        name,
+        kind: TaskKind::Updater,
+        failure,
+        anomaly,
+        summary,
+        duration,
+        updated,
+    })
+}
+
+// Function to run an updater's hook, the shell command in `CRONUP_<SETTING>_<NAME>` or
+// `CRONUP_<SETTING>`, within `CRONUP_HOOK_TIMEOUT[_<NAME>]` seconds. The hook finds the updater's name in
+// `CRONUP_UPDATER` and, after the updater ran, "ok" or "failed" in `CRONUP_RESULT`.
+// Its output is written to the updater's log. Returns the signature of its failure, or `None` if it
+// succeeded or no hook is configured.
+fn run_hook(
+    setting: &str,
+    name: &str,
+    result: Option<&str>,
+    log_file: &mut logging::LogFile,
+) -> Result<Option<String>, Box<dyn Error>> {
+    let Some(hook) = config::get_text_setting(setting, name).filter(|hook| !hook.is_empty()) else {
+        return Ok(None);
+    };
+
+    logging::log_line(
+        log_file,
+        &format!(
+            "Running {}: {}",
+            setting.to_lowercase().replace('_', "-"),
+            hook
+        ),
+    )?;
+    let mut command = process::shell(&hook);
+    command.env("CRONUP_UPDATER", name);
+    if let Some(result) = result {
+        command.env("CRONUP_RESULT", result);
+    }
+    let timeout = Duration::from_secs(config::get_setting(
+        "HOOK_TIMEOUT",
+        name,
+        DEFAULT_HOOK_TIMEOUT_SECS,
+    ));
+    let output = process::run_with_timeout(command, timeout, None)?;
+    logging::write_output(log_file, &output)?;
+
+    let failure = output.failure_signature();
+    if let Some(failure) = &failure {
+        logging::log_line(log_file, &format!("Hook failed: {}", failure))?;
+    }
+    Ok(failure)
+}
+
+// Function to flag a run whose output is far larger than the median of the updater's recent runs.
+// The warning is logged to the updater's log. Returns whether the run was flagged.
+fn check_log_volume(
+    log_home: &str,
+    name: &str,
+    output_bytes: u64,
+    log_file: &mut logging::LogFile,
+) -> Result<bool, Box<dyn Error>> {
+    let factor = config::get_volume_factor(name);
+    if factor == 0 || output_bytes < VOLUME_MIN_BYTES {
+        return Ok(false);
+    }
+    let runs = history::load(log_home)?;
+    let Some(median) = history::median_output_bytes(&runs, name, VOLUME_WINDOW, VOLUME_MIN_RUNS)
+    else {
+        return Ok(false);
+    };
+    if output_bytes <= median.saturating_mul(factor) {
+        return Ok(false);
+    }
+
+    logging::log_line(
+        log_file,
+        &format!(
+            "WARNING: log volume anomaly - {} bytes of output, more than {} times the median of {} bytes.",
+            output_bytes, factor, median
+        ),
+    )?;
+    Ok(true)
+}
+
+// Function to copy a failed step to `cronup.errors.log`, the one place to scan for problems of every
+// updater: which step failed and how, followed by the last `ERROR_EXCERPT_LINES` lines of its error
+// output, or of its standard output if it wrote no errors. Every line starts with the updater's name.
+fn log_error(
+    log_home: &str,
+    name: &str,
+    description: &str,
+    output: &process::CommandOutput,
+) -> Result<(), Box<dyn Error>> {
+    let mut log_file = logging::open_log(log_home, "errors")?;
+    logging::log_line(&mut log_file, &format!("{}: {}", name, description))?;
+    let stream = if output.stderr.iter().all(u8::is_ascii_whitespace) {
+        &output.stdout
+    } else {
+        &output.stderr
+    };
+    let text = String::from_utf8_lossy(stream);
+    let lines: Vec<&str> = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .collect();
+    for line in &lines[lines.len().saturating_sub(ERROR_EXCERPT_LINES)..] {
+        logging::log_line(&mut log_file, &format!("{}: {}", name, line))?;
+    }
+    Ok(())
+}
+
+// Function to run smoke tests, each within `SMOKE_TEST_TIMEOUT`, and log their outcome.
+// Returns the tests that failed.
+fn run_smoke_tests(
+    tests: &[String],
+    log_file: &mut logging::LogFile,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut failed = Vec::new();
+    for test in tests {
+        let output = process::run_with_timeout(process::shell(test), SMOKE_TEST_TIMEOUT, None)?;
+        if let Some(failure) = output.failure_signature() {
+            logging::log_line(
+                log_file,
+                &format!("Smoke test failed ({}): {}", failure, test),
+            )?;
+            failed.push(test.clone());
+        } else {
+            logging::log_line(log_file, &format!("Smoke test passed: {}", test))?;
+        }
+    }
+    Ok(failed)
+}
+
+// Function to log a warning if an updater's run took more than `CRONUP_DURATION_FACTOR` times the
+// average duration of its recent successful runs.
+fn check_duration(
+    log_home: &str,
+    name: &str,
+    duration: Duration,
+    log_file: &mut logging::LogFile,
+) -> Result<(), Box<dyn Error>> {
+    let factor = config::get_duration_factor(name);
+    if factor == 0 || duration.as_secs() < DURATION_MIN_SECS {
+        return Ok(());
+    }
+    let runs = history::load(log_home)?;
+    let Some(average) =
+        history::average_duration_secs(&runs, name, DURATION_WINDOW, DURATION_MIN_RUNS)
+    else {
+        return Ok(());
+    };
+    if duration.as_secs() <= average.saturating_mul(factor) {
+        return Ok(());
+    }
+
+    logging::log_line(
+        log_file,
+        &format!(
+            "WARNING: duration regression - the run took {} seconds, more than {} times the average of {} seconds.",
+            duration.as_secs(),
+            factor,
+            average
+        ),
+    )
+}
+
+// Function to log the prompts a command was found waiting at, and how each was handled.
+fn log_prompts(
+    output: &process::CommandOutput,
+    prompts: Option<&process::PromptHandling>,
+    log_file: &mut logging::LogFile,
+) -> Result<(), Box<dyn Error>> {
+    let Some(prompts) = prompts else {
+        return Ok(());
+    };
+    for prompt in &output.prompts {
+        if output.killed_at_prompt.as_ref() == Some(prompt) {
+            continue;
+        }
+        let handled = match &prompts.action {
+            process::PromptAction::Answer(answer) => format!("answered '{}'", answer),
+            process::PromptAction::Notify(_) => String::from("notified, left waiting"),
+            process::PromptAction::Kill => continue,
+        };
+        logging::log_line(
+            log_file,
+            &format!("Interactive prompt detected ({}): {}", handled, prompt),
+        )?;
+    }
+    Ok(())
+}
+
+// Function to run one of an updater's commands, retrying it with exponential backoff if it fails.
+// A fresh command is built for every attempt because a `Command` can't be reused once spawned.
+// Every attempt may take up to `timeout`, and is killed as hung after `inactivity` without output.
+// The output of failed attempts is logged immediately; the final attempt is returned to the caller.
+fn run_with_retries(
+    name: &str,
+    make_command: impl Fn() -> Command,
+    timeout: Duration,
+    inactivity: Option<Duration>,
+    log_file: &mut logging::LogFile,
+) -> Result<process::CommandOutput, Box<dyn Error>> {
+    let sampling = config::get_sampling(name);
+    let prompts = config::get_prompt_handling(name);
+    let retries = config::get_setting("RETRIES", name, 0);
+    let mut delay = Duration::from_secs(config::get_setting(
+        "RETRY_DELAY",
+        name,
+        DEFAULT_RETRY_DELAY_SECS,
+    ));
+
+    // The resources of every attempt count, not only those of the last one.
+    let mut usage = process::Usage::default();
+
+    let mut attempt = 1;
+    loop {
+        let mut output = process::run_watched(
+            make_command(),
+            timeout,
+            sampling,
+            prompts.as_ref(),
+            inactivity,
+        )?;
+        usage = usage.combine(output.usage);
+        log_prompts(&output, prompts.as_ref(), log_file)?;
+        // A command killed at a prompt would only ask again, so it isn't retried.
+        if output.succeeded() || attempt > retries || output.killed_at_prompt.is_some() {
+            output.usage = usage;
+            return Ok(output);
+        }
+
+        // Keep the failed attempt's output so the cause of the retry is visible in the log.
+        logging::write_output(log_file, &output)?;
+        logging::log_line(
+            log_file,
+            &format!(
+                "Attempt {} of {} {} - retrying in {} seconds.",
+                attempt,
+                retries + 1,
+                if output.status.is_some() {
+                    String::from("failed")
+                } else if let Some(limit) = output.killed_for_inactivity {
+                    format!("hung without output for {} seconds", limit.as_secs())
+                } else {
+                    format!("timed out after {} seconds", timeout.as_secs())
+                },
+                delay.as_secs()
+            ),
+        )?;
+
+        thread::sleep(delay);
+        delay = delay.saturating_mul(2);
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    // A panic in an updater thread still reports a result, so the run never waits for it forever.
+    #[test]
+    fn panicking_updater_reports_an_error() {
+        let (sender, receiver) = mpsc::channel();
+        thread::scope(|scope| {
+            scope.spawn(move || {
+                let result: Result<(), String> = catch_panic("brew", || panic!("boom"));
+                let _ = sender.send(result);
+            });
+        });
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_secs(5)),
+            Ok(Err(String::from("brew panicked: boom")))
+        );
+    }
+
+    // A second run in the same process starts a new section in every log it opens.
+    #[test]
+    fn later_run_starts_new_sections() {
+        let log_home = env::temp_dir().join(format!("cronup-sections-{}", std::process::id()));
+        fs::create_dir_all(&log_home).unwrap();
+        let log_home = log_home.to_string_lossy().into_owned();
+        let count_markers = || {
+            fs::read_to_string(format!("{}/cronup.sections.log", log_home))
+                .unwrap()
+                .matches(logstamp::RUN_START_MARKER)
+                .count()
+        };
+        logging::open_log(&log_home, "sections").unwrap();
+        logging::open_log(&log_home, "sections").unwrap();
+        assert_eq!(count_markers(), 1);
+        logging::reset();
+        logging::open_log(&log_home, "sections").unwrap();
+        assert_eq!(count_markers(), 2);
+        let _ = fs::remove_dir_all(&log_home);
+    }
+
+    // An updater's error fails only that updater, with the error as its signature.
+    #[test]
+    fn updater_error_fails_the_updater() {
+        let log_home = env::temp_dir().join(format!("cronup-updater-error-{}", std::process::id()));
+        fs::create_dir_all(&log_home).unwrap();
+        let log_home = log_home.to_string_lossy().into_owned();
+        let result = updater_error("brew", "brew panicked: boom", Duration::ZERO, &log_home);
+        assert_eq!(result.kind, TaskKind::Updater);
+        assert_eq!(result.failure.as_deref(), Some("brew panicked: boom"));
+        let log = fs::read_to_string(format!("{}/cronup.brew.log", log_home)).unwrap();
+        assert!(log.contains("ERROR: brew panicked: boom"));
+        assert!(log.contains("RESULT task=brew status=failed"));
+        let _ = fs::remove_dir_all(&log_home);
+    }
+
+    #[test]
+    fn result_lines_keep_their_format() {
+        assert_eq!(
+            result_line("brew", "ok", Duration::from_millis(95_400), Some(12)),
+            "RESULT task=brew status=ok duration=95s changed=12"
+        );
+        assert_eq!(
+            result_line("nvim", "failed", Duration::ZERO, None),
+            "RESULT task=nvim status=failed duration=0s changed=unknown"
+        );
+    }
+}
@@ -0,0 +1,177 @@
+// The run history behind the trend report: one line per updater run, appended to `cronup.history`
+// in `LOG_HOME`. Each line holds the tab-separated start time, updater name, outcome ("ok" or
+// "failed"), duration in seconds, number of updated packages ("-" if the updater can't tell), size
+// of the run's output in bytes, CPU time in seconds, and peak memory in bytes. Lines written by older
+// versions lack the last columns.
+
+// The `Local` struct from the `chrono` crate is used for the start time of a run, and
+// `NaiveDateTime` for reading it back.
+use chrono::{Local, NaiveDateTime};
+
+// Import various modules from the Rust standard library.
+use std::{
+    error::Error,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    time::Duration,
+};
+
+// The output of a run, including the resources it used.
+use crate::process::CommandOutput;
+
+// A single updater run read back from the history.
+pub struct Run {
+    // The local time the run started, as "YYYY-MM-DD HH:MM:SS".
+    pub started: String,
+    // The month the run started in, as "YYYY-MM".
+    pub month: String,
+    // The name of the updater.
+    pub name: String,
+    // Whether the run succeeded.
+    pub succeeded: bool,
+    // How long the run took, in seconds.
+    pub duration_secs: u64,
+    // The number of packages the run updated, if known.
+    pub updated: Option<usize>,
+    // The size of the run's output in bytes, if recorded.
+    pub output_bytes: Option<u64>,
+    // The CPU time of the run's process trees in seconds, if recorded.
+    pub cpu_secs: Option<u64>,
+    // The peak memory of the largest process of the run in bytes, if recorded.
+    pub peak_rss: Option<u64>,
+}
+
+// Function to get the current time in the history's format, "YYYY-MM-DD HH:MM:SS" in local time.
+// The history keeps this format regardless of the configured log timestamp format.
+pub fn timestamp() -> String {
+    Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+// Function to get the path of the history file.
+fn history_path(log_home: &str) -> String {
+    format!("{}/cronup.history", log_home)
+}
+
+// Function to append an updater run to the history.
+pub fn record(
+    log_home: &str,
+    started: &str,
+    name: &str,
+    output: &CommandOutput,
+    duration: Duration,
+    updated: Option<usize>,
+) -> Result<(), Box<dyn Error>> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(history_path(log_home))?;
+    writeln!(
+        file,
+        "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+        started,
+        name,
+        if output.succeeded() { "ok" } else { "failed" },
+        duration.as_secs(),
+        updated.map_or_else(|| String::from("-"), |count| count.to_string()),
+        output.stdout.len() + output.stderr.len(),
+        output.usage.cpu.as_secs(),
+        output.usage.peak_rss
+    )?;
+    Ok(())
+}
+
+// Function to read every run from the history, oldest first.
+// A missing history is empty, and malformed lines are skipped.
+pub fn load(log_home: &str) -> Result<Vec<Run>, Box<dyn Error>> {
+    let content = match fs::read_to_string(history_path(log_home)) {
+        Ok(content) => content,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error.into()),
+    };
+
+    Ok(content.lines().filter_map(parse_run).collect())
+}
+
+// Function to parse a single history line.
+fn parse_run(line: &str) -> Option<Run> {
+    let mut fields = line.split('\t');
+    let started = fields.next()?;
+    let name = fields.next()?;
+    let succeeded = match fields.next()? {
+        "ok" => true,
+        "failed" => false,
+        _ => return None,
+    };
+    let duration_secs = fields.next()?.parse().ok()?;
+    let updated = match fields.next()? {
+        "-" => None,
+        count => Some(count.parse().ok()?),
+    };
+    let output_bytes = fields.next().and_then(|bytes| bytes.parse().ok());
+    let cpu_secs = fields.next().and_then(|secs| secs.parse().ok());
+    let peak_rss = fields.next().and_then(|bytes| bytes.parse().ok());
+
+    Some(Run {
+        started: started.to_string(),
+        month: started.get(..7)?.to_string(),
+        name: name.to_string(),
+        succeeded,
+        duration_secs,
+        updated,
+        output_bytes,
+        cpu_secs,
+        peak_rss,
+    })
+}
+
+// Function to get the median output size of an updater's most recent runs, at most `window` of them.
+// Returns `None` if fewer than `min_runs` runs recorded their output size.
+pub fn median_output_bytes(
+    runs: &[Run],
+    name: &str,
+    window: usize,
+    min_runs: usize,
+) -> Option<u64> {
+    let mut sizes: Vec<u64> = runs
+        .iter()
+        .rev()
+        .filter(|run| run.name == name)
+        .filter_map(|run| run.output_bytes)
+        .take(window)
+        .collect();
+    if sizes.len() < min_runs.max(1) {
+        return None;
+    }
+    sizes.sort_unstable();
+    Some(sizes[sizes.len() / 2])
+}
+
+// Function to get the average duration in seconds of an updater's most recent successful runs, at
+// most `window` of them. Failed runs are left out because they often end early or time out.
+// Returns `None` if fewer than `min_runs` successful runs were recorded.
+pub fn average_duration_secs(
+    runs: &[Run],
+    name: &str,
+    window: usize,
+    min_runs: usize,
+) -> Option<u64> {
+    let durations: Vec<u64> = runs
+        .iter()
+        .rev()
+        .filter(|run| run.name == name && run.succeeded)
+        .map(|run| run.duration_secs)
+        .take(window)
+        .collect();
+    if durations.len() < min_runs.max(1) {
+        return None;
+    }
+    Some(durations.iter().sum::<u64>() / durations.len() as u64)
+}
+
+// Function to get the local start time of an updater's most recent successful run, if any.
+pub fn last_success(runs: &[Run], name: &str) -> Option<NaiveDateTime> {
+    runs.iter()
+        .rev()
+        .filter(|run| run.name == name && run.succeeded)
+        .find_map(|run| NaiveDateTime::parse_from_str(&run.started, "%Y-%m-%d %H:%M:%S").ok())
+}
@@ -0,0 +1,45 @@
+// The task engine behind cronup as a library, so other Rust programs (e.g. a menu bar app) can embed
+// the update orchestration instead of running the cronup binary. The package builds it as
+// `macpaw_tasks`:
+//
+//     let result = macpaw_tasks::run(&macpaw_tasks::config::log_home(), RunOptions::default(), &mut ())?;
+//     for task in &result.tasks { ... }
+//
+// `run` behaves like a cronup run without the notifications, emailed report, metrics, and status
+// page, and is configured through the same `CRONUP_*` environment variables. Pass an `Observer` to
+// follow its progress. Those presenting modules are public too, so an embedder can reuse them.
+
+// The modules of the task engine.
+mod bootstrap; // Installing the package managers on a fresh Mac.
+mod checks; // Check-only tasks and their thresholds.
+mod clock; // System clock sanity checks.
+pub mod config; // Settings read from the environment.
+pub mod connectivity; // The network check.
+mod drift; // Drift detection of the system tools.
+mod engine; // Running the tasks.
+mod history; // The run history for the trend report.
+mod lock; // The lock that prevents overlapping runs.
+pub mod logging; // Timestamped log file helpers.
+mod metered; // Metered network detection.
+mod oslog; // The unified log mirror.
+mod power; // The power source check.
+mod process; // Running commands with a timeout.
+mod progress; // The progress of a run, for resuming it.
+mod sudo; // Root privileges for the updaters that need them.
+mod updaters; // The `Updater` trait and its implementations.
+
+// The modules that present a run's results, used by the cronup binary.
+pub mod email; // The emailed run report.
+pub mod metrics; // The Prometheus and statsd metrics export.
+pub mod notify; // macOS notifications.
+pub mod outdated; // The outdated-packages report of `--check`.
+pub mod report; // The trend report.
+pub mod status; // The local HTTP status page.
+
+// Rolling back an upgrade, for `cronup rollback`.
+pub use updaters::rollback;
+
+// The typed API of the engine.
+pub use engine::{
+    run, updater_names, Observer, RunOptions, RunResult, RunStatus, TaskKind, TaskResult,
+};
@@ -0,0 +1,57 @@
+// An exclusive lock on `cronup.pid` in `LOG_HOME`, so a run that outlasts the schedule interval
+// isn't joined by a second one fighting over the same package managers and log files.
+// The lock is an `flock` on the open file, so the kernel releases it when the holder exits,
+// even if it crashed; a stale pid file never blocks the next run.
+
+// Import various modules from the Rust standard library.
+use std::{
+    error::Error,
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    os::unix::io::AsRawFd,
+    process,
+};
+
+// The `flock` operations for an exclusive lock that fails instead of waiting if it is held.
+const LOCK_EX: i32 = 2;
+const LOCK_NB: i32 = 4;
+
+extern "C" {
+    // Applies or removes an advisory lock on an open file.
+    fn flock(fd: i32, operation: i32) -> i32;
+}
+
+// The outcome of trying to take the lock.
+pub enum Lock {
+    // The lock is held for as long as the file stays open.
+    Acquired(File),
+    // Another run holds the lock; its pid, if the pid file could be read.
+    Held(Option<u32>),
+}
+
+// Function to take the run lock, writing this process's pid to the pid file once it is held.
+pub fn acquire(log_home: &str) -> Result<Lock, Box<dyn Error>> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(format!("{}/cronup.pid", log_home))?;
+
+    // The file descriptor stays valid while `file` is alive.
+    if unsafe { flock(file.as_raw_fd(), LOCK_EX | LOCK_NB) } != 0 {
+        let error = io::Error::last_os_error();
+        if error.kind() != io::ErrorKind::WouldBlock {
+            return Err(error.into());
+        }
+        let mut pid = String::new();
+        file.read_to_string(&mut pid)?;
+        return Ok(Lock::Held(pid.trim().parse().ok()));
+    }
+
+    // Replace the pid of the previous run with this one's.
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    writeln!(file, "{}", process::id())?;
+    Ok(Lock::Acquired(file))
+}
@@ -0,0 +1,240 @@
+// Helpers for writing timestamped lines to the `cronup.<name>.log` files in `LOG_HOME`.
+// With `CRONUP_PER_RUN_LOGS` set, every line is also written to `cronup/<name>/<run>.log`, and
+// `cronup/<name>/latest` links to the log of the most recent run.
+// With `CRONUP_LOG_ROTATION[_<NAME>]` set to "daily" or "run", the log is written to
+// `cronup.<name>.<YYYY-MM-DD>.log` or `cronup.<name>.<YYYYMMDD-HHMMSS>.log` instead of `cronup.<name>.log`,
+// and only the newest `CRONUP_LOG_KEEP[_<NAME>]` (default 14) of those files are kept. Their timestamp
+// format is still configured by the name of the unrotated log, e.g. `LOG_TIMESTAMP_FORMAT_CRONUP_BREW`.
+// The timestamp format of every log file is configured through the shared `logstamp` crate,
+// which cleanlog uses as well, so it can always parse what cronup wrote.
+// The first time a run opens a log, it writes the shared run start marker, so cleanlog can prune the
+// log by whole runs.
+// With `CRONUP_LOG_FORMAT[_<NAME>]` set to "json", every line is instead a JSON object for jq or a log
+// shipper: {"timestamp":...,"updater":...,"stream":...,"line":...,"exit_code":...}, where the stream is
+// "stdout" or "stderr" for a command's output, with its exit code, and "cronup" for cronup's own
+// messages, with a null exit code. cleanlog reads the timestamps of both formats.
+// With `CRONUP_OSLOG[_<NAME>]` set, cronup's own messages are mirrored into the unified log as well.
+
+// The timestamp format and run start marker shared with cleanlog.
+use logstamp::{TimestampFormat, RUN_START_MARKER};
+
+// The `Local` struct from the `chrono` crate is used to name per-run log files.
+use chrono::Local;
+
+// Import various modules from the Rust standard library.
+use std::{
+    collections::HashSet,
+    error::Error,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    os::unix::fs::symlink,
+    process,
+    sync::Mutex,
+};
+
+// The per-run log and log format settings, the unified log, the output type whose lines are written to the log, and
+// the JSON quoting of strings.
+use crate::{config, oslog, process::CommandOutput, status::json_string};
+
+// How many rotated log files of an updater are kept when `CRONUP_LOG_KEEP` is not set.
+const DEFAULT_LOG_KEEP: u64 = 14;
+
+// An open log file together with the timestamp format configured for it.
+pub struct LogFile {
+    // The file, opened for appending.
+    file: File,
+    // The format of the timestamp every line starts with.
+    format: TimestampFormat,
+    // The log file of the current run, if per-run logs are enabled.
+    run_file: Option<File>,
+    // The name of the updater or task the log belongs to.
+    name: String,
+    // Whether lines are written as JSON objects instead of plain text.
+    json: bool,
+    // Whether cronup's own messages are mirrored into the unified log.
+    oslog: bool,
+}
+
+// The name of the current run's per-run log files, once it was fixed, and the log files the run
+// opened, which got its run start marker.
+static RUN_NAME: Mutex<Option<String>> = Mutex::new(None);
+static STARTED: Mutex<Option<HashSet<String>>> = Mutex::new(None);
+
+// Function to return the name shared by every per-run log file of this run, e.g. `20240501-031500`.
+// It is fixed on first use, so all of a run's files are named alike however long the run takes.
+fn run_name() -> String {
+    let now = || Local::now().format("%Y%m%d-%H%M%S").to_string();
+    match RUN_NAME.lock() {
+        Ok(mut run_name) => run_name.get_or_insert_with(now).clone(),
+        Err(_) => now(),
+    }
+}
+
+// Function to start the logs of a new run in this process: the next open of every log starts a new
+// section, and the per-run logs get a new name.
+pub(crate) fn reset() {
+    if let Ok(mut run_name) = RUN_NAME.lock() {
+        *run_name = None;
+    }
+    if let Ok(mut started) = STARTED.lock() {
+        *started = None;
+    }
+}
+
+// Function to open `cronup/<name>/<run>.log` in append mode and point `cronup/<name>/latest` at it.
+// The link is created under a temporary name and renamed over `latest`, so readers never find it
+// missing or half-written.
+fn open_run_log(log_home: &str, name: &str) -> Result<File, Box<dyn Error>> {
+    let dir = format!("{}/cronup/{}", log_home, name);
+    fs::create_dir_all(&dir)?;
+
+    let file_name = format!("{}.log", run_name());
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(format!("{}/{}", dir, file_name))?;
+
+    // The link is relative, so it stays valid if the log directory is moved.
+    let temporary = format!("{}/.latest.{}", dir, process::id());
+    let _ = fs::remove_file(&temporary);
+    symlink(&file_name, &temporary)?;
+    fs::rename(&temporary, format!("{}/latest", dir))?;
+
+    Ok(file)
+}
+
+// Function to get the name of the rotated log file of this run, or `None` if the log isn't rotated.
+fn rotated_name(name: &str) -> Option<String> {
+    let period = match config::get_text_setting("LOG_ROTATION", name).as_deref() {
+        Some("daily") => Local::now().format("%Y-%m-%d").to_string(),
+        Some("run") => run_name(),
+        _ => return None,
+    };
+    Some(format!("cronup.{}.{}.log", name, period))
+}
+
+// Function to delete all but the newest `CRONUP_LOG_KEEP[_<NAME>]` rotated log files of `name`.
+// Their dates sort like their names, so the newest are the last ones in name order.
+fn remove_rotated(log_home: &str, name: &str) -> Result<(), Box<dyn Error>> {
+    let keep = config::get_setting("LOG_KEEP", name, DEFAULT_LOG_KEEP) as usize;
+    let prefix = format!("cronup.{}.", name);
+    let mut rotated: Vec<String> = fs::read_dir(log_home)?
+        .flatten()
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|file_name| {
+            file_name
+                .strip_prefix(&prefix)
+                .and_then(|rest| rest.strip_suffix(".log"))
+                .is_some_and(|period| {
+                    !period.is_empty() && period.chars().all(|c| c.is_ascii_digit() || c == '-')
+                })
+        })
+        .collect();
+    rotated.sort();
+    let excess = rotated.len().saturating_sub(keep);
+    for file_name in &rotated[..excess] {
+        fs::remove_file(format!("{}/{}", log_home, file_name))?;
+    }
+    Ok(())
+}
+
+// Function to open `cronup.<name>.log`, or the rotated log of this run, in append mode, creating it
+// if it doesn't exist.
+// The per-run log is opened as well when `CRONUP_PER_RUN_LOGS_<NAME>` or `CRONUP_PER_RUN_LOGS` is
+// set to a non-zero number.
+pub fn open_log(log_home: &str, name: &str) -> Result<LogFile, Box<dyn Error>> {
+    let base_name = format!("cronup.{}.log", name);
+    let rotated = rotated_name(name);
+    let file_name = rotated.clone().unwrap_or_else(|| base_name.clone());
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(format!("{}/{}", log_home, file_name))?;
+    let run_file = if config::get_setting("PER_RUN_LOGS", name, 0) > 0 {
+        Some(open_run_log(log_home, name)?)
+    } else {
+        None
+    };
+    let mut log_file = LogFile {
+        file,
+        format: TimestampFormat::for_log(&base_name),
+        run_file,
+        name: name.to_string(),
+        json: config::get_text_setting("LOG_FORMAT", name).as_deref() == Some("json"),
+        oslog: config::get_setting("OSLOG", name, 0) > 0,
+    };
+
+    // Start a new section the first time this run opens the log.
+    let first_open = STARTED
+        .lock()
+        .map(|mut started| started.get_or_insert_with(HashSet::new).insert(file_name))
+        .unwrap_or(false);
+    if first_open {
+        log_line(&mut log_file, RUN_START_MARKER)?;
+        if rotated.is_some() {
+            remove_rotated(log_home, name)?;
+        }
+    }
+    Ok(log_file)
+}
+
+// Function to write a line with the given timestamp to the log file and the per-run log, if any.
+// The stream and exit code only appear in JSON lines, which start with the timestamp as cleanlog expects.
+fn write_line(
+    log_file: &mut LogFile,
+    timestamp: &str,
+    stream: &str,
+    exit_code: Option<i32>,
+    line: &str,
+) -> Result<(), Box<dyn Error>> {
+    let text = if log_file.json {
+        format!(
+            "{{\"timestamp\":{},\"updater\":{},\"stream\":{},\"line\":{},\"exit_code\":{}}}",
+            json_string(timestamp),
+            json_string(&log_file.name),
+            json_string(stream),
+            json_string(line),
+            exit_code.map_or_else(|| String::from("null"), |code| code.to_string())
+        )
+    } else {
+        format!("[{}] {}", timestamp, line)
+    };
+    writeln!(log_file.file, "{}", text)?;
+    if let Some(run_file) = log_file.run_file.as_mut() {
+        writeln!(run_file, "{}", text)?;
+    }
+    Ok(())
+}
+
+// Function to write a single message to a log file with a timestamp.
+pub fn log_line(log_file: &mut LogFile, message: &str) -> Result<(), Box<dyn Error>> {
+    let timestamp = log_file.format.now();
+    if log_file.oslog {
+        oslog::log(&log_file.name, message);
+    }
+    write_line(log_file, &timestamp, "cronup", None, message)
+}
+
+// Function to write every non-empty line of a command's output to a log file with a timestamp.
+pub fn write_output(log_file: &mut LogFile, output: &CommandOutput) -> Result<(), Box<dyn Error>> {
+    // Get the current timestamp.
+    let timestamp = log_file.format.now();
+
+    let exit_code = output.status.and_then(|status| status.code());
+
+    // Standard output is logged before standard error.
+    for (name, stream) in [("stdout", &output.stdout), ("stderr", &output.stderr)] {
+        // Iterate over each line in the stream.
+        for line in BufReader::new(&stream[..]).lines() {
+            // Handle any errors that may occur while reading lines.
+            let line = line?;
+            // Check if the line is not empty after trimming whitespace.
+            if !line.trim().is_empty() {
+                // Write the line to the log file with a timestamp.
+                write_line(log_file, &timestamp, name, exit_code, &line)?;
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -1,238 +1,235 @@
-// This program automates updates for Homebrew, Cargo, Rustup, and Neovim plugins.
-// It checks for network connectivity before running update commands and logs the output with timestamps.
-
-// The `Local` struct from the `chrono` crate is used for handling dates and times.
-use chrono::Local;
+// This program automates updates for Homebrew, Cargo, Rustup, Neovim plugins, and the other tools in `updaters`.
+// It checks for network connectivity before running update commands and logs the output with timestamps,
+// followed by a short summary of what changed for the updaters that can parse their output.
+// Every command runs in its own process group with a timeout, so a hung updater can't block the whole run.
+// A command that writes nothing for `CRONUP_INACTIVITY_<NAME>` seconds (by default only Neovim's, after 300)
+// is killed as hung without waiting for the timeout.
+// An updater's commands run one at a time, and each one's exit status and duration are logged, so a
+// failure can be traced to its step. The remaining steps are skipped unless `CRONUP_ON_FAILURE` says
+// to continue.
+// Failed updaters can be retried with exponential backoff to ride out transient network problems.
+// Optionally, a macOS notification summarizes the run so failures are noticed without reading logs,
+// and long runs post progress notifications when they start and at their halfway point. Failures that were already notified aren't
+// notified again on every run unless they change or cronup is started with `--renotify`.
+// Besides updaters, check-only tasks monitor the system (e.g. disk usage) and flag threshold violations.
+// The system clock is checked against the existing logs first; if it has jumped backwards, the run
+// header in `cronup.run.log` notes the skew and time-sensitive actions are skipped.
+// A run whose output is far larger than usual for its updater, often the first sign of a looping or
+// erroring tool, is flagged as a log volume anomaly in its log and in the notification summary.
+// `CRONUP_SMOKE_TESTS_<NAME>` lists quick checks, e.g. "nvim; rg; gh auth status", run before and after
+// an updater; a check that passed before but fails after fails the updater as broken by the update.
+// A run that takes far longer than the moving average of its updater's recent runs, an early sign of
+// a wedged tool, is logged as a duration regression.
+// Every updater run is recorded in a history, and `cronup report [--html]` prints a monthly trend
+// report of updated packages, run durations, and failures from it.
+// `cronup rollback brew <formula>` returns a formula to the version recorded before the last upgrade.
+// While offline, the offline log lists how long ago every tool last updated successfully; tools past
+// `CRONUP_STALE_DAYS` (default 7) are named in the notification, which then repeats daily.
+// The exit code tells launchd or cron monitoring how the run went: 0 if every updater succeeded,
+// 2 if some failed, 3 if all of them failed, and 4 if the network was offline or behind a captive
+// portal. Errors of cronup itself exit with 1.
+// Every task's log section and `cronup.run.log` end with a line in a stable format for log watchers, e.g.
+// `RESULT task=brew status=ok duration=95s changed=12` (see engine.rs).
+// `cronup --check` only asks the installed tools for outdated packages and writes a report of them,
+// without upgrading anything, so updates can be reviewed before they run.
+// An updater that succeeded less than `CRONUP_MIN_INTERVAL` hours ago is skipped, unless the clock
+// is skewed. Updaters configured with `CRONUP_REQUIRE_AC` are deferred while on battery power, and
+// those configured with `CRONUP_ON_METERED=skip` while on a metered network such as a phone's hotspot.
+// Updaters with `CRONUP_QUIET_HOURS` (e.g. `09:00-18:00`) don't run during that daily window.
+// `cronup bootstrap` first installs the package managers that are missing (Homebrew and rustup) and
+// then runs the updates as usual, so a fresh Mac is set up with one command.
+// Only one run updates at a time: a run started while another one still holds the lock on
+// `cronup.pid` logs that it found one already running and exits with 0.
+// Run metrics are exported to a Prometheus node_exporter textfile directory (`CRONUP_METRICS_DIR`) and/or
+// a statsd server (`CRONUP_STATSD`).
+// With `CRONUP_LOG_ROTATION` set to "daily" or "run", every log goes to a dated file per day or run, of
+// which the newest `CRONUP_LOG_KEEP` are kept.
+// With `CRONUP_LOG_FORMAT` set to "json", the logs are written as one JSON object per line.
+// With `CRONUP_OSLOG=1`, cronup's messages are mirrored into the unified log under gg.hw.cronup.
+// With `CRONUP_EMAIL` set, a report of the run is emailed to that address through `sendmail`.
+// With `CRONUP_STATUS_PORT` set, the run's progress and results are served on a local status page.
+// Updaters that need root privileges (MacPorts, softwareupdate installing updates, or any updater with
+// `CRONUP_SUDO_<NAME>=1`) run through sudo, which asks the `CRONUP_SUDO_ASKPASS` program for the password
+// or relies on cached credentials; without elevation that updater fails and the run goes on.
+// With `CRONUP_MAX_PARALLEL` above 1, that many updaters run at the same time, each once its dependencies
+// are done; of the updaters ready to start, the one with the highest `CRONUP_PRIORITY_<NAME>` (default 0)
+// starts first, e.g. brew before the small ones on a slow machine.
+// Every failed step is also copied to `cronup.errors.log` with an excerpt of its error output, so the
+// problems of all updaters can be scanned in one place.
+// `CRONUP_PRE_HOOK_<NAME>` and `CRONUP_POST_HOOK_<NAME>` are shell commands run before and after an
+// updater (e.g. `brew bundle dump --force` after brew), with their output in the updater's log. A failed
+// pre-hook skips the updater, and a failed post-hook fails it.
+// With `CRONUP_BINSTALL_CARGO=1`, outdated Cargo packages are installed as prebuilt binaries through
+// cargo-binstall, which builds from source only those without one.
+// When rustup changed the toolchain, every Cargo package is rebuilt with `cargo install-update -a --force`.
+// `cronup --only brew,gem` runs only the named updaters (besides the check-only tasks).
+// A run that is killed before every updater had its turn is noted by the next run in `cronup.run.log`;
+// `cronup --resume` resumes it if it started the same day, skipping the updaters that already succeeded.
+// `cronup daemon` stays resident and runs the updaters on the cron-like schedules in `CRONUP_SCHEDULE`,
+// catching up on the runs missed while the Mac slept, and reloads the settings file `CRONUP_CONFIG`
+// when it changes (see daemon.rs). Its LaunchAgent sets `KeepAlive` instead of `StartInterval`.
+// Once a week, a baseline of the versions of common tools (`CRONUP_DRIFT_TOOLS`) and the hashes of
+// key config files (`CRONUP_DRIFT_FILES`) is recorded; what changed since that didn't come from cronup's
+// own runs is reported as drift in `cronup.drift.log` and the notification (see drift.rs).
+// The task engine is the `macpaw_tasks` library (see lib.rs), which other programs can embed; this
+// binary adds the command line, notifications, the emailed report, metrics, and the status page.
+
+// The daemon mode.
+mod daemon;
+
+// The task engine and the modules that present its results.
+use macpaw_tasks::{
+    config,
+    connectivity::{self, Connectivity},
+    email, metrics, notify, outdated, report, status, Observer, RunOptions, RunStatus, TaskKind,
+    TaskResult,
+};
 
 // Import various modules from the Rust standard library.
 use std::{
-    // The `env` module is used for interacting with environment variables.
+    // The `env` module is used for reading the command line.
     env,
     // The `Error` trait is used for error handling.
     error::Error,
-    // The `OpenOptions` struct is used for configuring how a file is opened.
-    fs::OpenOptions,
-    // The `BufRead`, `BufReader`, and `Write` traits are used for buffered I/O operations.
-    io::{BufRead, BufReader, Write},
-    // The `SocketAddr` and `TcpStream` structs are used for network socket operations.
-    net::{SocketAddr, TcpStream},
-    // The `Command` and `Stdio` structs are used for running external commands and handling their I/O.
-    process::{Command, Stdio},
-    // The `Duration` struct is used for specifying time intervals.
-    time::Duration,
 };
 
-// The main function of the program. It returns a `Result` type that can contain an empty tuple `()`
-// on success or a boxed error (`Box<dyn Error>`) on failure.
-fn main() -> Result<(), Box<dyn Error>> {
-    // Retrieve the log directory path from the environment variable `LOG_HOME`.
-    // If `LOG_HOME` is not set, default to `"/var/logs"`.
-    let log_home = env::var("LOG_HOME").unwrap_or_else(|_| String::from("/var/logs"));
-
-    // Check if the network is available by attempting to connect to a known address.
-    if !check_network()? {
-        // If the network is not available, log the offline status and exit.
-        log_offline(&log_home)?;
-        return Ok(());
-    }
+// The observer that shows a run's progress in progress notifications and on the status page.
+struct Presenter {
+    progress: notify::Progress,
+    status: status::Status,
+}
 
-    // Run and log Homebrew commands for updating and cleaning up packages.
-    run_commands_and_log(
-        vec![
-            // Update Homebrew package list.
-            "/opt/homebrew/bin/brew update",
-            // Upgrade all installed Homebrew packages.
-            "/opt/homebrew/bin/brew upgrade",
-            // Remove old versions of packages.
-            "/opt/homebrew/bin/brew cleanup",
-        ],
-        &log_home, // The directory where logs will be stored.
-        "brew",    // The name used to identify the log file.
-    )?;
-
-    // Run and log Cargo commands for updating Rust packages.
-    run_commands_and_log(
-        vec![
-            // Update all installed Cargo packages.
-            "~/.dev/cargo/bin/cargo install-update -a",
-        ],
-        &log_home,
-        "cargo",
-    )?;
-
-    // Run and log Rustup commands for updating Rust toolchains.
-    run_commands_and_log(
-        vec![
-            // Update Rust toolchains and components.
-            "~/.dev/cargo/bin/rustup update",
-        ],
-        &log_home,
-        "rustup",
-    )?;
-
-    // Run and log Neovim commands for updating plugins.
-
-    // Execute Neovim in headless mode to update plugins using the 'Lazy' plugin manager.
-    let status = Command::new("/opt/homebrew/bin/nvim") // Path to the Neovim executable.
-        .args(&[
-            "--headless",  // Run Neovim without a user interface.
-            "-V1",         // Set the verbosity level to 1 for logging.
-            "+Lazy! sync", // Run the ':Lazy sync' command to update plugins.
-            "+qa",         // Quit Neovim after running the command.
-        ])
-        .stdout(Stdio::piped()) // Capture standard output.
-        .stderr(Stdio::piped()) // Capture standard error.
-        .spawn()? // Start the process.
-        .wait_with_output()?; // Wait for the process to finish and collect the output.
-
-    // Get the current timestamp in the format "YYYY-MM-DD HH:MM:SS".
-    let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S");
-
-    // Define the path for the Neovim log file.
-    let log_path = format!("{}/cronup.nvim.log", log_home);
-
-    // Open the Neovim log file in append mode, creating it if it doesn't exist.
-    let mut nvim_log = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(&log_path)?;
-
-    // Write the status of the Neovim plugin update to the log file.
-    writeln!(
-        nvim_log,
-        "[{}] Neovim plugin update {}",
-        timestamp,
-        if status.status.success() {
-            // If the exit status is successful, indicate success.
-            "completed successfully"
-        } else {
-            // If the exit status is not successful, indicate failure.
-            "failed"
-        }
-    )?;
-
-    // Convert the standard output bytes to a UTF-8 string.
-    if let Ok(output) = String::from_utf8(status.stdout) {
-        // Iterate over each line in the output.
-        for line in output.lines() {
-            // Check if the line is not empty after trimming whitespace.
-            if !line.trim().is_empty() {
-                // Write the line to the log file with a timestamp.
-                writeln!(nvim_log, "[{}] {}", timestamp, line)?;
-            }
-        }
+impl Observer for Presenter {
+    fn updater_started(&mut self, name: &str, index: usize, total: usize) {
+        self.progress.updater_started(name, total);
+        self.status
+            .set_step(format!("{} ({} of {})", name, index + 1, total));
     }
 
-    // Convert the standard error bytes to a UTF-8 string.
-    if let Ok(error) = String::from_utf8(status.stderr) {
-        // Iterate over each line in the error output.
-        for line in error.lines() {
-            // Check if the line is not empty after trimming whitespace.
-            if !line.trim().is_empty() {
-                // Write the line to the log file with a timestamp.
-                writeln!(nvim_log, "[{}] {}", timestamp, line)?;
-            }
+    fn task_finished(&mut self, result: &TaskResult) {
+        if result.kind == TaskKind::Updater {
+            self.progress.updater_done();
+            self.status.record(result.name, result.failure.clone());
         }
     }
-
-    // Return `Ok(())` to indicate the program completed successfully.
-    Ok(())
 }
 
-// Function to check if the network is available.
-// It tries to establish a TCP connection to a known reliable DNS server.
-fn check_network() -> Result<bool, Box<dyn Error>> {
-    // Define the socket address for the DNS server at 9.9.9.9 on port 53.
-    let address: SocketAddr = "9.9.9.9:53".parse()?;
-
-    // Set a timeout duration of 5 seconds for the connection attempt.
-    let timeout = Duration::from_secs(5);
-
-    // Attempt to establish a TCP connection to the specified address with the timeout.
-    // The `is_ok()` method returns `true` if the connection was successful.
-    Ok(TcpStream::connect_timeout(&address, timeout).is_ok())
-}
-
-// Function to log that the system is offline and updates were aborted.
-fn log_offline(log_home: &str) -> Result<(), Box<dyn Error>> {
-    // Get the current timestamp.
-    let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S");
-
-    // Define the path for the offline log file.
-    let offline_log_path = format!("{}/cronup.offline.log", log_home);
+// The main function of the program. It returns a `Result` type that can contain an empty tuple `()`
+// on success or a boxed error (`Box<dyn Error>`) on failure.
+fn main() -> Result<(), Box<dyn Error>> {
+    // Retrieve the log directory path.
+    let log_home = config::log_home();
 
-    // Open the offline log file in append mode, creating it if it doesn't exist.
-    let mut file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(offline_log_path)?;
+    // `report` prints the trend report instead of running the updates.
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "report") {
+        return report::print_report(&log_home, args.iter().any(|arg| arg == "--html"));
+    }
 
-    // Write the offline status message to the log file with a timestamp.
-    writeln!(file, "[{}] System offline - updates aborted.", timestamp)?;
+    // `rollback <updater> <package>` returns a package to its version before the last upgrade.
+    if args.first().is_some_and(|arg| arg == "rollback") {
+        return match &args[1..] {
+            [updater, package] => macpaw_tasks::rollback(updater, package),
+            _ => Err("usage: cronup rollback brew <formula>".into()),
+        };
+    }
 
-    // Return `Ok(())` to indicate the function completed successfully.
-    Ok(())
-}
+    // `daemon` stays resident and starts the runs on schedule.
+    if args.first().is_some_and(|arg| arg == "daemon") {
+        return daemon::run(&log_home);
+    }
 
-// Function to run a list of shell commands and log their output.
-// It accepts a vector of command strings, the log directory, and a name for the log file.
-fn run_commands_and_log(
-    commands: Vec<&str>, // Vector of command strings to execute.
-    log_home: &str,      // Directory where the log file will be stored.
-    name: &str,          // Name used to identify the log file.
-) -> Result<(), Box<dyn Error>> {
-    // Define the path for the log file using the provided name.
-    let log_path = format!("{}/cronup.{}.log", log_home, name);
-
-    // Join the list of commands into a single string separated by '&&'.
-    // This ensures that the next command runs only if the previous one succeeds.
-    let shell_cmd = commands.join(" && ");
-
-    // Execute the combined shell command using `/bin/bash -c`.
-    let output = Command::new("/bin/bash")
-        .arg("-c") // Specify that the next argument is a command.
-        .arg(shell_cmd) // The shell command to execute.
-        .stdout(Stdio::piped()) // Capture standard output.
-        .stderr(Stdio::piped()) // Capture standard error.
-        .output()?; // Execute the command and wait for it to finish.
-
-    // Get the current timestamp.
-    let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S");
-
-    // Open the log file in append mode, creating it if it doesn't exist.
-    let mut log_file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(log_path)?;
-
-    // Create a buffered reader for the standard output.
-    let stdout = BufReader::new(&output.stdout[..]);
-
-    // Iterate over each line in the standard output.
-    for line in stdout.lines() {
-        // Handle any errors that may occur while reading lines.
-        let line = line?;
-        // Check if the line is not empty after trimming whitespace.
-        if !line.trim().is_empty() {
-            // Write the line to the log file with a timestamp.
-            writeln!(log_file, "[{}] {}", timestamp, line)?;
+    // `--check` reports outdated packages instead of running the updates.
+    if args.iter().any(|arg| arg == "--check") {
+        match connectivity::check() {
+            Connectivity::Online => {}
+            Connectivity::Portal(detail) => {
+                return Err(format!(
+                    "Captive portal detected ({}) - outdated check aborted.",
+                    detail
+                )
+                .into())
+            }
+            Connectivity::Offline => return Err("System offline - outdated check aborted.".into()),
         }
+        return outdated::write_report(&log_home);
     }
 
-    // Create a buffered reader for the standard error.
-    let stderr = BufReader::new(&output.stderr[..]);
-
-    // Iterate over each line in the standard error.
-    for line in stderr.lines() {
-        // Handle any errors that may occur while reading lines.
-        let line = line?;
-        // Check if the line is not empty after trimming whitespace.
-        if !line.trim().is_empty() {
-            // Write the line to the log file with a timestamp.
-            writeln!(log_file, "[{}] {}", timestamp, line)?;
-        }
+    // `--renotify` notifies known failures again instead of suppressing them.
+    let renotify = args.iter().any(|arg| arg == "--renotify");
+
+    // `bootstrap` installs the missing package managers before the updates, `--only` limits the run
+    // to a comma-separated list of updaters, and `--resume` resumes an interrupted run.
+    let only = args
+        .iter()
+        .position(|arg| arg == "--only")
+        .and_then(|index| args.get(index + 1))
+        .map(|names| {
+            names
+                .split(',')
+                .map(|name| name.trim().to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+    let options = RunOptions {
+        bootstrap: args.first().is_some_and(|arg| arg == "bootstrap"),
+        only,
+        resume: args.iter().any(|arg| arg == "--resume"),
+    };
+
+    // Post progress notifications as the updaters run, if enabled, and serve the status page.
+    let mut presenter = Presenter {
+        progress: notify::Progress::new(config::get_progress_enabled()),
+        status: status::Status::start(&log_home),
+    };
+    let run = macpaw_tasks::run(&log_home, options, &mut presenter)?;
+    if let RunStatus::AlreadyRunning(_) = run.status {
+        return Ok(());
     }
 
-    // Return `Ok(())` to indicate the function completed successfully.
-    Ok(())
+    // Collect the outcome of every task for the notification summary, the updaters whose output
+    // spiked, and the summaries of what changed for the emailed report.
+    let results: Vec<(&str, Option<String>)> = run
+        .tasks
+        .iter()
+        .map(|task| (task.name, task.failure.clone()))
+        .collect();
+    let anomalies: Vec<&str> = run
+        .tasks
+        .iter()
+        .filter(|task| task.anomaly)
+        .map(|task| task.name)
+        .collect();
+    let summaries: Vec<(&str, Vec<String>)> = run
+        .tasks
+        .iter()
+        .filter(|task| !task.summary.is_empty())
+        .map(|task| (task.name, task.summary.clone()))
+        .collect();
+
+    // Summarize the run in a notification and an email, and export its metrics, if enabled.
+    // These don't return errors: they ignore them, since a missing notification, report, or metric
+    // must never fail a run whose updates went through.
+    notify::notify_summary(&results, &anomalies, &log_home, renotify);
+    email::send_report(&results, &summaries);
+    if run.status == RunStatus::Completed {
+        let updater_metrics: Vec<metrics::UpdaterMetrics> = run
+            .tasks
+            .iter()
+            .filter(|task| task.kind == TaskKind::Updater)
+            .map(|task| metrics::UpdaterMetrics {
+                name: task.name,
+                duration: task.duration,
+                updated: task.updated,
+                failed: !task.succeeded(),
+            })
+            .collect();
+        metrics::export(&updater_metrics);
+    }
+
+    // Exit with a code that tells monitoring whether any updater failed or the network was unusable.
+    match run.exit_code() {
+        0 => Ok(()),
+        code => std::process::exit(code),
+    }
 }
@@ -0,0 +1,85 @@
+// Detection of metered networks, so bandwidth-heavy updaters can wait until the machine is off a
+// phone's hotspot. The interface and gateway of the default route are read from `route`:
+// - an iPhone's Personal Hotspot always hands out addresses in 172.20.10.0/28,
+// - an Android hotspot marks its DHCP offers with "ANDROID_METERED", which `ipconfig` shows, and
+// - interfaces listed in `CRONUP_METERED_INTERFACES` (e.g. `en7` for USB tethering) always count.
+// If the default route can't be read, the network counts as unmetered.
+
+// Import various modules from the Rust standard library.
+use std::{env, net::Ipv4Addr, sync::Mutex, time::Duration};
+
+// Running `route` and `ipconfig`.
+use crate::process;
+
+// The executables that report the default route and the DHCP packet of an interface.
+const ROUTE: &str = "/sbin/route";
+const IPCONFIG: &str = "/usr/sbin/ipconfig";
+
+// How long each of them may take before the network counts as unmetered.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+// The network an iPhone's Personal Hotspot assigns addresses from, and its prefix length.
+const IPHONE_HOTSPOT_NETWORK: Ipv4Addr = Ipv4Addr::new(172, 20, 10, 0);
+const IPHONE_HOTSPOT_PREFIX: u32 = 28;
+
+// The network the current run checked, once it was checked: why it is metered, if it is.
+static METERED: Mutex<Option<Option<String>>> = Mutex::new(None);
+
+// Function to determine whether the machine is on a metered network.
+// Returns why it is, e.g. "iPhone Personal Hotspot", or `None` if it isn't.
+// The network is checked once per run, so every updater sees the same answer.
+pub fn metered_network() -> Option<String> {
+    let Ok(mut metered) = METERED.lock() else {
+        return None;
+    };
+    metered.get_or_insert_with(detect).clone()
+}
+
+// Function to forget the network, so the next run checks it again.
+pub(crate) fn reset() {
+    if let Ok(mut metered) = METERED.lock() {
+        *metered = None;
+    }
+}
+
+// Function to check the default route for the signs of a metered network.
+fn detect() -> Option<String> {
+    let route = query(&format!("{} -n get default", ROUTE))?;
+    let field = |name: &str| {
+        route.lines().find_map(|line| {
+            let (key, value) = line.trim().split_once(':')?;
+            (key == name).then(|| value.trim().to_string())
+        })
+    };
+    let interface = field("interface")?;
+
+    let configured = env::var("CRONUP_METERED_INTERFACES").unwrap_or_default();
+    if configured
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .any(|name| name == interface)
+    {
+        return Some(format!("{} is configured as metered", interface));
+    }
+
+    let gateway = field("gateway").and_then(|gateway| gateway.parse::<Ipv4Addr>().ok());
+    if gateway.is_some_and(|gateway| {
+        let mask = u32::MAX << (32 - IPHONE_HOTSPOT_PREFIX);
+        u32::from(gateway) & mask == u32::from(IPHONE_HOTSPOT_NETWORK)
+    }) {
+        return Some(String::from("iPhone Personal Hotspot"));
+    }
+
+    let packet = query(&format!("{} getpacket {}", IPCONFIG, interface))?;
+    packet
+        .contains("ANDROID_METERED")
+        .then(|| String::from("Android hotspot"))
+}
+
+// Function to run a query command, returning its output if it succeeded.
+fn query(command_line: &str) -> Option<String> {
+    let output =
+        process::run_with_timeout(process::shell(command_line), QUERY_TIMEOUT, None).ok()?;
+    output
+        .succeeded()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
@@ -0,0 +1,181 @@
+// The export of run metrics for graphing update health across machines: the duration, number of
+// updated packages, and failure of every updater that ran.
+// - With `CRONUP_METRICS_DIR` set to node_exporter's textfile collector directory, they are written
+//   to `cronup.prom` there, replaced atomically so the collector never reads a partial file. The
+//   series of updaters that didn't run this time are kept from the previous file.
+// - With `CRONUP_STATSD` set to a `host:port`, they are sent as statsd gauges over UDP, named
+//   `<prefix>.<updater>.<metric>` with the prefix in `CRONUP_STATSD_PREFIX` (default "cronup").
+
+// Import various modules from the Rust standard library.
+use std::{
+    env, fs,
+    net::UdpSocket,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+// The statsd prefix used when `CRONUP_STATSD_PREFIX` is not set.
+const DEFAULT_STATSD_PREFIX: &str = "cronup";
+
+// The metrics of an updater's run.
+pub struct UpdaterMetrics {
+    pub name: &'static str,
+    pub duration: Duration,
+    // `None` if the updater can't tell how many packages it updated.
+    pub updated: Option<usize>,
+    pub failed: bool,
+}
+
+// Function to export the metrics of a run to every configured destination.
+pub fn export(metrics: &[UpdaterMetrics]) {
+    if let Some(directory) = setting("CRONUP_METRICS_DIR") {
+        let _ = write_textfile(Path::new(&directory), metrics);
+    }
+    if let Some(address) = setting("CRONUP_STATSD") {
+        let prefix =
+            setting("CRONUP_STATSD_PREFIX").unwrap_or_else(|| String::from(DEFAULT_STATSD_PREFIX));
+        let _ = send_statsd(&address, &prefix, metrics);
+    }
+}
+
+// Function to read a setting, treating an empty value as unset.
+fn setting(name: &str) -> Option<String> {
+    env::var(name)
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+// Function to write the metrics in the Prometheus text format to `cronup.prom` in `directory`.
+fn write_textfile(directory: &Path, metrics: &[UpdaterMetrics]) -> std::io::Result<()> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    // The series of the previous file, e.g. `cronup_updater_failed{updater="brew"} 0`, of the
+    // updaters that didn't run this time.
+    let previous = fs::read_to_string(directory.join("cronup.prom")).unwrap_or_default();
+    let kept: Vec<&str> = previous
+        .lines()
+        .filter(|line| {
+            series_updater(line)
+                .is_some_and(|name| !metrics.iter().any(|updater| updater.name == name))
+        })
+        .collect();
+    let mut text = String::new();
+    let mut family = |name: &str, help: &str, value: &dyn Fn(&UpdaterMetrics) -> Option<String>| {
+        text.push_str(&format!(
+            "# HELP {} {}\n# TYPE {} gauge\n",
+            name, help, name
+        ));
+        for updater in metrics {
+            if let Some(value) = value(updater) {
+                text.push_str(&format!(
+                    "{}{{updater=\"{}\"}} {}\n",
+                    name, updater.name, value
+                ));
+            }
+        }
+        for line in &kept {
+            if line.split('{').next() == Some(name) {
+                text.push_str(line);
+                text.push('\n');
+            }
+        }
+    };
+    family(
+        "cronup_updater_duration_seconds",
+        "How long the updater's last run took.",
+        &|updater| Some(format!("{:.3}", updater.duration.as_secs_f64())),
+    );
+    family(
+        "cronup_updater_packages_updated",
+        "How many packages the updater's last run updated.",
+        &|updater| updater.updated.map(|count| count.to_string()),
+    );
+    family(
+        "cronup_updater_failed",
+        "Whether the updater's last run failed.",
+        &|updater| Some(u8::from(updater.failed).to_string()),
+    );
+    family(
+        "cronup_updater_last_run_timestamp_seconds",
+        "When the updater last ran, in seconds since the epoch.",
+        &|_| Some(now.to_string()),
+    );
+
+    // Write to a hidden file first, which the collector ignores, and move it into place.
+    let temporary = directory.join(".cronup.prom.tmp");
+    fs::write(&temporary, text)?;
+    fs::rename(&temporary, directory.join("cronup.prom"))
+}
+
+// Function to get the updater a series line of `cronup.prom` is for, or `None` for other lines.
+fn series_updater(line: &str) -> Option<&str> {
+    line.split_once("{updater=\"")?
+        .1
+        .split_once('"')
+        .map(|(name, _)| name)
+}
+
+// Function to send the metrics as statsd gauges, one datagram per metric.
+fn send_statsd(address: &str, prefix: &str, metrics: &[UpdaterMetrics]) -> std::io::Result<()> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.connect(address)?;
+    for updater in metrics {
+        let mut lines = vec![
+            format!(
+                "{}.{}.duration_ms:{}|g",
+                prefix,
+                updater.name,
+                updater.duration.as_millis()
+            ),
+            format!(
+                "{}.{}.failed:{}|g",
+                prefix,
+                updater.name,
+                u8::from(updater.failed)
+            ),
+        ];
+        if let Some(updated) = updater.updated {
+            lines.push(format!("{}.{}.updated:{}|g", prefix, updater.name, updated));
+        }
+        for line in lines {
+            let _ = socket.send(line.as_bytes());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn textfile_keeps_the_series_of_updaters_that_did_not_run() {
+        let directory = env::temp_dir().join(format!("cronup-metrics-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let run = |name, failed| {
+            let metrics = [UpdaterMetrics {
+                name,
+                duration: Duration::from_secs(2),
+                updated: Some(1),
+                failed,
+            }];
+            write_textfile(&directory, &metrics).unwrap();
+            fs::read_to_string(directory.join("cronup.prom")).unwrap()
+        };
+        run("brew", true);
+        run("npm", false);
+        let text = run("brew", false);
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert!(text.contains("cronup_updater_failed{updater=\"brew\"} 0\n"));
+        assert!(!text.contains("cronup_updater_failed{updater=\"brew\"} 1"));
+        assert!(text.contains("cronup_updater_failed{updater=\"npm\"} 0\n"));
+        assert_eq!(
+            text.matches("# TYPE cronup_updater_failed gauge").count(),
+            1
+        );
+        assert_eq!(text.matches("cronup_updater_duration_seconds{").count(), 2);
+    }
+}
@@ -0,0 +1,243 @@
+// macOS notifications posted through `osascript`: a summary at the end of a run,
+// progress updates for long runs, and notices about updaters waiting for input.
+// Notified failures are recorded in `cronup.notified` in `LOG_HOME`, one "<name>\t<signature>" line
+// each, so a long-standing breakage is only notified once until its signature changes.
+// The summary can also be posted to a webhook in `CRONUP_WEBHOOK_URL` (e.g. Slack, Discord, or ntfy)
+// through curl, as JSON with the summary in "text" (Slack) and "content" (Discord) and every result in
+// "results", or as plain text with `CRONUP_WEBHOOK_FORMAT=text` (ntfy).
+
+// Import various modules from the Rust standard library.
+use std::{
+    collections::HashSet,
+    env, fs,
+    io::Write,
+    process::{Command, Stdio},
+};
+
+// The JSON quoting of the status page.
+use crate::status::json_string;
+
+// The title shown on cronup's notifications.
+const NOTIFICATION_TITLE: &str = "cronup";
+
+// The executable that posts to the webhook, and how long it may take.
+const CURL: &str = "/usr/bin/curl";
+const WEBHOOK_TIMEOUT_SECS: u64 = 30;
+
+// Function to post a notification summarizing the outcome of every check and updater,
+// e.g. "disk ok, brew ok, cargo failed, rustup ok, nvim ok".
+// Each result carries `None` on success or the signature of the failure. Failures whose signature
+// was already notified by an earlier run don't trigger a notification again unless `renotify` is set.
+// The names in `anomalies` are marked with a log volume anomaly, which always counts as news.
+pub fn notify_summary(
+    results: &[(&str, Option<String>)],
+    anomalies: &[&str],
+    log_home: &str,
+    renotify: bool,
+) {
+    let summary = results
+        .iter()
+        .map(|(name, failure)| {
+            format!(
+                "{} {}{}",
+                name,
+                if failure.is_none() { "ok" } else { "failed" },
+                if anomalies.contains(name) {
+                    " (log volume anomaly)"
+                } else {
+                    ""
+                }
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    // Collect the failures of this run as manifest lines.
+    let failures: Vec<String> = results
+        .iter()
+        .filter_map(|(name, failure)| {
+            failure
+                .as_ref()
+                .map(|signature| format!("{}\t{}", name, signature.replace(['\t', '\n'], " ")))
+        })
+        .collect();
+
+    // Only failures that weren't notified before count, unless asked to notify them again.
+    let manifest_path = format!("{}/cronup.notified", log_home);
+    let notified: HashSet<String> = if renotify {
+        HashSet::new()
+    } else {
+        fs::read_to_string(&manifest_path)
+            .unwrap_or_default()
+            .lines()
+            .map(String::from)
+            .collect()
+    };
+    let new_failure = failures.iter().any(|failure| !notified.contains(failure));
+
+    let news = new_failure || !anomalies.is_empty();
+    let notified = notify(&summary, news);
+    let posted = post_webhook(&summary, results, news);
+    if notified || posted {
+        // Record exactly the current failures, so a fixed failure is notified again if it returns.
+        let mut manifest = failures.join("\n");
+        if !manifest.is_empty() {
+            manifest.push('\n');
+        }
+        let _ = fs::write(&manifest_path, manifest);
+    }
+}
+
+// Function to post a run summary notification if `CRONUP_NOTIFY` asks for it:
+// `always` notifies after every run, `failure` only when something newly failed (or spiked),
+// and any other value (or none) disables summary notifications.
+// Returns whether summary notifications are enabled at all.
+fn notify(message: &str, new_failure: bool) -> bool {
+    let (enabled, post_now) = match env::var("CRONUP_NOTIFY").as_deref() {
+        Ok("always") => (true, true),
+        Ok("failure") => (true, new_failure),
+        _ => (false, false),
+    };
+    if post_now {
+        post(message);
+    }
+    enabled
+}
+
+// Function to post the summary to the webhook if `CRONUP_WEBHOOK_URL` is set, as `CRONUP_WEBHOOK_ON`
+// asks for it: `always` posts after every run, and `failure` (the default) only when something newly
+// failed (or spiked). Returns whether the webhook is configured at all.
+fn post_webhook(summary: &str, results: &[(&str, Option<String>)], new_failure: bool) -> bool {
+    let Some(url) = env::var("CRONUP_WEBHOOK_URL")
+        .ok()
+        .map(|url| url.trim().to_string())
+        .filter(|url| !url.is_empty())
+    else {
+        return false;
+    };
+    let post_now = match env::var("CRONUP_WEBHOOK_ON").as_deref() {
+        Ok("always") => true,
+        _ => new_failure,
+    };
+    if !post_now {
+        return true;
+    }
+
+    let (content_type, body) = if env::var("CRONUP_WEBHOOK_FORMAT").as_deref() == Ok("text") {
+        ("text/plain", format!("{}\n", summary))
+    } else {
+        let results = results
+            .iter()
+            .map(|(name, failure)| {
+                format!(
+                    "{{\"name\":{},\"ok\":{},\"failure\":{}}}",
+                    json_string(name),
+                    failure.is_none(),
+                    failure
+                        .as_deref()
+                        .map_or_else(|| String::from("null"), json_string)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        (
+            "application/json",
+            format!(
+                "{{\"text\":{},\"content\":{},\"results\":[{}]}}",
+                json_string(summary),
+                json_string(summary),
+                results
+            ),
+        )
+    };
+
+    // The body is passed on standard input to keep it out of the process list.
+    if let Ok(mut child) = Command::new(CURL)
+        .args([
+            "-fsS",
+            "-m",
+            &WEBHOOK_TIMEOUT_SECS.to_string(),
+            "-X",
+            "POST",
+        ])
+        .args(["-H", &format!("Content-Type: {}", content_type)])
+        .args(["--data-binary", "@-"])
+        .arg(url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+    {
+        if let Some(mut stdin) = child.stdin.take() {
+            let _ = stdin.write_all(body.as_bytes());
+        }
+        let _ = child.wait();
+    }
+    true
+}
+
+// Function to post a macOS notification with the given message.
+pub fn post(message: &str) {
+    // Escape the strings so they can be embedded in AppleScript string literals.
+    let escape = |text: &str| text.replace('\\', "\\\\").replace('"', "\\\"");
+    let script = format!(
+        "display notification \"{}\" with title \"{}\"",
+        escape(message),
+        escape(NOTIFICATION_TITLE)
+    );
+
+    let _ = Command::new("/usr/bin/osascript")
+        .arg("-e")
+        .arg(script)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+}
+
+// Progress notifications for long runs: one when the updaters start, e.g. "started, 6 tasks", and one
+// at the halfway point with the step that runs next, e.g. "3 of 6 tasks done, running brew".
+// They follow the run's observer callbacks, so nothing is posted between them.
+pub struct Progress {
+    enabled: bool,        // Whether to post the notifications at all.
+    done: usize,          // How many updaters finished.
+    started: bool,        // Whether the start was notified.
+    halfway_posted: bool, // Whether the halfway point was notified.
+}
+
+impl Progress {
+    // Creates the progress notifications of a run, which post nothing if `enabled` is false.
+    pub fn new(enabled: bool) -> Self {
+        Progress {
+            enabled,
+            done: 0,
+            started: false,
+            halfway_posted: false,
+        }
+    }
+
+    // Notes that an updater starts, notifying the start of the run or the halfway point if due.
+    pub fn updater_started(&mut self, name: &str, total: usize) {
+        if !self.enabled {
+            return;
+        }
+        if !self.started {
+            self.started = true;
+            post(&format!(
+                "started, {} {}",
+                total,
+                if total == 1 { "task" } else { "tasks" }
+            ));
+        } else if !self.halfway_posted && self.done * 2 >= total {
+            self.halfway_posted = true;
+            post(&format!(
+                "{} of {} tasks done, running {}",
+                self.done, total, name
+            ));
+        }
+    }
+
+    // Notes that an updater finished.
+    pub fn updater_done(&mut self) {
+        self.done += 1;
+    }
+}
@@ -0,0 +1,6 @@
+// A shim around `os_log_with_type`, which is a macro and so can't be called from Rust (see oslog.rs).
+#include <os/log.h>
+
+void cronup_os_log(os_log_t log, os_log_type_t type, const char *message) {
+    os_log_with_type(log, type, "%{public}s", message);
+}
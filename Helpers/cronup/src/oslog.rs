@@ -0,0 +1,62 @@
+// Mirrors cronup's own log messages into the macOS unified log when `CRONUP_OSLOG[_<NAME>]` is set
+// to a non-zero number, so Console.app and `log stream --predicate 'subsystem == "gg.hw.cronup"'` can
+// follow a run live. Every log file is a category, e.g. "brew" or "run"; the output of the commands stays in
+// the files only.
+// Messages are logged as public, since they hold nothing the files don't. They go through a C shim
+// around `os_log_with_type` (oslog.c, compiled by build.rs), since the `os_log` API is a set of macros.
+
+// Import various modules from the Rust standard library.
+use std::{
+    collections::HashMap,
+    ffi::{c_char, c_void, CString},
+    sync::Mutex,
+};
+
+// The subsystem cronup logs under.
+const SUBSYSTEM: &str = "gg.hw.cronup";
+
+// The default log type, and the error type used for warnings and failures.
+const OS_LOG_TYPE_DEFAULT: u8 = 0x00;
+const OS_LOG_TYPE_ERROR: u8 = 0x10;
+
+extern "C" {
+    // Creates a log object for a subsystem and category; it is never released.
+    fn os_log_create(subsystem: *const c_char, category: *const c_char) -> *mut c_void;
+    // Logs a message as a public string through `os_log_with_type`, in the C shim in oslog.c.
+    fn cronup_os_log(log: *mut c_void, log_type: u8, message: *const c_char);
+}
+
+// Function to write a message to the unified log under the given category.
+pub fn log(category: &str, message: &str) {
+    // The log objects are kept for the whole run, one per category; the pointers are stored as
+    // addresses because raw pointers can't be shared between threads.
+    static LOGS: Mutex<Option<HashMap<String, usize>>> = Mutex::new(None);
+    let Ok(mut logs) = LOGS.lock() else {
+        return;
+    };
+    let logs = logs.get_or_insert_with(HashMap::new);
+    let log = match logs.get(category) {
+        Some(log) => *log,
+        None => {
+            let (Ok(subsystem), Ok(name)) = (CString::new(SUBSYSTEM), CString::new(category))
+            else {
+                return;
+            };
+            // Both strings are valid and NUL-terminated for the duration of the call.
+            let log = unsafe { os_log_create(subsystem.as_ptr(), name.as_ptr()) } as usize;
+            logs.insert(category.to_string(), log);
+            log
+        }
+    };
+
+    let Ok(message) = CString::new(message.replace('\0', "")) else {
+        return;
+    };
+    let log_type = if message.to_bytes().starts_with(b"WARNING") {
+        OS_LOG_TYPE_ERROR
+    } else {
+        OS_LOG_TYPE_DEFAULT
+    };
+    // The message is NUL-terminated and outlives the call.
+    unsafe { cronup_os_log(log as *mut c_void, log_type, message.as_ptr()) };
+}
@@ -0,0 +1,47 @@
+// The report written by `cronup --check`: every installed updater that can tell is asked for its
+// outdated packages, without upgrading anything. The report is printed and written to
+// `cronup.outdated` in `LOG_HOME`, replacing the previous one.
+
+// Import various modules from the Rust standard library.
+use std::{error::Error, fs};
+
+// The updaters to query, the commands' runner, and the timestamp format of the run history.
+use crate::{config, history, process, updaters};
+
+// Function to query every installed updater for outdated packages, then print and save the report.
+pub fn write_report(log_home: &str) -> Result<(), Box<dyn Error>> {
+    let mut report = format!("Outdated packages as of {}\n", history::timestamp());
+
+    for updater in updaters::registry() {
+        let commands = updater.check_commands();
+        if commands.is_empty() || !updater.detect() {
+            continue;
+        }
+        report.push_str(&format!("\n==> {}\n", updater.name()));
+
+        // Run every query even if an earlier one fails, e.g. because `npm outdated` exits
+        // with an error whenever something is outdated.
+        let name = updater.name();
+        let command = process::shell(&commands.join("; "));
+        let output = process::run_with_timeout(command, config::get_timeout(name), None)?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let listing = stdout.trim_end();
+        if output.status.is_none() {
+            report.push_str("Timed out.\n");
+        } else if !output.succeeded() && listing.is_empty() {
+            // Without any listing, the error output is the only clue to what went wrong.
+            report.push_str(&format!("Check failed: {}\n", stderr.trim_end()));
+        } else if listing.is_empty() {
+            report.push_str("Nothing outdated.\n");
+        } else {
+            report.push_str(listing);
+            report.push('\n');
+        }
+    }
+
+    print!("{}", report);
+    fs::write(format!("{}/cronup.outdated", log_home), report)?;
+    Ok(())
+}
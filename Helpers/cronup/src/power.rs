@@ -0,0 +1,43 @@
+// The power source of the machine, so heavy updaters can wait until a laptop is on AC power.
+// It is read from `pmset -g batt`, whose first line names the source, e.g. "Now drawing from 'Battery Power'".
+// If `pmset` is missing or fails (e.g. on a Mac without a battery), the machine counts as on AC power.
+
+// Import various modules from the Rust standard library.
+use std::{sync::Mutex, time::Duration};
+
+// Running `pmset`.
+use crate::process;
+
+// The executable that reports the power source.
+const PMSET: &str = "/usr/bin/pmset";
+
+// How long `pmset` may take before the power source counts as unknown.
+const PMSET_TIMEOUT: Duration = Duration::from_secs(10);
+
+// The power source the current run read, once it was read.
+static ON_BATTERY: Mutex<Option<bool>> = Mutex::new(None);
+
+// Function to determine whether the machine is running on battery power.
+// The power source is read once per run, so every updater sees the same answer.
+pub fn on_battery() -> bool {
+    let Ok(mut on_battery) = ON_BATTERY.lock() else {
+        return false;
+    };
+    *on_battery.get_or_insert_with(|| {
+        let command = process::shell(&format!("{} -g batt", PMSET));
+        match process::run_with_timeout(command, PMSET_TIMEOUT, None) {
+            Ok(output) if output.succeeded() => String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .next()
+                .is_some_and(|line| line.contains("'Battery Power'")),
+            _ => false,
+        }
+    })
+}
+
+// Function to forget the power source, so the next run reads it again.
+pub(crate) fn reset() {
+    if let Ok(mut on_battery) = ON_BATTERY.lock() {
+        *on_battery = None;
+    }
+}
@@ -0,0 +1,657 @@
+// Running external commands in their own process group with a timeout.
+// If the timeout expires, the entire process group is terminated so that grandchildren
+// (e.g. the tools spawned by `brew upgrade`) don't outlive the run.
+// Output of extremely chatty commands can be sampled: only its first and last lines and every M-th
+// line in between are kept, and every gap is replaced by a marker stating how many lines were omitted.
+// The CPU time and peak memory of a command's process tree are taken from `wait4` when it is reaped.
+// Shell commands run in a stable locale (see `config::get_locale`), since their output is parsed.
+// A command whose output stalls on a line that looks like a question (e.g. "Continue? [y/N] " without
+// a line break) is taken to wait for input. Depending on `config::get_prompt_handling`, the prompt is
+// answered through standard input, the process group is killed, or it is left waiting and notified.
+// A command can also be given an inactivity limit: if it writes nothing at all for that long, e.g.
+// because it waits on something that will never come, it is taken to hang and its process group is
+// terminated like at the timeout.
+
+// Import various modules from the Rust standard library.
+use std::{
+    // `VecDeque` holds back the last lines of sampled output.
+    collections::VecDeque,
+    // The `Error` trait is used for error handling.
+    error::Error,
+    // The `BufRead`, `BufReader`, and `Read` traits are used for draining the child's output pipes,
+    // `Write` for answering prompts, and `io::Error` for reporting a failed `wait4`.
+    io::{self, BufRead, BufReader, Read, Write},
+    // The `CommandExt` trait is used for placing child processes in their own process group, and
+    // the `ExitStatusExt` trait for the status of an empty sequence of commands.
+    os::unix::process::{CommandExt, ExitStatusExt},
+    // The `Command`, `ExitStatus`, and `Stdio` structs are used for running external commands and handling their I/O.
+    process::{Command, ExitStatus, Stdio},
+    // `Arc` and `Mutex` share the time and tail of the latest output with the reader threads.
+    sync::{Arc, Mutex},
+    // The `thread` module is used for draining child output while waiting for the child to exit.
+    thread,
+    // The `Duration` and `Instant` structs are used for specifying and measuring time intervals.
+    time::{Duration, Instant},
+};
+
+// The locale setting applied to shell commands, and the notification posted about a prompt.
+use crate::{config, notify};
+
+// How long a timed-out process group gets to exit after SIGTERM before it receives SIGKILL.
+const KILL_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+// How often a running child is polled for exit while waiting on its timeout.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+// How many bytes of the latest output are kept to recognize a prompt.
+const PROMPT_TAIL_BYTES: usize = 256;
+
+// How many prompts a command may get answered before it counts as stuck and is killed.
+const MAX_ANSWERS: usize = 5;
+
+// The `wait4` option that makes it return immediately if the child is still running.
+const WNOHANG: i32 = 1;
+
+// The `struct timeval` of `<sys/time.h>` as laid out on 64-bit macOS.
+#[repr(C)]
+struct TimeVal {
+    tv_sec: i64,
+    tv_usec: i32,
+}
+
+// The `struct rusage` of `<sys/resource.h>`; only the CPU times and the peak memory are read.
+#[repr(C)]
+struct RUsage {
+    ru_utime: TimeVal,
+    ru_stime: TimeVal,
+    ru_maxrss: i64,
+    ru_other: [i64; 13],
+}
+
+extern "C" {
+    // Waits for a child like `waitpid` and also reports the resources it used.
+    fn wait4(pid: i32, status: *mut i32, options: i32, rusage: *mut RUsage) -> i32;
+}
+
+// The resources used by a command and the descendants it waited for, e.g. the tools `brew` spawns.
+#[derive(Clone, Copy, Default)]
+pub struct Usage {
+    // The CPU time spent in user and system mode.
+    pub cpu: Duration,
+    // The peak resident set size of the largest process, in bytes.
+    pub peak_rss: u64,
+}
+
+impl Usage {
+    // Returns the usage of this command and another one that ran after it.
+    pub fn combine(self, other: Usage) -> Usage {
+        Usage {
+            cpu: self.cpu + other.cpu,
+            peak_rss: self.peak_rss.max(other.peak_rss),
+        }
+    }
+}
+
+// Limits how much of a command's output is kept.
+#[derive(Clone, Copy)]
+pub struct Sampling {
+    // The number of first and of last lines kept.
+    pub lines: usize,
+    // Every how many lines one is kept between the first and the last lines.
+    pub every: usize,
+}
+
+// What to do when a command waits for input.
+pub enum PromptAction {
+    // Write the given answer and a line break to the command's standard input.
+    Answer(String),
+    // Kill the process group, so the command fails right away instead of at its timeout.
+    Kill,
+    // Leave the command waiting and post a notification naming the given updater.
+    Notify(String),
+}
+
+// How prompts of a command are recognized and handled.
+pub struct PromptHandling {
+    // How long the output must stall on a prompt-like line before the command counts as waiting.
+    pub idle: Duration,
+    pub action: PromptAction,
+}
+
+// The time and tail of a command's latest output on either stream, updated by the reader threads.
+struct Activity {
+    last_output: Instant,
+    tail: Vec<u8>,
+}
+
+// A stream that records every read in the shared `Activity`.
+struct Watched<R> {
+    stream: R,
+    activity: Arc<Mutex<Activity>>,
+}
+
+impl<R: Read> Read for Watched<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let read = self.stream.read(buffer)?;
+        if read > 0 {
+            if let Ok(mut activity) = self.activity.lock() {
+                activity.last_output = Instant::now();
+                activity.tail.extend_from_slice(&buffer[..read]);
+                let excess = activity.tail.len().saturating_sub(PROMPT_TAIL_BYTES);
+                activity.tail.drain(..excess);
+            }
+        }
+        Ok(read)
+    }
+}
+
+// The captured result of a command that was run with a timeout.
+pub struct CommandOutput {
+    // The exit status of the command, or `None` if it timed out or was killed at a prompt.
+    pub status: Option<ExitStatus>,
+    // Everything the command wrote to standard output.
+    pub stdout: Vec<u8>,
+    // Everything the command wrote to standard error.
+    pub stderr: Vec<u8>,
+    // The resources the command used.
+    pub usage: Usage,
+    // Every prompt the command was found waiting at, in order.
+    pub prompts: Vec<String>,
+    // The prompt the command was killed at, if it was killed for waiting for input.
+    pub killed_at_prompt: Option<String>,
+    // The inactivity limit the command was killed at, if it hung without output.
+    pub killed_for_inactivity: Option<Duration>,
+}
+
+impl CommandOutput {
+    // Returns the successful, empty output of running no command at all, to append commands to.
+    pub fn empty() -> Self {
+        CommandOutput {
+            status: Some(ExitStatus::from_raw(0)),
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+            usage: Usage::default(),
+            prompts: Vec::new(),
+            killed_at_prompt: None,
+            killed_for_inactivity: None,
+        }
+    }
+
+    // Appends the output of a command that ran after this one. The combined status stays that of
+    // the first command that failed, so the result only succeeds if every command did.
+    pub fn append(&mut self, next: CommandOutput) {
+        self.stdout.extend(next.stdout);
+        self.stderr.extend(next.stderr);
+        self.usage = self.usage.combine(next.usage);
+        self.prompts.extend(next.prompts);
+        if self.succeeded() {
+            self.status = next.status;
+            self.killed_at_prompt = next.killed_at_prompt;
+            self.killed_for_inactivity = next.killed_for_inactivity;
+        }
+    }
+
+    // Returns `true` if the command exited on its own with a successful status.
+    pub fn succeeded(&self) -> bool {
+        self.status.is_some_and(|status| status.success())
+    }
+
+    // Returns a short description of how the command failed, or `None` if it succeeded.
+    // It combines the exit status with the last line of error output (or of standard output if
+    // there is none), so the same breakage yields the same signature on every run.
+    pub fn failure_signature(&self) -> Option<String> {
+        let status = match self.status {
+            Some(status) if status.success() => return None,
+            Some(status) => match status.code() {
+                Some(code) => format!("exit {}", code),
+                None => String::from("killed by signal"),
+            },
+            // The prompt is the last line of output, so it isn't repeated.
+            None => match &self.killed_at_prompt {
+                Some(prompt) => return Some(format!("interactive prompt detected: {}", prompt)),
+                None if self.killed_for_inactivity.is_some() => String::from("hung"),
+                None => String::from("timed out"),
+            },
+        };
+        let last_line = [&self.stderr, &self.stdout].iter().find_map(|stream| {
+            String::from_utf8_lossy(stream)
+                .lines()
+                .map(str::trim)
+                .rfind(|line| !line.is_empty() && !line.starts_with("[cronup:"))
+                .map(String::from)
+        });
+        Some(match last_line {
+            Some(line) => format!("{}: {}", status, line),
+            None => status,
+        })
+    }
+}
+
+// Function to build a command that runs a shell command line through `/bin/bash -c`.
+// The command runs in the configured locale, so its output can be parsed the same way everywhere.
+pub fn shell(command_line: &str) -> Command {
+    let mut command = Command::new("/bin/bash");
+    command
+        .arg("-c") // Specify that the next argument is a command.
+        .arg(command_line); // The shell command to execute.
+    if let Some(locale) = config::get_locale() {
+        // `LC_ALL` overrides every `LC_*` variable the user set, and `LANG` is read by some tools alone.
+        command.env("LC_ALL", &locale).env("LANG", locale);
+    }
+    command
+}
+
+// Function to run a command in its own process group and wait for it with a timeout.
+// Output is kept in full unless `sampling` is given.
+pub fn run_with_timeout(
+    command: Command,
+    timeout: Duration,
+    sampling: Option<Sampling>,
+) -> Result<CommandOutput, Box<dyn Error>> {
+    run_watched(command, timeout, sampling, None, None)
+}
+
+// Function to run a command like `run_with_timeout`, handling the prompts it waits at as `prompts`
+// says. Without `prompts`, a prompt is left waiting until the timeout. With `inactivity`, a command
+// that writes nothing for that long is killed as hung.
+pub fn run_watched(
+    mut command: Command,
+    timeout: Duration,
+    sampling: Option<Sampling>,
+    prompts: Option<&PromptHandling>,
+    inactivity: Option<Duration>,
+) -> Result<CommandOutput, Box<dyn Error>> {
+    // Standard input only stays open when prompts are answered; otherwise reads get end of file.
+    let answering = matches!(
+        prompts,
+        Some(PromptHandling {
+            action: PromptAction::Answer(_),
+            ..
+        })
+    );
+
+    // Start the command as the leader of a new process group whose id equals its pid.
+    let mut child = command
+        .stdin(if answering {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped()) // Capture standard output.
+        .stderr(Stdio::piped()) // Capture standard error.
+        .process_group(0)
+        .spawn()?;
+    let mut stdin = child.stdin.take();
+
+    // Drain both pipes on background threads so a chatty child can't block on a full pipe.
+    let activity = Arc::new(Mutex::new(Activity {
+        last_output: Instant::now(),
+        tail: Vec::new(),
+    }));
+    let stdout_reader = spawn_reader(
+        child.stdout.take().map(|stream| Watched {
+            stream,
+            activity: Arc::clone(&activity),
+        }),
+        sampling,
+    );
+    let stderr_reader = spawn_reader(
+        child.stderr.take().map(|stream| Watched {
+            stream,
+            activity: Arc::clone(&activity),
+        }),
+        sampling,
+    );
+
+    // Poll the child until it exits, the deadline passes, or it is killed at a prompt.
+    // The child is reaped through `wait4` rather than `Child::try_wait` to learn its resource usage.
+    let pid = child.id();
+    let deadline = Instant::now() + timeout;
+    let mut found_prompts = Vec::new();
+    let mut killed_at_prompt = None;
+    let mut killed_for_inactivity = None;
+    // The output a prompt was last handled at, so every stall is handled only once.
+    let mut handled_output = None;
+    let (status, usage) = loop {
+        if let Some((status, usage)) = reap(pid, false)? {
+            break (Some(status), usage);
+        }
+        if Instant::now() >= deadline {
+            break (None, terminate(pid)?);
+        }
+        let hung = inactivity.filter(|limit| {
+            activity
+                .lock()
+                .is_ok_and(|activity| activity.last_output.elapsed() >= *limit)
+        });
+        if hung.is_some() {
+            killed_for_inactivity = hung;
+            break (None, terminate(pid)?);
+        }
+
+        let waiting = prompts.and_then(|prompts| {
+            let activity = activity.lock().ok()?;
+            if activity.last_output.elapsed() < prompts.idle
+                || handled_output == Some(activity.last_output)
+            {
+                return None;
+            }
+            handled_output = Some(activity.last_output);
+            prompt_line(&activity.tail).map(|prompt| (prompts, prompt))
+        });
+        if let Some((prompts, prompt)) = waiting {
+            found_prompts.push(prompt.clone());
+            match &prompts.action {
+                PromptAction::Answer(answer) if found_prompts.len() <= MAX_ANSWERS => {
+                    // A command that exited in the meantime is reaped on the next poll.
+                    if let Some(stdin) = stdin.as_mut() {
+                        let _ = stdin.write_all(format!("{}\n", answer).as_bytes());
+                    }
+                }
+                PromptAction::Notify(name) => {
+                    notify::post(&format!("{} is waiting for input: {}", name, prompt));
+                }
+                _ => {
+                    killed_at_prompt = Some(prompt);
+                    break (None, terminate(pid)?);
+                }
+            }
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+    drop(stdin);
+
+    Ok(CommandOutput {
+        status,
+        stdout: stdout_reader.join().unwrap_or_default(),
+        stderr: stderr_reader.join().unwrap_or_default(),
+        usage,
+        prompts: found_prompts,
+        killed_at_prompt,
+        killed_for_inactivity,
+    })
+}
+
+// Function to terminate a process group and reap its leader, returning the leader's resource usage.
+fn terminate(pid: u32) -> io::Result<Usage> {
+    // Ask the whole group to terminate, then force it if it doesn't comply in time.
+    kill_process_group(pid, "TERM");
+    let grace_deadline = Instant::now() + KILL_GRACE_PERIOD;
+    let mut reaped = reap(pid, false)?;
+    while reaped.is_none() && Instant::now() < grace_deadline {
+        thread::sleep(POLL_INTERVAL);
+        reaped = reap(pid, false)?;
+    }
+    kill_process_group(pid, "KILL");
+    // Reap the child so it doesn't linger as a zombie.
+    if reaped.is_none() {
+        reaped = reap(pid, true)?;
+    }
+    Ok(reaped.map_or_else(Usage::default, |(_, usage)| usage))
+}
+
+// Function to recognize a prompt at the end of a command's output: a last line that isn't finished
+// by a line break and ends like a question, e.g. "Proceed? [y/N] " or "Password:".
+fn prompt_line(tail: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(tail);
+    if text.ends_with('\n') {
+        return None;
+    }
+    let line = text.rsplit(['\n', '\r']).next()?.trim();
+    line.ends_with(['?', ':', ']', ')', '>'])
+        .then(|| line.to_string())
+}
+
+// Function to reap a child, returning its exit status and resource usage.
+// Without `block`, returns `None` if the child is still running.
+fn reap(pid: u32, block: bool) -> io::Result<Option<(ExitStatus, Usage)>> {
+    let mut status = 0;
+    // `RUsage` only holds integers, so all zeroes is a valid value.
+    let mut rusage: RUsage = unsafe { std::mem::zeroed() };
+    let options = if block { 0 } else { WNOHANG };
+    loop {
+        // Both pointers refer to live, correctly sized locals.
+        match unsafe { wait4(pid as i32, &mut status, options, &mut rusage) } {
+            0 => return Ok(None),
+            -1 => {
+                let error = io::Error::last_os_error();
+                if error.kind() != io::ErrorKind::Interrupted {
+                    return Err(error);
+                }
+            }
+            _ => break,
+        }
+    }
+
+    let seconds = |time: &TimeVal| {
+        Duration::from_secs(time.tv_sec.max(0) as u64)
+            + Duration::from_micros(time.tv_usec.max(0) as u64)
+    };
+    Ok(Some((
+        ExitStatus::from_raw(status),
+        Usage {
+            cpu: seconds(&rusage.ru_utime) + seconds(&rusage.ru_stime),
+            // macOS reports the peak resident set size in bytes.
+            peak_rss: rusage.ru_maxrss.max(0) as u64,
+        },
+    )))
+}
+
+// Function to read a child's output stream to the end on a separate thread.
+fn spawn_reader<R: Read + Send + 'static>(
+    stream: Option<R>,
+    sampling: Option<Sampling>,
+) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buffer = Vec::new();
+        // Partial output is still worth logging, so read errors are ignored.
+        match (stream, sampling) {
+            (Some(mut stream), None) => {
+                let _ = stream.read_to_end(&mut buffer);
+            }
+            (Some(stream), Some(sampling)) => {
+                let _ = read_sampled(stream, sampling, &mut buffer);
+            }
+            (None, _) => {}
+        }
+        buffer
+    })
+}
+
+// Function to read a stream line by line, keeping its first and last `lines` lines and every
+// `every`-th line in between. A marker line is added wherever lines were dropped.
+// The last lines are held back until the end of the stream, since only then is it known which they are.
+fn read_sampled<R: Read>(
+    stream: R,
+    sampling: Sampling,
+    buffer: &mut Vec<u8>,
+) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let mut line = Vec::new();
+    let mut tail: VecDeque<Vec<u8>> = VecDeque::with_capacity(sampling.lines + 1);
+    let mut index = 0;
+    let mut omitted = 0;
+
+    loop {
+        line.clear();
+        let read = reader.read_until(b'\n', &mut line);
+        if !matches!(read, Ok(length) if length > 0) {
+            // The lines still held back are the last ones.
+            if omitted > 0 {
+                buffer
+                    .extend_from_slice(format!("[cronup: {} lines omitted]\n", omitted).as_bytes());
+            }
+            for line in tail {
+                buffer.extend_from_slice(&line);
+            }
+            return read.map(|_| ());
+        }
+
+        // The first lines are kept right away.
+        if index < sampling.lines {
+            buffer.extend_from_slice(&line);
+            index += 1;
+            continue;
+        }
+
+        // Later lines are held back as the last ones until newer lines push them out; of those, only
+        // every `every`-th is kept.
+        tail.push_back(line.clone());
+        if tail.len() > sampling.lines {
+            let dropped = tail.pop_front().unwrap_or_default();
+            let position = index - sampling.lines;
+            if (position + 1).is_multiple_of(sampling.every) {
+                if omitted > 0 {
+                    buffer.extend_from_slice(
+                        format!("[cronup: {} lines omitted]\n", omitted).as_bytes(),
+                    );
+                    omitted = 0;
+                }
+                buffer.extend_from_slice(&dropped);
+            } else {
+                omitted += 1;
+            }
+            index += 1;
+        }
+    }
+}
+
+// Function to send a signal to every process in a process group.
+// Failures are ignored because the group may already have exited on its own.
+fn kill_process_group(pgid: u32, signal: &str) {
+    let _ = Command::new("/bin/kill")
+        .arg(format!("-{}", signal))
+        .arg("--")
+        .arg(format!("-{}", pgid))
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A user's language settings must not reach the tools whose output is parsed.
+    #[test]
+    fn shell_runs_in_stable_locale() {
+        let command = shell("brew upgrade");
+        let locale = config::get_locale().map(std::ffi::OsString::from);
+        for variable in ["LC_ALL", "LANG"] {
+            let value = command
+                .get_envs()
+                .find(|(key, _)| *key == variable)
+                .and_then(|(_, value)| value);
+            assert_eq!(
+                value,
+                locale.as_deref(),
+                "{} isn't set to the locale",
+                variable
+            );
+        }
+    }
+
+    // A long stream keeps its first and last lines, every 100th line in between, and counts the gaps.
+    #[test]
+    fn sampling_keeps_head_tail_and_stride() {
+        let input: String = (0..1000).map(|line| format!("line {}\n", line)).collect();
+        let mut buffer = Vec::new();
+        let sampling = Sampling {
+            lines: 3,
+            every: 100,
+        };
+        read_sampled(input.as_bytes(), sampling, &mut buffer).unwrap();
+
+        let mut expected = String::from("line 0\nline 1\nline 2\n");
+        for line in (102..1000).step_by(100) {
+            expected.push_str(&format!("[cronup: 99 lines omitted]\nline {}\n", line));
+        }
+        expected.push_str("[cronup: 94 lines omitted]\nline 997\nline 998\nline 999\n");
+        assert_eq!(String::from_utf8(buffer).unwrap(), expected);
+    }
+
+    // Output shorter than the first and last lines together is kept in full.
+    #[test]
+    fn sampling_keeps_short_output() {
+        let mut buffer = Vec::new();
+        let sampling = Sampling { lines: 3, every: 2 };
+        read_sampled(&b"a\nb\nc\nd\ne\n"[..], sampling, &mut buffer).unwrap();
+        assert_eq!(buffer, b"a\nb\nc\nd\ne\n");
+    }
+
+    #[test]
+    fn prompt_line_recognizes_questions() {
+        assert_eq!(
+            prompt_line(b"==> Upgrading 2 packages\nProceed? [y/N] ").as_deref(),
+            Some("Proceed? [y/N]")
+        );
+        assert_eq!(prompt_line(b"Password:").as_deref(), Some("Password:"));
+        assert_eq!(prompt_line(b"Proceed? [y/N]\n"), None);
+        assert_eq!(prompt_line(b"Downloading 42%"), None);
+    }
+
+    // How long the test commands' output stalls before they count as waiting.
+    const TEST_IDLE: Duration = Duration::from_millis(500);
+
+    // A command that asks a question and echoes the answer.
+    const QUESTION: &str = "printf 'Proceed? [y/N] '; read answer; echo \"answer: $answer\"";
+
+    #[test]
+    fn prompt_is_answered() {
+        let prompts = PromptHandling {
+            idle: TEST_IDLE,
+            action: PromptAction::Answer(String::from("y")),
+        };
+        let output = run_watched(
+            shell(QUESTION),
+            Duration::from_secs(30),
+            None,
+            Some(&prompts),
+            None,
+        )
+        .unwrap();
+        assert!(output.succeeded());
+        assert_eq!(output.prompts, ["Proceed? [y/N]"]);
+        assert!(String::from_utf8_lossy(&output.stdout).ends_with("answer: y\n"));
+    }
+
+    #[test]
+    fn prompt_is_killed() {
+        let prompts = PromptHandling {
+            idle: TEST_IDLE,
+            action: PromptAction::Kill,
+        };
+        // A password prompt reads from the terminal, so the command waits regardless of its input.
+        let command = shell("printf 'Password:'; sleep 60");
+        let started = Instant::now();
+        let output =
+            run_watched(command, Duration::from_secs(30), None, Some(&prompts), None).unwrap();
+        assert!(started.elapsed() < Duration::from_secs(15));
+        assert_eq!(output.killed_at_prompt.as_deref(), Some("Password:"));
+        assert_eq!(
+            output.failure_signature().as_deref(),
+            Some("interactive prompt detected: Password:")
+        );
+    }
+
+    #[test]
+    fn silent_command_is_killed_as_hung() {
+        // The output before the stall doesn't end like a question, so only the inactivity limit applies.
+        let command = shell("echo 'Syncing plugins'; sleep 60");
+        let started = Instant::now();
+        let output = run_watched(
+            command,
+            Duration::from_secs(30),
+            None,
+            None,
+            Some(Duration::from_secs(1)),
+        )
+        .unwrap();
+        assert!(started.elapsed() < Duration::from_secs(15));
+        assert_eq!(output.killed_for_inactivity, Some(Duration::from_secs(1)));
+        assert_eq!(
+            output.failure_signature().as_deref(),
+            Some("hung: Syncing plugins")
+        );
+    }
+}
@@ -0,0 +1,65 @@
+// The progress of the current run: `cronup.progress` in `LOG_HOME` holds the run's start time on its
+// first line, followed by the name of every updater that succeeded so far. It is removed once every
+// updater had its turn, so a file found by the next run means that run was killed before it finished.
+// With `--resume`, a run resumes an interrupted run of the same day, skipping the updaters it completed.
+
+// Import various modules from the Rust standard library.
+use std::{
+    error::Error,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+};
+
+// A run that was interrupted before it finished.
+pub struct Interrupted {
+    // The local time the run started, in the history's format.
+    pub started: String,
+    // The updaters it completed successfully.
+    pub completed: Vec<String>,
+}
+
+// Function to get the path of the progress file.
+fn progress_path(log_home: &str) -> String {
+    format!("{}/cronup.progress", log_home)
+}
+
+// Function to read the progress of an interrupted run, if the last run didn't finish.
+pub fn interrupted(log_home: &str) -> Option<Interrupted> {
+    let contents = fs::read_to_string(progress_path(log_home)).ok()?;
+    let mut lines = contents.lines();
+    let started = lines.next()?.trim().to_string();
+    let completed = lines
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect();
+    Some(Interrupted { started, completed })
+}
+
+// Function to start recording the progress of a run that started at `started`, with the updaters
+// already completed if it resumes an interrupted run.
+pub fn begin(log_home: &str, started: &str, completed: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut contents = format!("{}\n", started);
+    for name in completed {
+        contents.push_str(&format!("{}\n", name));
+    }
+    fs::write(progress_path(log_home), contents)?;
+    Ok(())
+}
+
+// Function to record that an updater completed successfully.
+pub fn complete(log_home: &str, name: &str) -> Result<(), Box<dyn Error>> {
+    let mut file = OpenOptions::new()
+        .append(true)
+        .open(progress_path(log_home))?;
+    writeln!(file, "{}", name)?;
+    Ok(())
+}
+
+// Function to record that the run finished, so the next one doesn't resume it.
+pub fn finish(log_home: &str) -> Result<(), Box<dyn Error>> {
+    match fs::remove_file(progress_path(log_home)) {
+        Err(error) if error.kind() != ErrorKind::NotFound => Err(error.into()),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,229 @@
+// The trend report printed by `cronup report`: per updater and month, the number of runs, failures,
+// average run duration and CPU time, peak memory, and updated packages, charted as bars, from the
+// run history.
+// Months in which an updater updated far more packages or ran far longer than in the months before
+// are flagged, to spot a machine whose update burden is growing abnormally.
+// The report is plain text by default and an HTML page with `--html`.
+
+// Import various modules from the Rust standard library.
+use std::{collections::BTreeMap, error::Error};
+
+// The run history the report is built from.
+use crate::history;
+
+// The width of the longest bar in the chart, in characters (text) or pixels / 4 (HTML).
+const BAR_WIDTH: usize = 40;
+
+// How many times the average of the previous months a value must reach to be flagged.
+const ABNORMAL_FACTOR: f64 = 2.0;
+
+// The totals per updater name and month, both sorted.
+type Stats = BTreeMap<String, BTreeMap<String, MonthStats>>;
+
+// The totals of one updater in one month.
+#[derive(Default)]
+struct MonthStats {
+    runs: u64,
+    failures: u64,
+    total_secs: u64,
+    // `None` if the updater never reported how many packages it updated.
+    updated: Option<usize>,
+    // The CPU time of the runs that recorded it, and how many did.
+    total_cpu_secs: u64,
+    measured_runs: u64,
+    // The highest peak memory of any run in bytes, `None` if no run recorded it.
+    peak_rss: Option<u64>,
+    // Whether this month stands out against the previous months.
+    abnormal: bool,
+}
+
+impl MonthStats {
+    // Returns the average run duration in seconds.
+    fn average_secs(&self) -> u64 {
+        self.total_secs / self.runs.max(1)
+    }
+
+    // Returns the average CPU time in seconds, or `None` if no run recorded it.
+    fn average_cpu_secs(&self) -> Option<u64> {
+        (self.measured_runs > 0).then(|| self.total_cpu_secs / self.measured_runs)
+    }
+}
+
+// Function to print the trend report for the history in `log_home`.
+pub fn print_report(log_home: &str, html: bool) -> Result<(), Box<dyn Error>> {
+    let stats = collect(history::load(log_home)?);
+    if html {
+        print!("{}", render_html(&stats));
+    } else {
+        print!("{}", render_text(&stats));
+    }
+    Ok(())
+}
+
+// Function to total the runs per updater and month, sorted by updater name and month.
+fn collect(runs: Vec<history::Run>) -> Stats {
+    let mut stats = Stats::new();
+    for run in runs {
+        let month = stats
+            .entry(run.name)
+            .or_default()
+            .entry(run.month)
+            .or_default();
+        month.runs += 1;
+        month.failures += u64::from(!run.succeeded);
+        month.total_secs += run.duration_secs;
+        if let Some(updated) = run.updated {
+            month.updated = Some(month.updated.unwrap_or(0) + updated);
+        }
+        if let Some(cpu_secs) = run.cpu_secs {
+            month.total_cpu_secs += cpu_secs;
+            month.measured_runs += 1;
+        }
+        if let Some(peak_rss) = run.peak_rss {
+            month.peak_rss = Some(month.peak_rss.unwrap_or(0).max(peak_rss));
+        }
+    }
+
+    // Compare every month to the average of the months before it.
+    for months in stats.values_mut() {
+        let mut previous: Vec<(f64, f64)> = Vec::new();
+        for month in months.values_mut() {
+            let updated = month.updated.unwrap_or(0) as f64;
+            let average = month.average_secs() as f64;
+            if !previous.is_empty() {
+                let count = previous.len() as f64;
+                let usual_updated =
+                    previous.iter().map(|(updated, _)| updated).sum::<f64>() / count;
+                let usual_average =
+                    previous.iter().map(|(_, average)| average).sum::<f64>() / count;
+                month.abnormal = (usual_updated > 0.0
+                    && updated >= usual_updated * ABNORMAL_FACTOR)
+                    || (usual_average > 0.0 && average >= usual_average * ABNORMAL_FACTOR);
+            }
+            previous.push((updated, average));
+        }
+    }
+
+    stats
+}
+
+// Function to get the length of a month's bar, scaled to the most updates in any month.
+fn bar_length(month: &MonthStats, most_updated: usize) -> usize {
+    month.updated.unwrap_or(0) * BAR_WIDTH / most_updated.max(1)
+}
+
+// Function to format a duration in seconds as minutes and seconds, e.g. "3m12s".
+fn format_duration(secs: u64) -> String {
+    format!("{}m{:02}s", secs / 60, secs % 60)
+}
+
+// Function to format the average CPU time of a month, or "-" if it wasn't recorded.
+fn format_cpu(month: &MonthStats) -> String {
+    month
+        .average_cpu_secs()
+        .map_or_else(|| String::from("-"), format_duration)
+}
+
+// Function to format the peak memory of a month in megabytes, or "-" if it wasn't recorded.
+fn format_memory(month: &MonthStats) -> String {
+    month.peak_rss.map_or_else(
+        || String::from("-"),
+        |bytes| format!("{}M", bytes / (1024 * 1024)),
+    )
+}
+
+// Function to get the most packages updated by any updater in any month.
+fn most_updated(stats: &Stats) -> usize {
+    stats
+        .values()
+        .flat_map(|months| months.values())
+        .filter_map(|month| month.updated)
+        .max()
+        .unwrap_or(0)
+}
+
+// Function to render the report as plain text with an ASCII bar chart.
+fn render_text(stats: &Stats) -> String {
+    if stats.is_empty() {
+        return String::from("No runs recorded yet.\n");
+    }
+
+    let most_updated = most_updated(stats);
+    let mut report =
+        String::from("cronup trend report (! = more than double the usual updates or duration)\n");
+    for (name, months) in stats {
+        report.push_str(&format!(
+            "\n{}\n  {:<7}  {:>4}  {:>6}  {:>8}  {:>8}  {:>8}  {:>7}\n",
+            name, "month", "runs", "failed", "avg time", "avg cpu", "peak mem", "updated"
+        ));
+        for (month, month_stats) in months {
+            let row = format!(
+                "  {:<7}  {:>4}  {:>6}  {:>8}  {:>8}  {:>8}  {:>7}  {}{}",
+                month,
+                month_stats.runs,
+                month_stats.failures,
+                format_duration(month_stats.average_secs()),
+                format_cpu(month_stats),
+                format_memory(month_stats),
+                month_stats
+                    .updated
+                    .map_or_else(|| String::from("-"), |count| count.to_string()),
+                "#".repeat(bar_length(month_stats, most_updated)),
+                if month_stats.abnormal { " !" } else { "" }
+            );
+            // Months without updates have no bar, so drop the padding before it.
+            report.push_str(row.trim_end());
+            report.push('\n');
+        }
+    }
+    report
+}
+
+// Function to render the report as an HTML page with a bar chart.
+fn render_html(stats: &Stats) -> String {
+    let most_updated = most_updated(stats);
+    let mut report = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>cronup trend report</title>\n\
+         <style>body{font-family:sans-serif}td,th{padding:2px 8px;text-align:right}\
+         .bar{background:#4a90d9;height:12px}.abnormal{background:#fdd}</style>\n\
+         </head>\n<body>\n<h1>cronup trend report</h1>\n\
+         <p>Highlighted months had more than double the usual updates or duration.</p>\n",
+    );
+    if stats.is_empty() {
+        report.push_str("<p>No runs recorded yet.</p>\n");
+    }
+    for (name, months) in stats {
+        report.push_str(&format!(
+            "<h2>{}</h2>\n<table>\n<tr><th>month</th><th>runs</th><th>failed</th>\
+             <th>avg time</th><th>avg cpu</th><th>peak mem</th><th>updated</th><th></th></tr>\n",
+            escape_html(name)
+        ));
+        for (month, month_stats) in months {
+            report.push_str(&format!(
+                "<tr{}><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td>\
+                 <td style=\"text-align:left\"><div class=\"bar\" style=\"width:{}px\"></div></td></tr>\n",
+                if month_stats.abnormal { " class=\"abnormal\"" } else { "" },
+                escape_html(month),
+                month_stats.runs,
+                month_stats.failures,
+                format_duration(month_stats.average_secs()),
+                format_cpu(month_stats),
+                format_memory(month_stats),
+                month_stats
+                    .updated
+                    .map_or_else(|| String::from("-"), |count| count.to_string()),
+                bar_length(month_stats, most_updated) * 4
+            ));
+        }
+        report.push_str("</table>\n");
+    }
+    report.push_str("</body>\n</html>\n");
+    report
+}
+
+// Function to escape text for use in HTML.
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
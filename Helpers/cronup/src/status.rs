@@ -0,0 +1,357 @@
+// A local HTTP status page, served while cronup runs when `CRONUP_STATUS_PORT` is set to a port number.
+// It only listens on 127.0.0.1 and answers `GET /` with a minimal HTML page and `GET /status.json`
+// with the same content as JSON: whether a run is in progress, the step it's at, the results of this
+// run so far, and the most recent run of every updater from the history.
+// `cronup daemon` serves the page for as long as it stays resident, with the next scheduled run of
+// every updater. The runs it starts don't serve a page of their own; they write their progress to
+// `cronup.status` in `LOG_HOME` (named by `CRONUP_STATUS_FILE`), which the daemon's page shows.
+
+// Import various modules from the Rust standard library.
+use std::{
+    env,
+    io::{BufRead, BufReader, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+// The run history the last results are read from, and the HTML escaping of the report.
+use crate::{history, report};
+
+// How long a client may take to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+// The state of the current run shown on the status page.
+#[derive(Default)]
+struct RunState {
+    // The local time the run started.
+    started: String,
+    // The step currently running, e.g. "brew (2 of 6)".
+    step: Option<String>,
+    // The outcome of every updater that finished in this run: its name and the signature of its
+    // failure, or `None` if it succeeded.
+    results: Vec<(String, Option<String>)>,
+    // In daemon mode, the next scheduled run of every updater: its name and local time.
+    next_runs: Vec<(String, String)>,
+    // In daemon mode, the file the current or last run writes its progress to.
+    forwarded: Option<String>,
+    // In daemon mode, whether a run is in progress.
+    running: bool,
+}
+
+// The status page of the current run.
+pub struct Status {
+    // The state of the run, shared with the server thread.
+    state: Arc<Mutex<RunState>>,
+    // The file the progress is written to for the daemon's page, if this run was started by it.
+    file: Option<String>,
+}
+
+// Function to get the path of the file a run started by the daemon writes its progress to.
+pub fn progress_path(log_home: &str) -> String {
+    format!("{}/cronup.status", log_home)
+}
+
+impl Status {
+    // Starts serving the status page if `CRONUP_STATUS_PORT` is set. The server runs until cronup exits.
+    pub fn start(log_home: &str) -> Self {
+        let state = Arc::new(Mutex::new(RunState {
+            started: history::timestamp(),
+            ..RunState::default()
+        }));
+
+        let port = env::var("CRONUP_STATUS_PORT")
+            .ok()
+            .and_then(|port| port.trim().parse::<u16>().ok())
+            .filter(|port| *port > 0);
+        if let Some(listener) =
+            port.and_then(|port| TcpListener::bind((Ipv4Addr::LOCALHOST, port)).ok())
+        {
+            let state = Arc::clone(&state);
+            let log_home = log_home.to_string();
+            thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let _ = serve(stream, &state, &log_home);
+                }
+            });
+        }
+
+        let file = env::var("CRONUP_STATUS_FILE")
+            .ok()
+            .filter(|file| !file.is_empty());
+        let status = Status { state, file };
+        status.forward();
+        status
+    }
+
+    // Starts serving the daemon's status page, which shows the progress the daemon's runs write to
+    // `progress_path`, if `CRONUP_STATUS_PORT` is set.
+    pub fn start_daemon(log_home: &str) -> Self {
+        let status = Self::start(log_home);
+        if let Ok(mut state) = status.state.lock() {
+            state.forwarded = Some(progress_path(log_home));
+        }
+        status
+    }
+
+    // Records which step is running.
+    pub fn set_step(&self, step: String) {
+        if let Ok(mut state) = self.state.lock() {
+            state.step = Some(step);
+        }
+        self.forward();
+    }
+
+    // Records the outcome of an updater that finished.
+    pub fn record(&self, name: &str, failure: Option<String>) {
+        if let Ok(mut state) = self.state.lock() {
+            state.results.push((name.to_string(), failure));
+        }
+        self.forward();
+    }
+
+    // Records the next scheduled run of every updater, in daemon mode.
+    pub fn set_next_runs(&self, next_runs: Vec<(String, String)>) {
+        if let Ok(mut state) = self.state.lock() {
+            state.next_runs = next_runs;
+        }
+    }
+
+    // Records whether the daemon has a run in progress.
+    pub fn set_running(&self, running: bool) {
+        if let Ok(mut state) = self.state.lock() {
+            state.running = running;
+        }
+    }
+
+    // Writes the progress of the run to the daemon's file, one tab-separated field list per line:
+    // "started", "step", and a "result" line per finished updater with its failure, if any.
+    // Tabs and line breaks in the fields, e.g. in a failure's signature, are written as spaces.
+    // A failed write only leaves the daemon's page behind, so it's ignored.
+    fn forward(&self) {
+        let (Some(file), Ok(state)) = (&self.file, self.state.lock()) else {
+            return;
+        };
+        let field = |value: &str| value.replace(['\t', '\n'], " ");
+        let mut content = format!("started\t{}\n", field(&state.started));
+        if let Some(step) = &state.step {
+            content.push_str(&format!("step\t{}\n", field(step)));
+        }
+        for (name, failure) in &state.results {
+            content.push_str(&format!(
+                "result\t{}\t{}\n",
+                field(name),
+                field(failure.as_deref().unwrap_or_default())
+            ));
+        }
+        let _ = std::fs::write(file, content);
+    }
+}
+
+// Function to read the progress a run started by the daemon wrote: when it started, its step, and
+// the outcome of every updater that finished. `forward` replaces tabs and line breaks in them.
+fn read_forwarded(path: &str) -> RunState {
+    let content = std::fs::read_to_string(path).unwrap_or_default();
+    let mut run = RunState::default();
+    for line in content.lines() {
+        let mut fields = line.split('\t');
+        match (fields.next(), fields.next(), fields.next()) {
+            (Some("started"), Some(time), _) => run.started = time.to_string(),
+            (Some("step"), Some(step), _) => run.step = Some(step.to_string()),
+            (Some("result"), Some(name), failure) => run.results.push((
+                name.to_string(),
+                failure
+                    .filter(|failure| !failure.is_empty())
+                    .map(String::from),
+            )),
+            _ => {}
+        }
+    }
+    run
+}
+
+// Function to answer a single request.
+fn serve(mut stream: TcpStream, state: &Mutex<RunState>, log_home: &str) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+
+    let (status, content_type, body) = match (method, path) {
+        ("GET", "/") => (
+            "200 OK",
+            "text/html; charset=utf-8",
+            page(state, log_home, false),
+        ),
+        ("GET", "/status.json") => ("200 OK", "application/json", page(state, log_home, true)),
+        ("GET", _) => ("404 Not Found", "text/plain", String::from("not found\n")),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            String::from("method not allowed\n"),
+        ),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+// Function to render the status as JSON, or else as an HTML page.
+fn page(state: &Mutex<RunState>, log_home: &str, json: bool) -> String {
+    let (started, step, results, next_runs, forwarded, running) = match state.lock() {
+        Ok(state) => (
+            state.started.clone(),
+            state.step.clone(),
+            state.results.clone(),
+            state.next_runs.clone(),
+            state.forwarded.clone(),
+            state.running,
+        ),
+        Err(_) => (String::new(), None, Vec::new(), Vec::new(), None, false),
+    };
+    // The daemon shows the current or last run it started, whose step only counts while it runs.
+    let (started, step, results) = match &forwarded {
+        Some(path) => {
+            let run = read_forwarded(path);
+            (run.started, run.step.filter(|_| running), run.results)
+        }
+        None => (started, step, results),
+    };
+    // A run serves its own page only while it runs.
+    let running = forwarded.is_none() || running;
+
+    // The most recent run of every updater, in the order they last ran.
+    let runs = history::load(log_home).unwrap_or_default();
+    let mut last_runs: Vec<&history::Run> = Vec::new();
+    for run in runs.iter().rev() {
+        if !last_runs.iter().any(|last| last.name == run.name) {
+            last_runs.push(run);
+        }
+    }
+    last_runs.reverse();
+
+    if json {
+        let results = results
+            .iter()
+            .map(|(name, failure)| {
+                format!(
+                    "{{\"name\":{},\"ok\":{},\"failure\":{}}}",
+                    json_string(name),
+                    failure.is_none(),
+                    failure
+                        .as_deref()
+                        .map_or_else(|| String::from("null"), json_string)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        let last_runs = last_runs
+            .iter()
+            .map(|run| {
+                format!(
+                    "{{\"name\":{},\"started\":{},\"ok\":{},\"duration_secs\":{}}}",
+                    json_string(&run.name),
+                    json_string(&run.started),
+                    run.succeeded,
+                    run.duration_secs
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        let next_runs = next_runs
+            .iter()
+            .map(|(name, at)| {
+                format!(
+                    "{{\"name\":{},\"at\":{}}}",
+                    json_string(name),
+                    json_string(at)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        return format!(
+            "{{\"started\":{},\"running\":{},\"step\":{},\"results\":[{}],\"last_runs\":[{}],\"next_runs\":[{}]}}\n",
+            json_string(&started),
+            running,
+            step.as_deref()
+                .map_or_else(|| String::from("null"), json_string),
+            results,
+            last_runs,
+            next_runs
+        );
+    }
+
+    let mut page = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>cronup status</title>\n</head>\n<body>\n",
+    );
+    if started.is_empty() {
+        page.push_str("<h1>cronup</h1>\n<p>No run yet.</p>\n");
+    } else {
+        page.push_str(&format!(
+            "<h1>cronup</h1>\n<p>Run started {}, running {}.</p>\n",
+            report::escape_html(&started),
+            report::escape_html(step.as_deref().unwrap_or("nothing"))
+        ));
+    }
+    page.push_str(if !running {
+        "<h2>Last run</h2>\n<ul>\n"
+    } else {
+        "<h2>This run</h2>\n<ul>\n"
+    });
+    for (name, failure) in &results {
+        page.push_str(&format!(
+            "<li>{}: {}</li>\n",
+            report::escape_html(name),
+            report::escape_html(&failure.as_ref().map_or_else(
+                || String::from("ok"),
+                |failure| format!("failed ({})", failure)
+            ))
+        ));
+    }
+    page.push_str("</ul>\n<h2>Last runs</h2>\n<ul>\n");
+    for run in &last_runs {
+        page.push_str(&format!(
+            "<li>{}: {} at {}, {} seconds</li>\n",
+            report::escape_html(&run.name),
+            if run.succeeded { "ok" } else { "failed" },
+            report::escape_html(&run.started),
+            run.duration_secs
+        ));
+    }
+    page.push_str("</ul>\n");
+    if forwarded.is_some() {
+        page.push_str("<h2>Next runs</h2>\n<ul>\n");
+        for (name, at) in &next_runs {
+            page.push_str(&format!(
+                "<li>{}: {}</li>\n",
+                report::escape_html(name),
+                report::escape_html(at)
+            ));
+        }
+        page.push_str("</ul>\n");
+    }
+    page.push_str("</body>\n</html>\n");
+    page
+}
+
+// Function to quote a string as a JSON string literal.
+pub fn json_string(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
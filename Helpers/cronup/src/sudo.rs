@@ -0,0 +1,54 @@
+// Root privileges for the updaters that need them (see `Updater::needs_sudo`, overridden by
+// `CRONUP_SUDO_<NAME>`), e.g. MacPorts, or softwareupdate installing updates. Their commands run
+// through sudo, which never prompts: with `CRONUP_SUDO_ASKPASS` or `SUDO_ASKPASS` naming a program that
+// prints the password (e.g. a script reading it from the Keychain), sudo asks that program; otherwise
+// sudo needs cached credentials or a sudoers rule that allows the commands without a password.
+// Elevation is tried before the updater runs, so an updater without it fails on its own with the
+// reason logged, instead of in every command, and the rest of the run goes on.
+
+// Import various modules from the Rust standard library.
+use std::{env, time::Duration};
+
+// The askpass path expansion, running the check, and quoting the wrapped command line.
+use crate::{config, process, updaters::quote};
+
+// The sudo executable.
+const SUDO: &str = "/usr/bin/sudo";
+
+// How long the elevation check may take, including an askpass program unlocking the Keychain.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(60);
+
+// Function to get the configured askpass program, if any.
+fn askpass() -> Option<String> {
+    ["CRONUP_SUDO_ASKPASS", "SUDO_ASKPASS"]
+        .iter()
+        .find_map(|key| env::var(key).ok())
+        .map(|path| config::expand_path(path.trim()))
+        .filter(|path| !path.is_empty())
+}
+
+// Function to wrap a shell command line so it runs as root through sudo, without prompting.
+pub fn wrap(command_line: &str) -> String {
+    let sudo = match askpass() {
+        Some(askpass) => format!("SUDO_ASKPASS={} {} -A", quote(&askpass), SUDO),
+        None => format!("{} -n", SUDO),
+    };
+    format!("{} /bin/bash -c {}", sudo, quote(command_line))
+}
+
+// Function to check that sudo grants root privileges without prompting.
+// Returns why not otherwise, e.g. "sudo: a password is required".
+pub fn check() -> Result<(), String> {
+    let command = process::shell(&wrap("true"));
+    match process::run_with_timeout(command, CHECK_TIMEOUT, None) {
+        Ok(output) if output.succeeded() => Ok(()),
+        Ok(output) if output.status.is_none() => Err(String::from("sudo timed out")),
+        Ok(output) => Err(String::from_utf8_lossy(&output.stderr)
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .unwrap_or("sudo refused")
+            .to_string()),
+        Err(error) => Err(error.to_string()),
+    }
+}
@@ -0,0 +1,57 @@
+// Updates asdf plugins and the tool versions installed with them.
+// Every plugin is updated on its own, followed by installing the latest version of its tool and
+// making it the version set in the home directory's `.tool-versions`. The plugin's name is logged
+// before each step, so the output of every plugin can be told apart in the log.
+
+use super::{list_plugins, locate, quote, Updater};
+
+pub struct Asdf {
+    asdf: Option<String>, // The path to the asdf executable, if installed.
+}
+
+impl Asdf {
+    pub fn new() -> Self {
+        Asdf {
+            asdf: locate("asdf"),
+        }
+    }
+}
+
+impl Updater for Asdf {
+    fn name(&self) -> &'static str {
+        "asdf"
+    }
+
+    fn detect(&self) -> bool {
+        self.asdf.is_some()
+    }
+
+    // asdf is usually installed through Homebrew or MacPorts.
+    fn dependencies(&self) -> &'static [&'static str] {
+        &["brew", "macports"]
+    }
+
+    fn commands(&self) -> Vec<String> {
+        let asdf = quote(self.asdf.as_deref().unwrap_or("asdf"));
+        let mut commands = Vec::new();
+        for plugin in list_plugins(&asdf, "plugin list") {
+            let name = quote(&plugin);
+            // Announce the plugin so its output is recognizable in the log.
+            commands.push(format!("echo {}", quote(&format!("==> {}", plugin))));
+            // Update the plugin itself, which also makes newer tool versions known.
+            commands.push(format!("{} plugin update {}", asdf, name));
+            // Install the latest version of the tool and use it globally.
+            commands.push(format!("{} install {} latest", asdf, name));
+            commands.push(format!("{} set --home {} latest", asdf, name));
+        }
+        commands
+    }
+
+    fn check_commands(&self) -> Vec<String> {
+        // List the latest version of every plugin's tool next to whether it is installed.
+        vec![format!(
+            "{} latest --all",
+            quote(self.asdf.as_deref().unwrap_or("asdf"))
+        )]
+    }
+}
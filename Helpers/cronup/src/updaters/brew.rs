@@ -0,0 +1,462 @@
+// Updates, upgrades, and cleans up Homebrew packages.
+// The installed formula versions are recorded before upgrading, in `cronup.brew.snapshot` next to the
+// logs. If the run fails, the formulae that were upgraded anyway are listed in the log.
+// `cronup rollback brew <formula>` returns a formula to the version recorded before the last upgrade.
+// While the previous keg is still in the Cellar (`brew cleanup` only runs after a successful upgrade),
+// the new keg is removed and the old one relinked and pinned. Once it's gone, the old version is
+// extracted into the local tap `cronup/rollback` and installed from there as `<formula>@<version>`.
+// Taps listed in `CRONUP_TAPS_BREW` are added and those in `CRONUP_UNTAPS_BREW` removed before
+// upgrading. Casks listed in `CRONUP_PINNED_CASKS_BREW` are never upgraded; their available
+// versions are only reported, for apps where an unattended upgrade is risky (e.g. audio plugins).
+// If `CRONUP_BREWFILE_BREW` names a Brewfile, the installed packages are reconciled with it through
+// `brew bundle` instead of upgraded: missing entries are installed, outdated ones upgraded, and
+// packages not listed are removed. Every install, upgrade, and removal is summarized in the log.
+// `CRONUP_DOCTOR_BREW` adds a `brew doctor` pre-flight check whose diagnostics are logged: with
+// `abort`, nothing is upgraded if it finds problems; with `warn`, the run continues regardless.
+// Pinned formulae are never upgraded by Homebrew; those with a newer version are listed in the log.
+
+use std::{collections::BTreeMap, error::Error, fs, path::Path};
+
+use super::{count_lines, locate, quote, summary_line, Updater};
+use crate::{clock, config, logging, process, process::CommandOutput};
+
+// The local tap old formula versions are extracted into when their keg is gone.
+const ROLLBACK_TAP: &str = "cronup/rollback";
+
+// A formula whose version changed since the snapshot.
+struct Upgrade {
+    formula: String, // The formula name.
+    old: String,     // The version before the run.
+    new: String,     // The version after the run.
+}
+
+pub struct Brew {
+    brew: Option<String>, // The path to the Homebrew executable, if installed.
+}
+
+impl Brew {
+    pub fn new() -> Self {
+        Brew {
+            brew: locate("brew"),
+        }
+    }
+
+    // Where the formula versions are recorded before upgrading.
+    fn snapshot_path() -> String {
+        format!("{}/cronup.brew.snapshot", config::log_home())
+    }
+
+    // Lists the formulae that changed since the snapshot, or nothing if the snapshot is missing.
+    fn changed_formulae(&self, brew: &str) -> Result<Vec<Upgrade>, Box<dyn Error>> {
+        let before = match fs::read_to_string(Self::snapshot_path()) {
+            Ok(snapshot) => parse_versions(&snapshot),
+            Err(_) => return Ok(Vec::new()),
+        };
+        let output = process::run_with_timeout(
+            process::shell(&format!("{} list --formula --versions", quote(brew))),
+            config::get_timeout(self.name()),
+            None,
+        )?;
+        let after = parse_versions(&String::from_utf8_lossy(&output.stdout));
+
+        // A formula changed if its newest version isn't one it had before.
+        let changed: Vec<Upgrade> = after
+            .iter()
+            .filter_map(|(formula, versions)| {
+                let previous = before.get(formula)?;
+                let new = versions.last()?;
+                if previous.contains(new) {
+                    return None;
+                }
+                Some(Upgrade {
+                    formula: formula.clone(),
+                    old: previous.last()?.clone(),
+                    new: new.clone(),
+                })
+            })
+            .collect();
+        Ok(changed)
+    }
+}
+
+// Function to list the commands that roll a formula back to its version `old`, with whether the old
+// keg is still in the Cellar.
+fn rollback_commands(brew: &str, formula: &str, old: &str, keg_exists: bool) -> Vec<String> {
+    let brew = quote(brew);
+    if keg_exists {
+        // Uninstalling without `--force` only removes the newest keg; linking then picks the previous
+        // one. Pinning keeps the next run from upgrading the formula again until it is unpinned.
+        let formula = quote(formula);
+        return vec![
+            format!("{} uninstall --ignore-dependencies {}", brew, formula),
+            format!("{} link {}", brew, formula),
+            format!("{} pin {}", brew, formula),
+        ];
+    }
+
+    // `brew extract` takes the version without the revision, e.g. "14.1.1" of keg "14.1.1_1". The
+    // extracted formula has a name of its own, so upgrades of the formula don't replace it.
+    let version = old.split('_').next().unwrap_or(old);
+    vec![
+        format!(
+            "{{ {brew} tap | grep -qxF {tap} || {brew} tap-new --no-git {tap}; }}",
+            brew = brew,
+            tap = ROLLBACK_TAP
+        ),
+        format!(
+            "{} extract --force --version={} {} {}",
+            brew,
+            quote(version),
+            quote(formula),
+            ROLLBACK_TAP
+        ),
+        format!(
+            "{} uninstall --ignore-dependencies {}",
+            brew,
+            quote(formula)
+        ),
+        format!(
+            "{} install {}",
+            brew,
+            quote(&format!("{}/{}@{}", ROLLBACK_TAP, formula, version))
+        ),
+    ]
+}
+
+// Function to roll a formula back to the version recorded before the last upgrade, for
+// `cronup rollback brew <formula>`. Every command and its output are logged to `cronup.brew.log`.
+pub fn rollback(formula: &str) -> Result<(), Box<dyn Error>> {
+    let brew = locate("brew").ok_or("Homebrew is not installed")?;
+    let snapshot = fs::read_to_string(Brew::snapshot_path()).map_err(|_| {
+        format!(
+            "No versions recorded before an upgrade in {}",
+            Brew::snapshot_path()
+        )
+    })?;
+    let old = parse_versions(&snapshot)
+        .get(formula)
+        .and_then(|versions| versions.last().cloned())
+        .ok_or_else(|| format!("{} wasn't installed before the last upgrade", formula))?;
+
+    let timeout = config::get_timeout("brew");
+    let run = |command: String| process::run_with_timeout(process::shell(&command), timeout, None);
+    let listing = run(format!(
+        "{} list --formula --versions {}",
+        quote(&brew),
+        quote(formula)
+    ))?;
+    let current = parse_versions(&String::from_utf8_lossy(&listing.stdout))
+        .remove(formula)
+        .and_then(|versions| versions.last().cloned())
+        .unwrap_or_else(|| String::from("none"));
+    if current == old {
+        println!("{} is already at {}.", formula, old);
+        return Ok(());
+    }
+    let cellar = run(format!("{} --cellar {}", quote(&brew), quote(formula)))?;
+    let keg = Path::new(String::from_utf8_lossy(&cellar.stdout).trim()).join(&old);
+
+    let mut log_file = logging::open_log(&config::log_home(), "brew")?;
+    let message = format!("Rolling back {} {} -> {}", formula, current, old);
+    println!("{}", message);
+    logging::log_line(&mut log_file, &message)?;
+    for command in rollback_commands(&brew, formula, &old, keg.is_dir()) {
+        println!("{}", command);
+        logging::log_line(&mut log_file, &command)?;
+        let output = run(command.clone())?;
+        logging::write_output(&mut log_file, &output)?;
+        if !output.succeeded() {
+            let message = format!("Rollback of {} failed at: {}", formula, command);
+            logging::log_line(&mut log_file, &message)?;
+            return Err(message.into());
+        }
+    }
+    let message = format!("Rolled back {} to {}", formula, old);
+    println!("{}", message);
+    logging::log_line(&mut log_file, &message)?;
+    Ok(())
+}
+
+// Function to parse `brew list --versions` output into the installed versions of every formula.
+fn parse_versions(listing: &str) -> BTreeMap<String, Vec<String>> {
+    listing
+        .lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            let formula = words.next()?.to_string();
+            Some((formula, words.map(String::from).collect()))
+        })
+        .collect()
+}
+
+// Function to read a list setting, separated by spaces or commas (e.g. `CRONUP_TAPS_BREW`).
+fn get_list_setting(setting: &str) -> Vec<String> {
+    config::get_text_setting(setting, "brew")
+        .unwrap_or_default()
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|item| !item.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+impl Updater for Brew {
+    fn name(&self) -> &'static str {
+        "brew"
+    }
+
+    fn detect(&self) -> bool {
+        self.brew.is_some()
+    }
+
+    fn commands(&self) -> Vec<String> {
+        let brew = quote(self.brew.as_deref().unwrap_or("brew"));
+        let pinned_casks = get_list_setting("PINNED_CASKS");
+
+        let mut commands = vec![
+            // Drop the previous run's snapshot, so an early failure can't be rolled back against it.
+            format!("rm -f {}", quote(&Self::snapshot_path())),
+            // Update Homebrew package list.
+            format!("{} update", brew),
+        ];
+        match config::get_text_setting("DOCTOR", self.name()).as_deref() {
+            // Check the installation for problems and stop before upgrading if there are any.
+            Some("abort") => commands.push(format!(
+                "{{ {} doctor || {{ echo 'brew doctor found problems - upgrade aborted.'; false; }}; }}",
+                brew
+            )),
+            // Check the installation for problems, but upgrade anyway.
+            Some("warn") => commands.push(format!(
+                "{{ {} doctor || echo 'brew doctor found problems - upgrading anyway.'; }}",
+                brew
+            )),
+            _ => {}
+        }
+        for tap in get_list_setting("TAPS") {
+            // Add the tap; tapping an existing tap does nothing.
+            commands.push(format!("{} tap {}", brew, quote(&tap)));
+        }
+        for tap in get_list_setting("UNTAPS") {
+            // Remove the tap if it is still tapped.
+            commands.push(format!(
+                "{{ ! {brew} tap | grep -qixF {tap} || {brew} untap {tap}; }}",
+                brew = brew,
+                tap = quote(&tap)
+            ));
+        }
+        // Record the installed formula versions in case the upgrade has to be rolled back.
+        commands.push(format!(
+            "{} list --formula --versions > {}",
+            brew,
+            quote(&Self::snapshot_path())
+        ));
+        if let Some(brewfile) = config::get_text_setting("BREWFILE", self.name()) {
+            let brewfile = quote(&config::expand_path(&brewfile));
+            // Install and upgrade everything the Brewfile lists.
+            commands.push(format!("{} bundle install --file {}", brew, brewfile));
+            // Remove every package the Brewfile doesn't list.
+            commands.push(format!(
+                "{} bundle cleanup --file {} --force",
+                brew, brewfile
+            ));
+        } else if pinned_casks.is_empty() {
+            // Upgrade all installed Homebrew packages.
+            commands.push(format!("{} upgrade", brew));
+        } else {
+            let pinned: Vec<String> = pinned_casks.iter().map(|cask| quote(cask)).collect();
+            // Upgrade all formulae, then every outdated cask that isn't pinned.
+            commands.push(format!("{} upgrade --formula", brew));
+            commands.push(format!(
+                "casks=$({brew} outdated --cask --quiet | grep -vxF{excludes} || true) && \
+                 {{ [ -z \"$casks\" ] || {brew} upgrade --cask $casks; }}",
+                brew = brew,
+                excludes = pinned
+                    .iter()
+                    .map(|cask| format!(" -e {}", cask))
+                    .collect::<String>()
+            ));
+            // Report the versions available for the pinned casks, as "<cask> (<installed>) != <latest>".
+            commands.push(format!(
+                "{{ {} outdated --cask --greedy --verbose {} || true; }}",
+                brew,
+                pinned.join(" ")
+            ));
+        }
+        // Report the pinned formulae that are outdated, as "<formula> (<installed>) < <latest> [pinned at <version>]".
+        commands.push(format!(
+            "pinned=$({brew} list --pinned) && \
+             {{ [ -z \"$pinned\" ] || {brew} outdated --formula --verbose $pinned || true; }}",
+            brew = brew
+        ));
+        // Remove old versions of packages, unless the clock is off and their age can't be judged.
+        if !clock::is_skewed() {
+            commands.push(format!("{} cleanup", brew));
+        }
+        commands
+    }
+
+    fn check_commands(&self) -> Vec<String> {
+        // List outdated formulae and casks with their installed and available versions.
+        vec![format!(
+            "{} outdated --verbose",
+            quote(self.brew.as_deref().unwrap_or("brew"))
+        )]
+    }
+
+    // Pinned casks with a newer version are summarized, and after a failed run, the formulae that
+    // were upgraded anyway are listed with how to roll them back.
+    fn post_process(
+        &self,
+        output: &CommandOutput,
+        log_file: &mut logging::LogFile,
+    ) -> Result<(), Box<dyn Error>> {
+        logging::write_output(log_file, output)?;
+
+        let pinned_casks = get_list_setting("PINNED_CASKS");
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        for line in stdout.lines() {
+            let Some((cask, versions)) = line.trim().split_once(' ') else {
+                continue;
+            };
+            if let (true, Some((installed, latest))) = (
+                pinned_casks.iter().any(|pinned| pinned == cask),
+                versions.split_once(" != "),
+            ) {
+                logging::log_line(
+                    log_file,
+                    &format!(
+                        "Pinned cask {} not upgraded: {} installed, {} available",
+                        cask,
+                        installed.trim_matches(|c| c == '(' || c == ')'),
+                        latest
+                    ),
+                )?;
+            }
+        }
+
+        // List the pinned formulae that were left behind.
+        let pinned: Vec<String> = stdout
+            .lines()
+            .filter_map(|line| {
+                let (formula, rest) = line.trim().split_once(' ')?;
+                let (installed, rest) = rest.split_once(" < ")?;
+                let (latest, _) = rest.split_once(" [pinned at ")?;
+                Some(format!(
+                    "{} {} (available: {})",
+                    formula,
+                    installed.trim_matches(|c| c == '(' || c == ')'),
+                    latest
+                ))
+            })
+            .collect();
+        if !pinned.is_empty() {
+            logging::log_line(log_file, "Pinned formulae skipped:")?;
+            for formula in &pinned {
+                logging::log_line(log_file, &format!("  {}", formula))?;
+            }
+        }
+
+        // Summarize how `brew bundle` changed the installed packages.
+        if config::get_text_setting("BREWFILE", self.name()).is_some() {
+            for (prefix, action) in [
+                ("Installing ", "installed"),
+                ("Upgrading ", "upgraded"),
+                ("Uninstalling ", "removed"),
+            ] {
+                let packages: Vec<&str> = stdout
+                    .lines()
+                    .filter_map(|line| line.trim().strip_prefix(prefix)?.split_whitespace().next())
+                    .map(|package| package.trim_end_matches("..."))
+                    .collect();
+                if !packages.is_empty() {
+                    logging::log_line(
+                        log_file,
+                        &format!("Brewfile: {} {}", action, packages.join(", ")),
+                    )?;
+                }
+            }
+        }
+
+        if output.succeeded() {
+            return Ok(());
+        }
+
+        let brew = self.brew.as_deref().unwrap_or("brew");
+        for upgrade in self.changed_formulae(brew)? {
+            logging::log_line(
+                log_file,
+                &format!(
+                    "Upgraded before failure: {} {} -> {} (roll back with `cronup rollback brew {}`)",
+                    upgrade.formula, upgrade.old, upgrade.new, upgrade.formula
+                ),
+            )?;
+        }
+        Ok(())
+    }
+
+    // `brew upgrade` announces every formula it upgrades with "==> Upgrading <formula>", after
+    // a "==> Upgrading <n> outdated packages:" overview that mustn't be counted.
+    fn updated_packages(&self, output: &CommandOutput) -> Option<usize> {
+        Some(count_lines(output, |line| {
+            line.strip_prefix("==> Upgrading ")
+                .is_some_and(|rest| !rest.starts_with(|c: char| c.is_ascii_digit()))
+        }))
+    }
+
+    // The "==> Upgrading <n> outdated packages:" overview lists every upgrade as "<name> <old> -> <new>",
+    // once for the formulae and once for the casks.
+    fn summary(&self, output: &CommandOutput) -> Vec<String> {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let mut upgrades = Vec::new();
+        let mut in_overview = false;
+        for line in stdout.lines().map(str::trim) {
+            if line.starts_with("==>") {
+                in_overview = line
+                    .strip_prefix("==> Upgrading ")
+                    .is_some_and(|rest| rest.ends_with(':'));
+            } else if in_overview && line.contains(" -> ") {
+                upgrades.push(line.to_string());
+            }
+        }
+        summary_line("Upgraded", ("package", "packages"), &upgrades)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixture;
+    use super::*;
+
+    // Recorded with a German locale: brew's own messages stay English, git's and the number formats don't.
+    const UPGRADE: &str = include_str!("../../tests/fixtures/brew-upgrade.de.txt");
+
+    #[test]
+    fn rollback_relinks_old_keg_or_extracts_it() {
+        assert_eq!(
+            rollback_commands("/opt/homebrew/bin/brew", "ripgrep", "14.1.0", true),
+            [
+                "'/opt/homebrew/bin/brew' uninstall --ignore-dependencies 'ripgrep'",
+                "'/opt/homebrew/bin/brew' link 'ripgrep'",
+                "'/opt/homebrew/bin/brew' pin 'ripgrep'",
+            ]
+        );
+        assert_eq!(
+            rollback_commands("brew", "git", "2.44.0_1", false)[1..],
+            [
+                "'brew' extract --force --version='2.44.0' 'git' cronup/rollback",
+                "'brew' uninstall --ignore-dependencies 'git'",
+                "'brew' install 'cronup/rollback/git@2.44.0'",
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_localized_upgrade() {
+        let output = fixture(UPGRADE);
+        let brew = Brew { brew: None };
+        assert_eq!(brew.updated_packages(&output), Some(2));
+        assert_eq!(
+            brew.summary(&output),
+            ["Upgraded 2 packages: ripgrep 14.1.0 -> 14.1.1, git 2.44.0 -> 2.45.0"]
+        );
+    }
+}
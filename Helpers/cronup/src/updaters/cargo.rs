@@ -0,0 +1,231 @@
+// Updates installed Cargo packages through the `cargo-update` subcommand.
+// With `CRONUP_BINSTALL_CARGO=1` and cargo-binstall installed, `cargo install-update -l` only lists the
+// outdated packages, and cargo-binstall installs their prebuilt binaries instead of compiling them. It
+// falls back to building a package from source (with `cargo install`) when no binary is available.
+// When rustup changed the toolchain since the version it recorded (see rustup.rs), every package is
+// rebuilt with `cargo install-update -a --force` instead, since binaries built with the old toolchain
+// may break, and the cascade is logged.
+
+use std::{error::Error, fs, path::Path, process::Command, sync::Mutex, time::Duration};
+
+use super::{locate, quote, rustup, summary_line, Updater};
+use crate::{config, logging, process, process::CommandOutput};
+
+// How long `rustc --version` may take.
+const VERSION_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct Cargo {
+    cargo: Option<String>,    // The path to the Cargo executable, if installed.
+    binstall: Option<String>, // The path to cargo-binstall, if it is installed and enabled.
+    toolchain_change: Mutex<Option<(String, String)>>, // The toolchain versions a forced rebuild is for.
+}
+
+impl Cargo {
+    pub fn new() -> Self {
+        Cargo {
+            cargo: locate("cargo"),
+            binstall: (config::get_setting("BINSTALL", "cargo", 0) != 0)
+                .then(|| locate("cargo-binstall"))
+                .flatten(),
+            toolchain_change: Mutex::new(None),
+        }
+    }
+
+    // Function to compare the toolchain version rustup recorded with the current one.
+    // Returns both if they differ; a record that matches the current toolchain is removed.
+    fn detect_toolchain_change(&self) -> Option<(String, String)> {
+        let record = rustup::toolchain_record_path();
+        let before = fs::read_to_string(&record).ok()?.trim().to_string();
+        let mut command = Command::new(locate("rustc")?);
+        command.arg("--version");
+        let output = process::run_with_timeout(command, VERSION_TIMEOUT, None).ok()?;
+        let after = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if !output.succeeded() || after.is_empty() {
+            return None;
+        }
+        if before == after || before.is_empty() {
+            let _ = fs::remove_file(&record);
+            return None;
+        }
+        Some((before, after))
+    }
+
+    // Function to check whether this run uses cargo-binstall, which a forced rebuild replaces.
+    fn uses_binstall(&self) -> bool {
+        self.binstall.is_some()
+            && self
+                .toolchain_change
+                .lock()
+                .map_or(true, |change| change.is_none())
+    }
+}
+
+impl Updater for Cargo {
+    fn name(&self) -> &'static str {
+        "cargo"
+    }
+
+    fn detect(&self) -> bool {
+        self.cargo.is_some()
+    }
+
+    // Packages should be rebuilt with the freshly updated toolchain.
+    fn dependencies(&self) -> &'static [&'static str] {
+        &["rustup"]
+    }
+
+    fn commands(&self) -> Vec<String> {
+        let cargo = quote(self.cargo.as_deref().unwrap_or("cargo"));
+        let change = self.detect_toolchain_change();
+        let forced = change.is_some();
+        if let Ok(mut slot) = self.toolchain_change.lock() {
+            *slot = change;
+        }
+        if forced {
+            // Rebuild every package with the new toolchain, and only then drop the recorded version.
+            return vec![
+                format!("{} install-update -a --force", cargo),
+                format!("rm -f {}", quote(&rustup::toolchain_record_path())),
+            ];
+        }
+        let Some(binstall) = &self.binstall else {
+            // Update all installed Cargo packages.
+            return vec![format!("{} install-update -a", cargo)];
+        };
+
+        // List the packages, keeping the table in the output for the summary, and install the outdated
+        // ones with cargo-binstall, which finds Cargo for source builds on `PATH`.
+        let cargo_dir = Path::new(self.cargo.as_deref().unwrap_or("cargo"))
+            .parent()
+            .map_or_else(String::new, |dir| dir.to_string_lossy().into_owned());
+        vec![format!(
+            "table=$({} install-update -l) && printf '%s\\n' \"$table\" && \
+             packages=$(printf '%s\\n' \"$table\" | /usr/bin/awk '$NF == \"Yes\" {{ print $1 }}') && \
+             {{ [ -z \"$packages\" ] || PATH={}:\"$PATH\" {} binstall --no-confirm $packages; }}",
+            cargo,
+            quote(&cargo_dir),
+            quote(binstall)
+        )]
+    }
+
+    // A forced rebuild is logged with the toolchain change that caused it.
+    fn post_process(
+        &self,
+        output: &CommandOutput,
+        log_file: &mut logging::LogFile,
+    ) -> Result<(), Box<dyn Error>> {
+        if let Some((before, after)) = self
+            .toolchain_change
+            .lock()
+            .ok()
+            .and_then(|change| change.clone())
+        {
+            logging::log_line(
+                log_file,
+                &format!(
+                    "Toolchain changed by rustup ({} -> {}) - rebuilding every package with --force.",
+                    before, after
+                ),
+            )?;
+        }
+        logging::write_output(log_file, output)
+    }
+
+    fn check_commands(&self) -> Vec<String> {
+        // List the installed packages and whether an update is available, without installing anything.
+        vec![format!(
+            "{} install-update -l",
+            quote(self.cargo.as_deref().unwrap_or("cargo"))
+        )]
+    }
+
+    // cargo-update ends with "Overall updated <n> packages." when anything was updated. cargo-binstall
+    // installs every outdated package or fails, so then they are counted from the table.
+    fn updated_packages(&self, output: &CommandOutput) -> Option<usize> {
+        if self.uses_binstall() {
+            return Some(if output.succeeded() {
+                outdated_packages(output).len()
+            } else {
+                0
+            });
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let count = stdout.lines().find_map(|line| {
+            line.trim()
+                .strip_prefix("Overall updated ")?
+                .split_whitespace()
+                .next()?
+                .parse()
+                .ok()
+        });
+        Some(count.unwrap_or(0))
+    }
+
+    fn summary(&self, output: &CommandOutput) -> Vec<String> {
+        if self.uses_binstall() && !output.succeeded() {
+            return Vec::new();
+        }
+        summary_line(
+            "Updated",
+            ("package", "packages"),
+            &outdated_packages(output),
+        )
+    }
+}
+
+// Function to list the packages cargo-update marks as outdated, e.g. "ripgrep v14.1.0 -> v14.1.1".
+// It first prints a table of "<package> <installed> <latest> <needs update>" rows.
+fn outdated_packages(output: &CommandOutput) -> Vec<String> {
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stdout
+        .lines()
+        .filter_map(
+            |line| match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [package, installed, latest, "Yes"] => {
+                    Some(format!("{} {} -> {}", package, installed, latest))
+                }
+                _ => None,
+            },
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixture;
+    use super::*;
+
+    // Recorded with a German locale, which only changes git's progress output and the number formats.
+    const UPDATE: &str = include_str!("../../tests/fixtures/cargo-install-update.de.txt");
+    const BINSTALL: &str = include_str!("../../tests/fixtures/cargo-binstall.txt");
+
+    #[test]
+    fn parses_localized_update() {
+        let output = fixture(UPDATE);
+        let cargo = Cargo {
+            cargo: None,
+            binstall: None,
+            toolchain_change: Mutex::new(None),
+        };
+        assert_eq!(cargo.updated_packages(&output), Some(1));
+        assert_eq!(
+            cargo.summary(&output),
+            ["Updated 1 package: ripgrep v14.1.0 -> v14.1.1"]
+        );
+    }
+
+    #[test]
+    fn parses_binstall_update() {
+        let output = fixture(BINSTALL);
+        let cargo = Cargo {
+            cargo: None,
+            binstall: Some(String::from("cargo-binstall")),
+            toolchain_change: Mutex::new(None),
+        };
+        assert_eq!(cargo.updated_packages(&output), Some(2));
+        assert_eq!(
+            cargo.summary(&output),
+            ["Updated 2 packages: ripgrep v14.1.0 -> v14.1.1, cargo-update v13.4.0 -> v13.5.0"]
+        );
+    }
+}
@@ -0,0 +1,48 @@
+// Updates globally installed PHP packages through Composer.
+
+use super::{locate, quote, Updater};
+
+pub struct Composer {
+    composer: Option<String>, // The path to the composer executable, if installed.
+}
+
+impl Composer {
+    pub fn new() -> Self {
+        Composer {
+            composer: locate("composer"),
+        }
+    }
+}
+
+impl Updater for Composer {
+    fn name(&self) -> &'static str {
+        "composer"
+    }
+
+    fn detect(&self) -> bool {
+        self.composer.is_some()
+    }
+
+    // Composer and the PHP it runs on are usually installed through Homebrew or MacPorts.
+    fn dependencies(&self) -> &'static [&'static str] {
+        &["brew", "macports"]
+    }
+
+    fn commands(&self) -> Vec<String> {
+        vec![
+            // Update all global packages without prompting or coloring the output.
+            format!(
+                "{} global update --no-interaction --no-ansi",
+                quote(self.composer.as_deref().unwrap_or("composer"))
+            ),
+        ]
+    }
+
+    fn check_commands(&self) -> Vec<String> {
+        // List outdated global packages.
+        vec![format!(
+            "{} global outdated --no-interaction --no-ansi",
+            quote(self.composer.as_deref().unwrap_or("composer"))
+        )]
+    }
+}
@@ -0,0 +1,47 @@
+// Pulls newer versions of Docker images and prunes the dangling images they replace.
+// The images to refresh are listed in `CRONUP_IMAGES_DOCKER`, separated by spaces or commas
+// (e.g. "postgres:16, node:22-alpine"). Works with Docker Desktop and OrbStack alike, but their
+// engine must be running; cronup doesn't start it.
+
+use super::{locate, quote, Updater};
+use crate::config;
+
+pub struct Docker {
+    docker: Option<String>, // The path to the docker executable, if installed.
+}
+
+impl Docker {
+    pub fn new() -> Self {
+        Docker {
+            docker: locate("docker"),
+        }
+    }
+}
+
+impl Updater for Docker {
+    fn name(&self) -> &'static str {
+        "docker"
+    }
+
+    fn detect(&self) -> bool {
+        self.docker.is_some()
+    }
+
+    fn commands(&self) -> Vec<String> {
+        let docker = quote(self.docker.as_deref().unwrap_or("docker"));
+        let images = config::get_text_setting("IMAGES", self.name()).unwrap_or_default();
+
+        // Fail early with a clear message if the engine isn't running.
+        let mut commands = vec![format!("{} info --format '{{{{.ServerVersion}}}}'", docker)];
+        for image in images
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|image| !image.is_empty())
+        {
+            // Pull the newest version of the image's tag.
+            commands.push(format!("{} pull --quiet {}", docker, quote(image)));
+        }
+        // Remove the untagged images left behind by the pulls.
+        commands.push(format!("{} image prune --force", docker));
+        commands
+    }
+}
@@ -0,0 +1,91 @@
+// Updates installed Ruby gems and removes their old versions.
+// The system Ruby that ships with macOS is left alone, since changing it needs root privileges
+// and can break the operating system's own scripts.
+
+use super::{count_lines, locate, quote, summary_line, Updater};
+use crate::process::CommandOutput;
+
+// The gem executable of the system Ruby.
+const SYSTEM_GEM: &str = "/usr/bin/gem";
+
+pub struct Gem {
+    gem: Option<String>, // The path to a user-managed gem executable, if installed.
+}
+
+impl Gem {
+    pub fn new() -> Self {
+        Gem {
+            gem: locate("gem").filter(|path| path != SYSTEM_GEM),
+        }
+    }
+}
+
+impl Updater for Gem {
+    fn name(&self) -> &'static str {
+        "gem"
+    }
+
+    fn detect(&self) -> bool {
+        self.gem.is_some()
+    }
+
+    // Ruby itself is usually installed through Homebrew or MacPorts.
+    fn dependencies(&self) -> &'static [&'static str] {
+        &["brew", "macports"]
+    }
+
+    fn commands(&self) -> Vec<String> {
+        let gem = quote(self.gem.as_deref().unwrap_or("gem"));
+        vec![
+            // Update all installed gems.
+            format!("{} update", gem),
+            // Remove old versions of updated gems.
+            format!("{} cleanup", gem),
+        ]
+    }
+
+    fn check_commands(&self) -> Vec<String> {
+        // List outdated gems with their installed and available versions.
+        vec![format!(
+            "{} outdated",
+            quote(self.gem.as_deref().unwrap_or("gem"))
+        )]
+    }
+
+    // `gem update` prints "Updating <gem>" for every gem it updates, after "Updating installed gems".
+    fn updated_packages(&self, output: &CommandOutput) -> Option<usize> {
+        Some(count_lines(output, |line| {
+            line.starts_with("Updating ") && line != "Updating installed gems"
+        }))
+    }
+
+    // `gem update` ends with "Gems updated: <gem> <gem> ..." when anything was updated; it doesn't
+    // list the versions.
+    fn summary(&self, output: &CommandOutput) -> Vec<String> {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let gems: Vec<String> = stdout
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("Gems updated: "))
+            .flat_map(str::split_whitespace)
+            .map(String::from)
+            .collect();
+        summary_line("Updated", ("gem", "gems"), &gems)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixture;
+    use super::*;
+
+    // Recorded with a German locale, which only changes the fetch message and the number formats.
+    const UPDATE: &str = include_str!("../../tests/fixtures/gem-update.de.txt");
+
+    #[test]
+    fn parses_localized_update() {
+        let output = fixture(UPDATE);
+        let gem = Gem { gem: None };
+        assert_eq!(gem.updated_packages(&output), Some(2));
+        assert_eq!(gem.summary(&output), ["Updated 2 gems: rake, rubocop"]);
+    }
+}
@@ -0,0 +1,155 @@
+// Re-installs Go binaries at their latest version.
+// If gup is installed, it updates every binary in the Go bin directory. Otherwise the packages
+// listed in `CRONUP_PACKAGES_GO` (separated by spaces or commas, e.g. "golang.org/x/tools/gopls")
+// are installed again with `go install <package>@latest`.
+// The module versions of the installed binaries are recorded before the run and compared
+// afterwards, so every version change is logged.
+
+use std::{collections::BTreeMap, error::Error, fs};
+
+use super::{locate, quote, Updater};
+use crate::{config, logging, process, process::CommandOutput};
+
+pub struct Go {
+    go: Option<String>,  // The path to the go executable, if installed.
+    gup: Option<String>, // The path to the gup executable, if installed.
+}
+
+impl Go {
+    pub fn new() -> Self {
+        Go {
+            go: locate("go"),
+            gup: locate("gup"),
+        }
+    }
+
+    // Where the binary versions are recorded before updating.
+    fn snapshot_path() -> String {
+        format!("{}/cronup.go.snapshot", config::log_home())
+    }
+
+    // The directory `go install` puts binaries in: `GOBIN`, or the `bin` directory of the first `GOPATH` entry.
+    fn bin_dir(&self) -> String {
+        let go = quote(self.go.as_deref().unwrap_or("go"));
+        let command = process::shell(&format!("{} env GOBIN GOPATH", go));
+        let output = process::run_with_timeout(command, config::get_timeout(self.name()), None)
+            .map(|output| String::from_utf8_lossy(&output.stdout).into_owned())
+            .unwrap_or_default();
+        let mut lines = output.lines();
+        match (lines.next(), lines.next()) {
+            (Some(gobin), _) if !gobin.is_empty() => gobin.to_string(),
+            (_, Some(gopath)) if !gopath.is_empty() => {
+                format!("{}/bin", gopath.split(':').next().unwrap_or(gopath))
+            }
+            _ => config::expand_path("~/go/bin"),
+        }
+    }
+
+    // Lists the module versions of the binaries in the Go bin directory.
+    fn list_versions(&self) -> Result<BTreeMap<String, String>, Box<dyn Error>> {
+        let output = process::run_with_timeout(
+            process::shell(&self.version_command()),
+            config::get_timeout(self.name()),
+            None,
+        )?;
+        Ok(parse_versions(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    // The command that prints the module versions of the binaries in the Go bin directory.
+    fn version_command(&self) -> String {
+        format!(
+            "{} version -m {}",
+            quote(self.go.as_deref().unwrap_or("go")),
+            quote(&self.bin_dir())
+        )
+    }
+}
+
+// Function to parse `go version -m <dir>` output into the main module version of every binary.
+// Each binary starts with a "<path>: <go version>" line, followed by indented build information
+// that includes a "mod <module> <version>" line.
+fn parse_versions(listing: &str) -> BTreeMap<String, String> {
+    let mut versions = BTreeMap::new();
+    let mut binary = None;
+    for line in listing.lines() {
+        if !line.starts_with(char::is_whitespace) {
+            binary = line
+                .split_once(": ")
+                .map(|(path, _)| path.rsplit('/').next().unwrap_or(path).to_string());
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        if let (Some(name), Some("mod"), Some(module), Some(version)) =
+            (&binary, fields.next(), fields.next(), fields.next())
+        {
+            versions.insert(name.clone(), format!("{} {}", module, version));
+        }
+    }
+    versions
+}
+
+impl Updater for Go {
+    fn name(&self) -> &'static str {
+        "go"
+    }
+
+    fn detect(&self) -> bool {
+        self.go.is_some()
+    }
+
+    // Go itself is usually installed through Homebrew or MacPorts.
+    fn dependencies(&self) -> &'static [&'static str] {
+        &["brew", "macports"]
+    }
+
+    fn commands(&self) -> Vec<String> {
+        let go = quote(self.go.as_deref().unwrap_or("go"));
+        let mut commands = vec![
+            // Record the installed versions so changes can be logged afterwards.
+            format!(
+                "{} > {}",
+                self.version_command(),
+                quote(&Self::snapshot_path())
+            ),
+        ];
+        if let Some(gup) = &self.gup {
+            // Update every binary in the Go bin directory.
+            commands.push(format!("{} update", quote(gup)));
+        } else {
+            let packages = config::get_text_setting("PACKAGES", self.name()).unwrap_or_default();
+            for package in packages
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|package| !package.is_empty())
+            {
+                // Re-install the package at its latest version.
+                commands.push(format!("{} install {}@latest", go, quote(package)));
+            }
+        }
+        commands
+    }
+
+    // Logs every binary whose module version changed during the run, even if it failed later on.
+    fn post_process(
+        &self,
+        output: &CommandOutput,
+        log_file: &mut logging::LogFile,
+    ) -> Result<(), Box<dyn Error>> {
+        logging::write_output(log_file, output)?;
+
+        let before = match fs::read_to_string(Self::snapshot_path()) {
+            Ok(snapshot) => parse_versions(&snapshot),
+            Err(_) => return Ok(()),
+        };
+        for (binary, version) in self.list_versions()? {
+            match before.get(&binary) {
+                Some(previous) if *previous == version => {}
+                Some(previous) => logging::log_line(
+                    log_file,
+                    &format!("Updated {}: {} -> {}", binary, previous, version),
+                )?,
+                None => logging::log_line(log_file, &format!("Installed {}: {}", binary, version))?,
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+// Updates MacPorts and upgrades outdated ports, as an alternative or complement to Homebrew.
+// MacPorts is installed as root, so every command runs through sudo (see sudo.rs), and the updater
+// fails instead of hanging on a password prompt if sudo can't elevate without one.
+
+use super::{locate, quote, Updater};
+
+pub struct MacPorts {
+    port: Option<String>, // The path to the port executable, if installed.
+}
+
+impl MacPorts {
+    pub fn new() -> Self {
+        MacPorts {
+            port: locate("port"),
+        }
+    }
+}
+
+impl Updater for MacPorts {
+    fn name(&self) -> &'static str {
+        "macports"
+    }
+
+    fn detect(&self) -> bool {
+        self.port.is_some()
+    }
+
+    fn needs_sudo(&self) -> bool {
+        true
+    }
+
+    fn commands(&self) -> Vec<String> {
+        let port = quote(self.port.as_deref().unwrap_or("port"));
+        vec![
+            // Update MacPorts itself and the ports tree.
+            format!("{} selfupdate", port),
+            // Upgrade all outdated ports; `-N` answers any question with its default.
+            format!("{} -N upgrade outdated", port),
+            // Remove the versions that were deactivated by the upgrade.
+            format!("{} -N uninstall inactive", port),
+        ]
+    }
+
+    fn check_commands(&self) -> Vec<String> {
+        // List outdated ports against the ports tree of the last sync, which needs no root privileges.
+        vec![format!(
+            "{} outdated",
+            quote(self.port.as_deref().unwrap_or("port"))
+        )]
+    }
+}
@@ -0,0 +1,41 @@
+// Upgrades apps installed from the Mac App Store through the `mas` command line tool.
+
+use super::{locate, quote, Updater};
+
+pub struct Mas {
+    mas: Option<String>, // The path to the mas executable, if installed.
+}
+
+impl Mas {
+    pub fn new() -> Self {
+        Mas { mas: locate("mas") }
+    }
+}
+
+impl Updater for Mas {
+    fn name(&self) -> &'static str {
+        "mas"
+    }
+
+    fn detect(&self) -> bool {
+        self.mas.is_some()
+    }
+
+    fn commands(&self) -> Vec<String> {
+        let mas = quote(self.mas.as_deref().unwrap_or("mas"));
+        vec![
+            // List the apps with pending updates so the log shows what changed.
+            format!("{} outdated", mas),
+            // Upgrade all outdated apps.
+            format!("{} upgrade", mas),
+        ]
+    }
+
+    fn check_commands(&self) -> Vec<String> {
+        // List the apps with pending updates.
+        vec![format!(
+            "{} outdated",
+            quote(self.mas.as_deref().unwrap_or("mas"))
+        )]
+    }
+}
@@ -0,0 +1,54 @@
+// Updates mise plugins and the tool versions installed with mise.
+// Every plugin is updated on its own, with its name logged first, so the output of every plugin
+// can be told apart in the log. Tools are then upgraded within the versions their configuration allows.
+
+use super::{list_plugins, locate, quote, Updater};
+
+pub struct Mise {
+    mise: Option<String>, // The path to the mise executable, if installed.
+}
+
+impl Mise {
+    pub fn new() -> Self {
+        Mise {
+            mise: locate("mise"),
+        }
+    }
+}
+
+impl Updater for Mise {
+    fn name(&self) -> &'static str {
+        "mise"
+    }
+
+    fn detect(&self) -> bool {
+        self.mise.is_some()
+    }
+
+    // mise is usually installed through Homebrew or MacPorts.
+    fn dependencies(&self) -> &'static [&'static str] {
+        &["brew", "macports"]
+    }
+
+    fn commands(&self) -> Vec<String> {
+        let mise = quote(self.mise.as_deref().unwrap_or("mise"));
+        let mut commands = Vec::new();
+        for plugin in list_plugins(&mise, "plugins ls") {
+            // Announce the plugin so its output is recognizable in the log.
+            commands.push(format!("echo {}", quote(&format!("==> {}", plugin))));
+            // Update the plugin, which also makes newer tool versions known.
+            commands.push(format!("{} plugins update {}", mise, quote(&plugin)));
+        }
+        // Upgrade every installed tool.
+        commands.push(format!("{} upgrade --yes", mise));
+        commands
+    }
+
+    fn check_commands(&self) -> Vec<String> {
+        // List the tools with newer versions available.
+        vec![format!(
+            "{} outdated",
+            quote(self.mise.as_deref().unwrap_or("mise"))
+        )]
+    }
+}
@@ -0,0 +1,256 @@
+// The updaters cronup runs, each in its own module behind the `Updater` trait.
+// To add an updater, implement the trait in a new module and add it to `registry()`.
+// Tool binaries are located at run time, so updaters whose tool isn't installed are skipped.
+
+// Import various modules from the Rust standard library.
+use std::{env, error::Error, path::Path, time::Duration};
+
+// Shared helpers for logging and command output.
+use crate::{config, logging, process, process::CommandOutput};
+
+// One module per updater.
+mod asdf;
+mod brew;
+mod cargo;
+mod composer;
+mod docker;
+mod gem;
+mod go;
+mod macports;
+mod mas;
+mod mise;
+mod nix;
+mod node;
+mod nvim;
+mod pipx;
+mod rustup;
+mod softwareupdate;
+mod tlmgr;
+
+// The interface every updater implements. Updaters may run in parallel, so they must be `Sync`.
+pub trait Updater: Sync {
+    // The name used for the log file (`cronup.<name>.log`), per-updater settings, and the run summary.
+    fn name(&self) -> &'static str;
+
+    // Returns whether the tool is installed on this machine. Updaters that aren't detected are skipped.
+    fn detect(&self) -> bool;
+
+    // The names of the updaters that must run before this one, e.g. because they update its toolchain.
+    fn dependencies(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    // Returns whether the commands need root privileges, so they run through sudo (see sudo.rs).
+    // `CRONUP_SUDO_<NAME>` set to 1 or 0 overrides it, e.g. for a TeX Live tree owned by root.
+    fn needs_sudo(&self) -> bool {
+        false
+    }
+
+    // The commands below are only requested after `detect()` returned `true`.
+
+    // The shell commands to run, in order. Each one runs on its own, and by default the rest are
+    // skipped once one fails (see `CRONUP_ON_FAILURE`).
+    fn commands(&self) -> Vec<String>;
+
+    // How many seconds a command may go without any output before it counts as hung and its process
+    // group is killed, e.g. for a tool known to wait on something forever. 0 never kills it for that.
+    // `CRONUP_INACTIVITY_<NAME>` overrides it.
+    fn inactivity_limit(&self) -> u64 {
+        0
+    }
+
+    // How long the command at `index` in `commands()` may take, if less than what is left of the
+    // updater's timeout, e.g. for a step that is known to hang at times. `None` leaves it at that.
+    fn step_timeout(&self, _index: usize) -> Option<Duration> {
+        None
+    }
+
+    // The shell commands that list outdated packages without changing anything, for `cronup --check`.
+    // Every one runs, even if the previous one failed. Updaters that can't tell return none.
+    fn check_commands(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    // Writes the outcome of the run to the updater's log file. By default, every output line is logged.
+    fn post_process(
+        &self,
+        output: &CommandOutput,
+        log_file: &mut logging::LogFile,
+    ) -> Result<(), Box<dyn Error>> {
+        logging::write_output(log_file, output)
+    }
+
+    // Counts the packages a run updated, for the trend report. `None` if the updater can't tell.
+    fn updated_packages(&self, _output: &CommandOutput) -> Option<usize> {
+        None
+    }
+
+    // Summarizes what the run changed for a human reader, logged after the raw output,
+    // e.g. "Upgraded 2 packages: ripgrep 14.1.0 -> 14.1.1, git 2.44.0 -> 2.45.0". Empty if nothing did.
+    fn summary(&self, _output: &CommandOutput) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+// Function to list every updater in the order they run.
+// Updaters run in the order listed here unless a dependency requires one to move later.
+pub fn registry() -> Vec<Box<dyn Updater>> {
+    resolve_order(vec![
+        Box::new(brew::Brew::new()),
+        Box::new(macports::MacPorts::new()),
+        Box::new(cargo::Cargo::new()),
+        Box::new(rustup::Rustup::new()),
+        Box::new(mas::Mas::new()),
+        Box::new(softwareupdate::SoftwareUpdate::new()),
+        Box::new(node::Node::new()),
+        Box::new(pipx::Pipx::new()),
+        Box::new(gem::Gem::new()),
+        Box::new(composer::Composer::new()),
+        Box::new(go::Go::new()),
+        Box::new(asdf::Asdf::new()),
+        Box::new(mise::Mise::new()),
+        Box::new(tlmgr::Tlmgr::new()),
+        Box::new(nix::Nix::new()),
+        Box::new(docker::Docker::new()),
+        Box::new(nvim::Nvim::new()),
+    ])
+}
+
+// Function to order updaters so that every updater runs after its dependencies.
+// The order is otherwise kept stable. Dependencies that aren't registered are ignored, and
+// updaters caught in a dependency cycle are appended in their original order.
+fn resolve_order(mut pending: Vec<Box<dyn Updater>>) -> Vec<Box<dyn Updater>> {
+    let mut ordered: Vec<Box<dyn Updater>> = Vec::with_capacity(pending.len());
+
+    while !pending.is_empty() {
+        // A dependency is satisfied once it has been placed or if it isn't pending at all.
+        let ready = pending.iter().position(|updater| {
+            updater
+                .dependencies()
+                .iter()
+                .all(|dependency| !pending.iter().any(|other| other.name() == *dependency))
+        });
+
+        match ready {
+            Some(index) => ordered.push(pending.remove(index)),
+            None => {
+                // Every remaining updater waits on another one, so there is a cycle.
+                ordered.append(&mut pending);
+            }
+        }
+    }
+
+    ordered
+}
+
+// Directories searched after `PATH`, because launchd starts cronup with a minimal `PATH`.
+// `$CARGO_HOME/bin` is searched as well when `CARGO_HOME` is set.
+const FALLBACK_DIRS: &[&str] = &[
+    "/opt/homebrew/bin",
+    "/usr/local/bin",
+    "/opt/local/bin",
+    "~/.cargo/bin",
+    "~/go/bin",
+    "/usr/local/go/bin",
+    "~/.local/bin",
+    "~/.orbstack/bin",
+    "/Library/TeX/texbin",
+    "~/.nix-profile/bin",
+    "/run/current-system/sw/bin",
+    "/nix/var/nix/profiles/default/bin",
+];
+
+// Function to find a tool's executable, returning its full path.
+// `CRONUP_BIN_<TOOL>` (e.g. `CRONUP_BIN_BREW`) overrides the search entirely; otherwise `PATH`
+// is searched first, followed by `$CARGO_HOME/bin` and the fallback directories.
+pub fn locate(tool: &str) -> Option<String> {
+    // A configured override is used as-is after expanding `~` and variables, but only if it exists.
+    let override_key = format!("CRONUP_BIN_{}", tool.to_uppercase().replace('-', "_"));
+    if let Ok(path) = env::var(override_key) {
+        let path = config::expand_path(&path);
+        return Path::new(&path).is_file().then_some(path);
+    }
+
+    // Collect the directories to search, in order of preference.
+    let mut dirs: Vec<String> = env::var("PATH")
+        .unwrap_or_default()
+        .split(':')
+        .filter(|dir| !dir.is_empty())
+        .map(config::expand_path)
+        .collect();
+    if let Ok(cargo_home) = env::var("CARGO_HOME") {
+        dirs.push(format!("{}/bin", config::expand_path(&cargo_home)));
+    }
+    dirs.extend(FALLBACK_DIRS.iter().map(|dir| config::expand_path(dir)));
+
+    // Return the first directory that contains the tool.
+    dirs.iter()
+        .map(|dir| Path::new(dir).join(tool))
+        .find(|path| path.is_file())
+        .map(|path| path.to_string_lossy().into_owned())
+}
+
+// How long listing an updater's plugins may take before it is given up.
+const LIST_TIMEOUT: Duration = Duration::from_secs(60);
+
+// Function to list the plugins of a plugin-based tool (e.g. asdf or mise), one per output line.
+// `executable` is the quoted path to the tool and `list_command` its arguments for listing plugins.
+// Only the first word of each line is used, so extra columns such as URLs are ignored.
+// Returns no plugins if the listing fails, leaving the updater's other commands to run.
+fn list_plugins(executable: &str, list_command: &str) -> Vec<String> {
+    let command = process::shell(&format!("{} {}", executable, list_command));
+    match process::run_with_timeout(command, LIST_TIMEOUT, None) {
+        Ok(output) if output.succeeded() => String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| line.split_whitespace().next())
+            .map(String::from)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+// Function to count the lines of a command's standard output that match a predicate.
+// Lines dropped by output sampling aren't counted, so the result is a lower bound.
+fn count_lines(output: &CommandOutput, matches: impl Fn(&str) -> bool) -> usize {
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| matches(line.trim()))
+        .count()
+}
+
+// Function to phrase a list of changes as a summary line, e.g. "Upgraded 1 package: ripgrep 14.1.0 -> 14.1.1".
+// `noun` is the singular and plural of what changed. Returns no line if nothing changed.
+fn summary_line(action: &str, noun: (&str, &str), changes: &[String]) -> Vec<String> {
+    if changes.is_empty() {
+        return Vec::new();
+    }
+    vec![format!(
+        "{} {} {}: {}",
+        action,
+        changes.len(),
+        if changes.len() == 1 { noun.0 } else { noun.1 },
+        changes.join(", ")
+    )]
+}
+
+// Function to roll a package of an updater back to its version before the last upgrade, for
+// `cronup rollback <updater> <package>`. Only Homebrew formulae can be rolled back so far.
+pub fn rollback(name: &str, package: &str) -> Result<(), Box<dyn Error>> {
+    match name {
+        "brew" => brew::rollback(package),
+        _ => Err(format!("Rolling back isn't supported for {}", name).into()),
+    }
+}
+
+// Function to quote a path for use in a shell command line.
+pub fn quote(path: &str) -> String {
+    format!("'{}'", path.replace('\'', "'\\''"))
+}
+
+// Function to build the successful output of a command from a recorded fixture, for parser tests.
+#[cfg(test)]
+fn fixture(stdout: &str) -> CommandOutput {
+    let mut output = CommandOutput::empty();
+    output.stdout = stdout.as_bytes().to_vec();
+    output
+}
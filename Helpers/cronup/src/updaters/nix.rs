@@ -0,0 +1,54 @@
+// Updates packages installed with Nix.
+// By default, every package in the user's Nix profile is upgraded with `nix profile upgrade`.
+// Set `CRONUP_FLAKE_NIX` to a flake directory (e.g. a nix-darwin configuration) to update its lock
+// file and rebuild it instead. The rebuild runs `darwin-rebuild switch --flake <path>` unless
+// `CRONUP_REBUILD_NIX` sets a different command, which is run from within the flake directory.
+
+use super::{locate, quote, Updater};
+use crate::config;
+
+pub struct Nix {
+    nix: Option<String>,            // The path to the nix executable, if installed.
+    darwin_rebuild: Option<String>, // The path to the darwin-rebuild executable, if installed.
+}
+
+impl Nix {
+    pub fn new() -> Self {
+        Nix {
+            nix: locate("nix"),
+            darwin_rebuild: locate("darwin-rebuild"),
+        }
+    }
+}
+
+impl Updater for Nix {
+    fn name(&self) -> &'static str {
+        "nix"
+    }
+
+    fn detect(&self) -> bool {
+        self.nix.is_some()
+    }
+
+    fn commands(&self) -> Vec<String> {
+        let nix = quote(self.nix.as_deref().unwrap_or("nix"));
+        let flake = match config::get_text_setting("FLAKE", self.name()) {
+            Some(flake) => quote(&config::expand_path(&flake)),
+            // Without a flake, upgrade the packages of the user's profile.
+            None => return vec![format!("{} profile upgrade '.*'", nix)],
+        };
+        let rebuild = config::get_text_setting("REBUILD", self.name()).unwrap_or_else(|| {
+            format!(
+                "{} switch --flake {}",
+                quote(self.darwin_rebuild.as_deref().unwrap_or("darwin-rebuild")),
+                flake
+            )
+        });
+        vec![
+            // Update the flake's inputs and record them in its lock file.
+            format!("{} flake update --flake {}", nix, flake),
+            // Rebuild the configuration from the updated lock file.
+            format!("cd {} && {}", flake, rebuild),
+        ]
+    }
+}
@@ -0,0 +1,64 @@
+// Updates globally installed Node.js packages for every installed package manager (npm, pnpm, yarn).
+
+use super::{locate, quote, Updater};
+
+pub struct Node {
+    npm: Option<String>,  // The path to the npm executable, if installed.
+    pnpm: Option<String>, // The path to the pnpm executable, if installed.
+    yarn: Option<String>, // The path to the yarn executable, if installed.
+}
+
+impl Node {
+    pub fn new() -> Self {
+        Node {
+            npm: locate("npm"),
+            pnpm: locate("pnpm"),
+            yarn: locate("yarn"),
+        }
+    }
+}
+
+impl Updater for Node {
+    fn name(&self) -> &'static str {
+        "node"
+    }
+
+    fn detect(&self) -> bool {
+        self.npm.is_some() || self.pnpm.is_some() || self.yarn.is_some()
+    }
+
+    // Node.js itself is usually installed through Homebrew or MacPorts.
+    fn dependencies(&self) -> &'static [&'static str] {
+        &["brew", "macports"]
+    }
+
+    fn commands(&self) -> Vec<String> {
+        let mut commands = Vec::new();
+        if let Some(npm) = &self.npm {
+            // Update all global npm packages.
+            commands.push(format!("{} update -g", quote(npm)));
+        }
+        if let Some(pnpm) = &self.pnpm {
+            // Update all global pnpm packages.
+            commands.push(format!("{} update -g", quote(pnpm)));
+        }
+        if let Some(yarn) = &self.yarn {
+            // Update all global yarn packages (Yarn Classic; later versions have no global packages).
+            commands.push(format!("{} global upgrade", quote(yarn)));
+        }
+        commands
+    }
+
+    fn check_commands(&self) -> Vec<String> {
+        let mut commands = Vec::new();
+        if let Some(npm) = &self.npm {
+            // List outdated global npm packages.
+            commands.push(format!("{} outdated -g", quote(npm)));
+        }
+        if let Some(pnpm) = &self.pnpm {
+            // List outdated global pnpm packages.
+            commands.push(format!("{} outdated -g", quote(pnpm)));
+        }
+        commands
+    }
+}
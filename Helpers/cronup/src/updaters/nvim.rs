@@ -0,0 +1,387 @@
+// Updates Neovim plugins by running their plugin manager in a headless Neovim, followed by the LSP
+// servers, linters, and formatters Mason manages: `:MasonUpdate` refreshes its registry and
+// `:MasonToolsUpdateSync` (from mason-tool-installer) updates the installed tools, each only if it exists.
+// The synchronous variant is used because `:MasonToolsUpdate` would still be running when Neovim quits.
+// Last, `:TSUpdateSync` updates the Treesitter parsers, unless `CRONUP_TREESITTER_NVIM` is 0. Parsers are
+// compiled locally and a compilation occasionally never ends, so that step has its own timeout,
+// `CRONUP_TREESITTER_TIMEOUT_NVIM` (default 600 seconds), instead of taking up the updater's.
+// Headless Neovim sometimes waits on a prompt that never shows, so a step without any output for
+// `CRONUP_INACTIVITY_NVIM` (default 300) seconds is killed as hung, first with SIGTERM and then SIGKILL.
+//
+// The plugin manager is detected from the files it keeps in Neovim's config and data directories
+// (`$XDG_CONFIG_HOME/nvim` and `$XDG_DATA_HOME/nvim`, by default under `~/.config` and `~/.local/share`):
+// lazy.nvim, packer.nvim, vim-plug, or paq-nvim, in that order, and Lazy if none is found.
+// `CRONUP_PLUGIN_MANAGER_NVIM` set to "lazy", "packer", "vim-plug", or "paq" skips the detection; any
+// other value except "auto" is a custom Ex command that updates the plugins synchronously, e.g.
+// "MyPlugins update".
+
+use std::{
+    env,
+    error::Error,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use super::{locate, quote, summary_line, Updater};
+use crate::{config, logging, process::CommandOutput};
+
+// How long updating the Treesitter parsers may take when `CRONUP_TREESITTER_TIMEOUT_NVIM` is not set.
+const DEFAULT_TREESITTER_TIMEOUT_SECS: u64 = 600;
+
+// How long Neovim may go without output before it counts as hung, unless `CRONUP_INACTIVITY_NVIM` is set.
+const DEFAULT_INACTIVITY_SECS: u64 = 300;
+
+// The position of the Treesitter step in `commands()`, after the Lazy sync and the Mason update.
+const TREESITTER_STEP: usize = 2;
+
+// The plugin managers the updater can run.
+#[derive(Debug, PartialEq)]
+enum Manager {
+    Lazy,
+    Packer,
+    VimPlug,
+    Paq,
+    // A custom Ex command, without the leading colon.
+    Custom(String),
+}
+
+impl Manager {
+    // Function to get the manager's name for the log.
+    fn name(&self) -> &str {
+        match self {
+            Manager::Lazy => "lazy",
+            Manager::Packer => "packer",
+            Manager::VimPlug => "vim-plug",
+            Manager::Paq => "paq",
+            Manager::Custom(command) => command,
+        }
+    }
+
+    // Function to get the Neovim arguments that update the plugins and quit once they are done.
+    // packer and paq update asynchronously, so Neovim quits on the event they send when they finish,
+    // and with an error right away if their command doesn't exist, since the event would never come.
+    fn arguments(&self) -> String {
+        match self {
+            Manager::Lazy => String::from("'+Lazy! sync' +qa"),
+            Manager::Packer => async_arguments("PackerComplete", "PackerSync"),
+            Manager::VimPlug => String::from("'+PlugUpgrade' '+PlugUpdate --sync' +qa"),
+            Manager::Paq => async_arguments("PaqDoneSync", "PaqSync"),
+            Manager::Custom(command) => format!("{} +qa", quote(&format!("+{}", command))),
+        }
+    }
+}
+
+pub struct Nvim {
+    nvim: Option<String>, // The path to the Neovim executable, if installed.
+    manager: Manager,     // The plugin manager that updates the plugins.
+    treesitter: bool,     // Whether the Treesitter parsers are updated.
+}
+
+impl Nvim {
+    pub fn new() -> Self {
+        let manager = match config::get_text_setting("PLUGIN_MANAGER", "nvim").as_deref() {
+            None | Some("" | "auto") => detect_manager(
+                &xdg_dir("XDG_CONFIG_HOME", ".config"),
+                &xdg_dir("XDG_DATA_HOME", ".local/share"),
+            ),
+            Some("lazy") => Manager::Lazy,
+            Some("packer") => Manager::Packer,
+            Some("vim-plug") => Manager::VimPlug,
+            Some("paq") => Manager::Paq,
+            Some(command) => Manager::Custom(command.trim_start_matches(':').to_string()),
+        };
+        Nvim {
+            nvim: locate("nvim"),
+            manager,
+            treesitter: config::get_setting("TREESITTER", "nvim", 1) != 0,
+        }
+    }
+}
+
+// Function to build the Neovim arguments that run an asynchronous plugin manager's command and quit on
+// the event it sends when done, or with an error if the command doesn't exist.
+fn async_arguments(event: &str, command: &str) -> String {
+    format!(
+        "-c 'autocmd User {} quitall' -c 'if exists(\":{}\") == 2 | execute \"{}\" | else | cquit | endif'",
+        event, command, command
+    )
+}
+
+// Function to get Neovim's directory in an XDG base directory, e.g. `~/.config/nvim`, from the variable
+// if it is set or else from its default below the home directory.
+fn xdg_dir(variable: &str, default: &str) -> PathBuf {
+    let base = env::var(variable)
+        .ok()
+        .filter(|base| !base.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(&env::var("HOME").unwrap_or_default()).join(default));
+    base.join("nvim")
+}
+
+// Function to detect the plugin manager from the files it keeps in Neovim's config and data directories.
+// Lazy is assumed if none is found, since it was the only one supported before.
+fn detect_manager(config_dir: &Path, data_dir: &Path) -> Manager {
+    let markers = [
+        (Manager::Lazy, config_dir.join("lazy-lock.json")),
+        (Manager::Lazy, data_dir.join("lazy/lazy.nvim")),
+        (
+            Manager::Packer,
+            config_dir.join("plugin/packer_compiled.lua"),
+        ),
+        (
+            Manager::Packer,
+            data_dir.join("site/pack/packer/start/packer.nvim"),
+        ),
+        (Manager::VimPlug, config_dir.join("autoload/plug.vim")),
+        (Manager::VimPlug, data_dir.join("site/autoload/plug.vim")),
+        (Manager::Paq, data_dir.join("site/pack/paqs/start/paq-nvim")),
+    ];
+    markers
+        .into_iter()
+        .find(|(_, marker)| marker.exists())
+        .map_or(Manager::Lazy, |(manager, _)| manager)
+}
+
+impl Updater for Nvim {
+    fn name(&self) -> &'static str {
+        "nvim"
+    }
+
+    fn detect(&self) -> bool {
+        self.nvim.is_some()
+    }
+
+    // Plugins should be synced by the Neovim version Homebrew or MacPorts just upgraded.
+    fn dependencies(&self) -> &'static [&'static str] {
+        &["brew", "macports"]
+    }
+
+    fn commands(&self) -> Vec<String> {
+        let nvim = quote(self.nvim.as_deref().unwrap_or("nvim"));
+        let mut commands = vec![
+            // Run Neovim without a user interface, set the verbosity level to 1 for logging,
+            // update the plugins with their manager, and quit Neovim afterwards.
+            format!("{} --headless -V1 {}", nvim, self.manager.arguments()),
+            // Update Mason's registry and tools in a fresh Neovim, so the plugins just synced are loaded.
+            format!(
+                "{} --headless -V1 '+{}' '+{}' +qa",
+                nvim,
+                if_command_exists("MasonUpdate"),
+                if_command_exists("MasonToolsUpdateSync")
+            ),
+        ];
+        if self.treesitter {
+            // Update the parsers nvim-treesitter installed, waiting for them to compile.
+            commands.push(format!(
+                "{} --headless -V1 '+{}' +qa",
+                nvim,
+                if_command_exists("TSUpdateSync")
+            ));
+        }
+        commands
+    }
+
+    // A headless Neovim waiting on a prompt never exits, and wedged the whole run before.
+    fn inactivity_limit(&self) -> u64 {
+        DEFAULT_INACTIVITY_SECS
+    }
+
+    fn step_timeout(&self, index: usize) -> Option<Duration> {
+        (self.treesitter && index == TREESITTER_STEP).then(|| {
+            Duration::from_secs(config::get_setting(
+                "TREESITTER_TIMEOUT",
+                self.name(),
+                DEFAULT_TREESITTER_TIMEOUT_SECS,
+            ))
+        })
+    }
+
+    // Only Lazy can list plugin updates without installing them.
+    fn check_commands(&self) -> Vec<String> {
+        if self.manager != Manager::Lazy {
+            return Vec::new();
+        }
+        vec![format!(
+            // Run Neovim without a user interface and run the ':Lazy check' command, which fetches
+            // plugin updates and logs them without installing them.
+            "{} --headless -V1 '+Lazy! check' +qa",
+            quote(self.nvim.as_deref().unwrap_or("nvim"))
+        )]
+    }
+
+    // Neovim's output doesn't say whether the sync worked, so a status line precedes it.
+    fn post_process(
+        &self,
+        output: &CommandOutput,
+        log_file: &mut logging::LogFile,
+    ) -> Result<(), Box<dyn Error>> {
+        logging::log_line(
+            log_file,
+            &format!(
+                "Neovim plugin update with {} {}",
+                self.manager.name(),
+                if output.succeeded() {
+                    "completed successfully"
+                } else {
+                    "failed"
+                }
+            ),
+        )?;
+        logging::write_output(log_file, output)
+    }
+
+    fn updated_packages(&self, output: &CommandOutput) -> Option<usize> {
+        Some(
+            mason_results(output, "successfully installed").len()
+                + treesitter_results(output, false).len(),
+        )
+    }
+
+    fn summary(&self, output: &CommandOutput) -> Vec<String> {
+        let updated = mason_results(output, "successfully installed");
+        let failed = mason_results(output, "failed to install");
+        let mut summary = summary_line("Updated", ("Mason package", "Mason packages"), &updated);
+        summary.extend(summary_line(
+            "Failed to update",
+            ("Mason package", "Mason packages"),
+            &failed,
+        ));
+        summary.extend(summary_line(
+            "Updated",
+            ("Treesitter parser", "Treesitter parsers"),
+            &treesitter_results(output, false),
+        ));
+        summary.extend(summary_line(
+            "Failed to update",
+            ("Treesitter parser", "Treesitter parsers"),
+            &treesitter_results(output, true),
+        ));
+        summary
+    }
+}
+
+// Function to build an Ex command that runs a user command only if it is defined, e.g. by a plugin
+// that isn't installed everywhere. The command runs through `execute`, since a user command defined
+// without `-bar` would take the following `| endif` as its argument.
+fn if_command_exists(command: &str) -> String {
+    format!(
+        "if exists(\":{}\") == 2 | execute \"{}\" | endif",
+        command, command
+    )
+}
+
+// Function to get Neovim's messages. It prints them to standard error in headless mode, so both streams
+// are read.
+fn messages(output: &CommandOutput) -> String {
+    format!(
+        "{}\n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    )
+}
+
+// Function to list the Mason packages mason-tool-installer reported with an outcome, e.g.
+// "stylua: successfully installed", together with the version it was updating them to, if logged.
+fn mason_results(output: &CommandOutput, outcome: &str) -> Vec<String> {
+    let text = messages(output);
+    let messages: Vec<&str> = text
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("[mason-tool-installer] "))
+        .collect();
+    messages
+        .iter()
+        .filter_map(|message| message.strip_suffix(&format!(": {}", outcome)))
+        .map(|package| {
+            let version = messages
+                .iter()
+                .find_map(|message| message.strip_prefix(&format!("{}: updating to ", package)));
+            match version {
+                Some(version) => format!("{} -> {}", package, version),
+                None => package.to_string(),
+            }
+        })
+        .collect()
+}
+
+// Function to list the Treesitter parsers nvim-treesitter reported as installed, e.g. "Treesitter
+// parser for lua has been installed", or as failed, e.g. "nvim-treesitter[lua]: Error during compilation".
+fn treesitter_results(output: &CommandOutput, failed: bool) -> Vec<String> {
+    let mut parsers: Vec<String> = Vec::new();
+    for line in messages(output).lines() {
+        let parser = if failed {
+            line.split_once("nvim-treesitter[")
+                .and_then(|(_, rest)| rest.split_once("]:"))
+                .map(|(parser, _)| parser)
+        } else {
+            line.split_once("Treesitter parser for ")
+                .and_then(|(_, rest)| rest.split_once(" has been installed"))
+                .map(|(parser, _)| parser)
+        };
+        if let Some(parser) = parser.filter(|parser| !parsers.iter().any(|other| other == parser)) {
+            parsers.push(parser.to_string());
+        }
+    }
+    parsers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixture;
+    use super::*;
+
+    const MASON_UPDATE: &str = include_str!("../../tests/fixtures/mason-tools-update.txt");
+    const TS_UPDATE: &str = include_str!("../../tests/fixtures/ts-update-sync.txt");
+
+    #[test]
+    fn parses_mason_tools_update() {
+        let output = fixture(MASON_UPDATE);
+        let nvim = Nvim {
+            nvim: None,
+            manager: Manager::Lazy,
+            treesitter: true,
+        };
+        assert_eq!(nvim.updated_packages(&output), Some(2));
+        assert_eq!(
+            nvim.summary(&output),
+            [
+                "Updated 2 Mason packages: stylua -> v0.20.0, lua-language-server -> 3.9.3",
+                "Failed to update 1 Mason package: pyright -> 1.1.366"
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_treesitter_update() {
+        let output = fixture(TS_UPDATE);
+        let nvim = Nvim {
+            nvim: None,
+            manager: Manager::Lazy,
+            treesitter: true,
+        };
+        assert_eq!(nvim.updated_packages(&output), Some(2));
+        assert_eq!(
+            nvim.summary(&output),
+            [
+                "Updated 2 Treesitter parsers: lua, markdown",
+                "Failed to update 1 Treesitter parser: rust"
+            ]
+        );
+    }
+
+    #[test]
+    fn detects_the_plugin_manager() {
+        let base = std::env::temp_dir().join(format!("cronup-nvim-{}", std::process::id()));
+        let (config_dir, data_dir) = (base.join("config"), base.join("data"));
+        let _ = std::fs::remove_dir_all(&base);
+        assert_eq!(detect_manager(&config_dir, &data_dir), Manager::Lazy);
+
+        std::fs::create_dir_all(data_dir.join("site/pack/paqs/start/paq-nvim")).unwrap();
+        assert_eq!(detect_manager(&config_dir, &data_dir), Manager::Paq);
+
+        std::fs::create_dir_all(config_dir.join("plugin")).unwrap();
+        std::fs::write(config_dir.join("plugin/packer_compiled.lua"), "").unwrap();
+        assert_eq!(detect_manager(&config_dir, &data_dir), Manager::Packer);
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
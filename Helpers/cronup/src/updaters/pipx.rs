@@ -0,0 +1,83 @@
+// Upgrades Python applications installed with pipx.
+
+use super::{count_lines, locate, quote, summary_line, Updater};
+use crate::process::CommandOutput;
+
+pub struct Pipx {
+    pipx: Option<String>, // The path to the pipx executable, if installed.
+}
+
+impl Pipx {
+    pub fn new() -> Self {
+        Pipx {
+            pipx: locate("pipx"),
+        }
+    }
+}
+
+impl Updater for Pipx {
+    fn name(&self) -> &'static str {
+        "pipx"
+    }
+
+    fn detect(&self) -> bool {
+        self.pipx.is_some()
+    }
+
+    // pipx and the Python it runs on are usually installed through Homebrew or MacPorts.
+    fn dependencies(&self) -> &'static [&'static str] {
+        &["brew", "macports"]
+    }
+
+    fn commands(&self) -> Vec<String> {
+        vec![
+            // Upgrade every installed application in its own virtual environment.
+            format!(
+                "{} upgrade-all",
+                quote(self.pipx.as_deref().unwrap_or("pipx"))
+            ),
+        ]
+    }
+
+    // pipx reports every upgrade as "upgraded package <name> from <old> to <new>".
+    fn updated_packages(&self, output: &CommandOutput) -> Option<usize> {
+        Some(count_lines(output, |line| {
+            line.starts_with("upgraded package ")
+        }))
+    }
+
+    fn summary(&self, output: &CommandOutput) -> Vec<String> {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let upgrades: Vec<String> = stdout
+            .lines()
+            .filter_map(|line| {
+                let rest = line.trim().strip_prefix("upgraded package ")?;
+                let (package, rest) = rest.split_once(" from ")?;
+                let (old, rest) = rest.split_once(" to ")?;
+                let new = rest.split_whitespace().next()?;
+                Some(format!("{} {} -> {}", package, old, new))
+            })
+            .collect();
+        summary_line("Upgraded", ("package", "packages"), &upgrades)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixture;
+    use super::*;
+
+    // Recorded with a German locale, which only changes the messages pipx doesn't print itself.
+    const UPGRADE: &str = include_str!("../../tests/fixtures/pipx-upgrade-all.de.txt");
+
+    #[test]
+    fn parses_localized_upgrade() {
+        let output = fixture(UPGRADE);
+        let pipx = Pipx { pipx: None };
+        assert_eq!(pipx.updated_packages(&output), Some(2));
+        assert_eq!(
+            pipx.summary(&output),
+            ["Upgraded 2 packages: black 24.4.0 -> 24.4.2, ruff 0.4.4 -> 0.4.8"]
+        );
+    }
+}
@@ -0,0 +1,58 @@
+// Updates Rust toolchains and components.
+// The toolchain version is recorded before the update, so the cargo updater can tell that its packages
+// were built with an older toolchain and rebuild them (see cargo.rs).
+
+use super::{locate, quote, Updater};
+use crate::config;
+
+// Function to get the path of the toolchain version recorded before rustup updated it. It is kept until
+// cargo has rebuilt its packages, so an update whose rebuild failed or didn't run is still noticed.
+pub fn toolchain_record_path() -> String {
+    format!("{}/cronup.toolchain", config::log_home())
+}
+
+pub struct Rustup {
+    rustup: Option<String>, // The path to the Rustup executable, if installed.
+}
+
+impl Rustup {
+    pub fn new() -> Self {
+        Rustup {
+            rustup: locate("rustup"),
+        }
+    }
+}
+
+impl Updater for Rustup {
+    fn name(&self) -> &'static str {
+        "rustup"
+    }
+
+    fn detect(&self) -> bool {
+        self.rustup.is_some()
+    }
+
+    fn commands(&self) -> Vec<String> {
+        let rustup = quote(self.rustup.as_deref().unwrap_or("rustup"));
+        let mut commands = Vec::new();
+        if let Some(rustc) = locate("rustc") {
+            // Record the toolchain version, unless an earlier one still waits for cargo's rebuild.
+            commands.push(format!(
+                "{{ [ -e {record} ] || {} --version > {record} || rm -f {record}; }}",
+                quote(&rustc),
+                record = quote(&toolchain_record_path())
+            ));
+        }
+        // Update Rust toolchains and components.
+        commands.push(format!("{} update", rustup));
+        commands
+    }
+
+    fn check_commands(&self) -> Vec<String> {
+        // List the toolchains and components with available updates.
+        vec![format!(
+            "{} check",
+            quote(self.rustup.as_deref().unwrap_or("rustup"))
+        )]
+    }
+}
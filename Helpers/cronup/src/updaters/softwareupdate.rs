@@ -0,0 +1,61 @@
+// Downloads or installs macOS software updates through `softwareupdate`.
+// By default updates are only downloaded, so they are ready to install whenever it suits.
+// Set `CRONUP_MODE_SOFTWAREUPDATE=install` to install them as well; the commands then run through sudo
+// (see sudo.rs), and the machine is never restarted on its own.
+
+use super::{locate, quote, Updater};
+use crate::config;
+
+pub struct SoftwareUpdate {
+    softwareupdate: Option<String>, // The path to the softwareupdate executable, if available.
+}
+
+impl SoftwareUpdate {
+    pub fn new() -> Self {
+        SoftwareUpdate {
+            softwareupdate: locate("softwareupdate"),
+        }
+    }
+
+    // Returns whether updates are installed rather than only downloaded.
+    fn installs(&self) -> bool {
+        config::get_text_setting("MODE", "softwareupdate").as_deref() == Some("install")
+    }
+}
+
+impl Updater for SoftwareUpdate {
+    fn name(&self) -> &'static str {
+        "softwareupdate"
+    }
+
+    fn detect(&self) -> bool {
+        self.softwareupdate.is_some()
+    }
+
+    fn needs_sudo(&self) -> bool {
+        self.installs()
+    }
+
+    fn commands(&self) -> Vec<String> {
+        let softwareupdate = quote(self.softwareupdate.as_deref().unwrap_or("softwareupdate"));
+        let action = if self.installs() {
+            "--install"
+        } else {
+            "--download"
+        };
+        vec![
+            // List the available updates so the log shows what is pending.
+            format!("{} --list", softwareupdate),
+            // Download (or install) all available updates.
+            format!("{} {} --all", softwareupdate, action),
+        ]
+    }
+
+    fn check_commands(&self) -> Vec<String> {
+        // List the available macOS updates.
+        vec![format!(
+            "{} --list",
+            quote(self.softwareupdate.as_deref().unwrap_or("softwareupdate"))
+        )]
+    }
+}
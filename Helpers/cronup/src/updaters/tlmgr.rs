@@ -0,0 +1,45 @@
+// Updates TeX Live and all of its installed packages through tlmgr.
+// MacTeX installs TeX Live owned by root, so either the TeX Live tree must be writable by the
+// user running cronup, or `CRONUP_SUDO_TLMGR=1` runs tlmgr through sudo (see sudo.rs).
+
+use super::{locate, quote, Updater};
+
+pub struct Tlmgr {
+    tlmgr: Option<String>, // The path to the tlmgr executable, if installed.
+}
+
+impl Tlmgr {
+    pub fn new() -> Self {
+        Tlmgr {
+            tlmgr: locate("tlmgr"),
+        }
+    }
+}
+
+impl Updater for Tlmgr {
+    fn name(&self) -> &'static str {
+        "tlmgr"
+    }
+
+    fn detect(&self) -> bool {
+        self.tlmgr.is_some()
+    }
+
+    fn commands(&self) -> Vec<String> {
+        vec![
+            // Update tlmgr itself first, then every installed package.
+            format!(
+                "{} update --self --all",
+                quote(self.tlmgr.as_deref().unwrap_or("tlmgr"))
+            ),
+        ]
+    }
+
+    fn check_commands(&self) -> Vec<String> {
+        // List the packages that would be updated, without updating them.
+        vec![format!(
+            "{} update --list --self --all",
+            quote(self.tlmgr.as_deref().unwrap_or("tlmgr"))
+        )]
+    }
+}
@@ -0,0 +1,204 @@
+// Timestamp formats for the log files the helpers write and cleanlog prunes, shared so that cleanlog
+// always parses timestamps the way they were written.
+// Every log line starts with "[<timestamp>] ", or is a JSON object whose first key is "timestamp" when a
+// helper writes structured logs. The format of a log file is configured through
+// `LOG_TIMESTAMP_FORMAT_<LOG>` (e.g. `LOG_TIMESTAMP_FORMAT_CRONUP_BREW` for `cronup.brew.log`) or, for
+// all files, `LOG_TIMESTAMP_FORMAT`; its time zone likewise through `LOG_TIMESTAMP_ZONE[_<LOG>]`.
+// Formats are "default" (2024-01-31 08:00:00), "rfc3339" (2024-01-31T08:00:00+01:00), or a chrono
+// strftime pattern that doesn't contain "]". An invalid pattern falls back to the default format, so a
+// typo can't break logging. Zones are "local" (the default) or "utc".
+// Set the variables for the writing helper and for cleanlog alike, e.g. in both LaunchAgent plists.
+// A log is divided into sections, one per run, each starting with a line containing `RUN_START_MARKER`,
+// so cleanlog can prune and report whole runs.
+
+// Import various modules from the Rust standard library.
+use std::env;
+
+// The `chrono` crate is used for formatting and parsing dates and times.
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+
+// The message a helper logs first in every run, which starts a new section of the log.
+pub const RUN_START_MARKER: &str = "=== RUN START ===";
+
+// The start of a structured log line, followed by its timestamp.
+const JSON_TIMESTAMP_PREFIX: &str = "{\"timestamp\":\"";
+
+// The format used unless another one is configured.
+const DEFAULT_PATTERN: &str = "%Y-%m-%d %H:%M:%S";
+
+// The RFC 3339 format with seconds and a numeric offset.
+const RFC3339_PATTERN: &str = "%Y-%m-%dT%H:%M:%S%:z";
+
+// The time zone timestamps are written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Zone {
+    Local, // The system's local time zone.
+    Utc,   // Coordinated Universal Time.
+}
+
+// The timestamp format of a log file.
+#[derive(Debug, Clone)]
+pub struct TimestampFormat {
+    pattern: String, // The chrono strftime pattern.
+    zone: Zone,      // The time zone timestamps are written in.
+}
+
+impl Default for TimestampFormat {
+    fn default() -> Self {
+        TimestampFormat {
+            pattern: String::from(DEFAULT_PATTERN),
+            zone: Zone::Local,
+        }
+    }
+}
+
+impl TimestampFormat {
+    // Returns the format configured for a log file, given its file name (e.g. "cronup.brew.log").
+    pub fn for_log(file_name: &str) -> Self {
+        let key = log_key(file_name);
+        Self::from_settings(
+            setting("LOG_TIMESTAMP_FORMAT", &key).as_deref(),
+            setting("LOG_TIMESTAMP_ZONE", &key).as_deref(),
+        )
+    }
+
+    // Returns the format for the given format and zone settings.
+    // A pattern that chrono can't format, or that contains "]" and so couldn't be parsed back from a
+    // log line, is replaced with the default, since formatting with it would panic.
+    fn from_settings(format: Option<&str>, zone: Option<&str>) -> Self {
+        let pattern = match format {
+            None | Some("default") => String::from(DEFAULT_PATTERN),
+            Some("rfc3339") => String::from(RFC3339_PATTERN),
+            Some(pattern)
+                if pattern.contains(']')
+                    || StrftimeItems::new(pattern).any(|item| matches!(item, Item::Error)) =>
+            {
+                String::from(DEFAULT_PATTERN)
+            }
+            Some(pattern) => pattern.to_string(),
+        };
+        let zone = match zone {
+            Some(zone) if zone.eq_ignore_ascii_case("utc") => Zone::Utc,
+            _ => Zone::Local,
+        };
+        TimestampFormat { pattern, zone }
+    }
+
+    // Returns the current time in this format.
+    pub fn now(&self) -> String {
+        match self.zone {
+            Zone::Local => Local::now().format(&self.pattern).to_string(),
+            Zone::Utc => Utc::now().format(&self.pattern).to_string(),
+        }
+    }
+
+    // Parses the timestamp at the start of a log line, "[<timestamp>] ..." or
+    // "{\"timestamp\":\"<timestamp>\",...}".
+    // Returns `None` if the line doesn't start with a timestamp in this format.
+    pub fn parse_line(&self, line: &str) -> Option<DateTime<Utc>> {
+        if let Some(rest) = line.strip_prefix(JSON_TIMESTAMP_PREFIX) {
+            return self.parse(&rest[..rest.find('"')?]);
+        }
+        let rest = line.strip_prefix('[')?;
+        self.parse(&rest[..rest.find(']')?])
+    }
+
+    // Parses a timestamp in this format. Timestamps without an offset are read in the configured zone.
+    pub fn parse(&self, text: &str) -> Option<DateTime<Utc>> {
+        if let Ok(timestamp) = DateTime::parse_from_str(text, &self.pattern) {
+            return Some(timestamp.with_timezone(&Utc));
+        }
+        let naive = NaiveDateTime::parse_from_str(text, &self.pattern).ok()?;
+        match self.zone {
+            Zone::Local => Some(
+                Local
+                    .from_local_datetime(&naive)
+                    .earliest()?
+                    .with_timezone(&Utc),
+            ),
+            Zone::Utc => Some(Utc.from_utc_datetime(&naive)),
+        }
+    }
+}
+
+// Function to derive the environment variable suffix of a log file, e.g. "CRONUP_BREW" for "cronup.brew.log".
+fn log_key(file_name: &str) -> String {
+    file_name
+        .strip_suffix(".log")
+        .unwrap_or(file_name)
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+// Function to read a setting for a log file, falling back to the setting for all files.
+fn setting(name: &str, key: &str) -> Option<String> {
+    [format!("{}_{}", name, key), name.to_string()]
+        .iter()
+        .find_map(|variable| env::var(variable).ok())
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Checks that a timestamp written now in the format parses back to about the current time.
+    fn assert_round_trip(format: &TimestampFormat) {
+        let before = Utc::now().timestamp();
+        let parsed = format
+            .parse_line(&format!("[{}] message", format.now()))
+            .unwrap()
+            .timestamp();
+        assert!((before..=Utc::now().timestamp()).contains(&parsed));
+    }
+
+    #[test]
+    fn formats_round_trip() {
+        assert_round_trip(&TimestampFormat::from_settings(None, None));
+        assert_round_trip(&TimestampFormat::from_settings(Some("rfc3339"), None));
+        assert_round_trip(&TimestampFormat::from_settings(
+            Some("%d.%m.%Y %H:%M:%S"),
+            None,
+        ));
+        assert_round_trip(&TimestampFormat::from_settings(None, Some("UTC")));
+    }
+
+    #[test]
+    fn utc_zone_is_read_as_utc() {
+        let format = TimestampFormat::from_settings(None, Some("utc"));
+        assert_eq!(format.zone, Zone::Utc);
+        assert_eq!(
+            format.parse("2024-01-31 08:00:00").unwrap().to_rfc3339(),
+            "2024-01-31T08:00:00+00:00"
+        );
+    }
+
+    #[test]
+    fn json_lines_are_parsed() {
+        let format = TimestampFormat::from_settings(Some("rfc3339"), None);
+        let line = "{\"timestamp\":\"2024-01-31T08:00:00+01:00\",\"line\":\"[x]\"}";
+        assert_eq!(
+            format.parse_line(line).unwrap().to_rfc3339(),
+            "2024-01-31T07:00:00+00:00"
+        );
+        assert!(format.parse_line("no timestamp").is_none());
+    }
+
+    #[test]
+    fn invalid_patterns_fall_back_to_the_default() {
+        for pattern in ["%Q", "%Y-%m-%d]", "%"] {
+            let format = TimestampFormat::from_settings(Some(pattern), None);
+            assert_eq!(format.pattern, DEFAULT_PATTERN);
+            format.now();
+        }
+    }
+}
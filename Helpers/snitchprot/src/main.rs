@@ -1,17 +1,94 @@
 // This program monitors the connection state of a Proton VPN and automatically manages Little Snitch firewall profiles
 // When the VPN connects, it disables Little Snitch, and when VPN disconnects, it enables a specific "VPN Off" profile
+//
+// Usage:
+//   snitchprot                             Check the VPN state once and apply the matching profile (run by launchd)
+//   snitchprot state [--json]              Print the current VPN state and the profile snitchprot maintains for it
+//   snitchprot set-profile <name> [--json] Activate a Little Snitch profile, or deactivate all with "none"
+//   snitchprot override --profile <name> --for <duration> [--json]
+//                                          Enforce a profile and suspend automatic switching, e.g. "--for 1h"
+//   snitchprot override --clear [--json]   End a manual override early and resume automatic switching
+//   snitchprot serve [--port <port> | --socket <path>]
+//                                          Serve the state as JSON over HTTP until killed, for dashboards
+//   snitchprot de-escalate [--json]        Reverse the escalation steps taken, e.g. to turn Wi-Fi back on
+// Lowering protection can be delayed until the VPN has been seen as connected in several consecutive
+// runs (SNITCHPROT_OBSERVATIONS, default 1), so a flapping connection doesn't toggle Little Snitch.
+// Raising protection on disconnect always happens immediately. Switches discarded this way are counted
+// as suppressed flips, which `state` and the status endpoint report.
+// A manual override is persisted in the preferences, so it survives restarts of the launchd job, and
+// is reported by `state` until it expires.
+// If Little Snitch isn't installed, VPN transitions are still tracked and logged, but profile changes
+// are skipped and reported once with a notification instead of failing on every run.
+// After every transition the external IP address and its coarse location are looked up and logged,
+// to verify that "connected" really means traffic leaves through the VPN. Set SNITCHPROT_PRIVACY=1
+// to disable the lookup, which sends a request to a third-party service.
+//
+// The resolvers macOS uses (State:/Network/Global/DNS in the dynamic store, read through scutil) are
+// checked on every run and changes are logged, since firewall rules don't help when DNS is wrong.
+// With SNITCHPROT_DNS_SERVERS set (separated by spaces or commas), those servers are pinned on the
+// network service SNITCHPROT_DNS_SERVICE (default "Wi-Fi") while the VPN is disconnected, and the
+// pin is removed again once it connects, so the VPN's DNS takes over. Like profile switching, this
+// is suspended during a manual override.
+//
+// With fast user switching, every logged-in user's LaunchAgent runs at the same time, but Little Snitch
+// profiles apply to the whole machine. Lowering protection is therefore only done while one of the
+// users in SNITCHPROT_USERS (separated by spaces or commas; by default the user running snitchprot)
+// owns the active GUI session, so an inactive user's agent can't disable Little Snitch for the active
+// user. Raising protection on disconnect is never suppressed, since it's the safe direction.
+// Suppressed changes are logged and applied by the next refresh once a configured user is active.
+//
+// If the "VPN Off" profile can't be enabled (e.g. Little Snitch rejects the CLI call), snitchprot
+// escalates through the steps in SNITCHPROT_ESCALATION (separated by spaces or commas, default "notify"),
+// taking the next one after every SNITCHPROT_ESCALATION_AFTER failed attempts (default 3):
+//   notify       post a notification that the machine is unprotected
+//   pf           load packet filter rules blocking all outgoing traffic except on loopback and VPN tunnel
+//                (utun) interfaces, or the rules in the file SNITCHPROT_PF_RULES, into the anchor
+//                com.apple/snitchprot and enable pf
+//   network-off  turn off Wi-Fi on SNITCHPROT_WIFI_DEVICE (default en0)
+// Every step taken is logged and recorded, and reversed in the opposite order as soon as the profile
+// snitchprot maintains is applied again, or on request with `de-escalate`.
+//
+// A VPN that has kept Little Snitch disabled for longer than SNITCHPROT_MAX_UNPROTECTED (default 12h,
+// "0" to never check) may be stuck as "connected" in scutil while traffic leaves directly. It is then
+// verified by checking that the default route goes through a tunnel (utun or ipsec) interface. If it
+// doesn't, protection is re-enabled as if the VPN had disconnected, with a notification, until a later
+// verification succeeds; if it does, the next verification is due after another period.
+//
+// Right after a disconnect, the restrictive profile may also block what's needed to reconnect, e.g. the
+// VPN client itself or a captive-portal helper. With SNITCHPROT_EXCEPTION_APPS set (executable paths,
+// separated by commas), the rule group SNITCHPROT_EXCEPTION_GROUP (default "snitchprot exceptions") is
+// enabled for SNITCHPROT_EXCEPTION_WINDOW (default 2m) when protection is raised on a disconnect, and
+// disabled again once the window expires or the VPN connects. The group's allow rules are written to
+// snitchprot-exceptions.lsrules next to the log; subscribe to that file once in Little Snitch to create it.
+//
+// For testing, SNITCHPROT_FIXTURES names a directory whose mock executables replace sudo, scutil,
+// littlesnitch, networksetup, pfctl, osascript, curl and route (looked up by file name). The preferences are then kept in its
+// "preferences" file (one key=value per line) and the console user is read from its "console_user"
+// file, so tests never touch the real preferences or firewall. See tests/scenarios.rs.
+//
+// `serve` is a long-running server (e.g. a LaunchAgent with KeepAlive) that answers GET / and
+// GET /status.json with the JSON of `state --json`, including the last VPN transitions. It listens on
+// 127.0.0.1 only, on SNITCHPROT_STATUS_PORT (default 18231) unless --port is given, or on a Unix socket.
+//
+// The subcommands are meant to be called from Shortcuts.app or other automations. Their JSON output is
+// a single object on one line and the exit codes are stable: 0 on success, 1 on failure, 2 on misuse.
 
 // Standard library imports
 use std::env; // For reading environment variables
 use std::error::Error; // Provides the Error trait for error handling
-use std::io::Write; // Provides writing capabilities for files
-use std::path::PathBuf;
-use std::process::Command; // Allows executing system commands
-use std::time::{SystemTime, UNIX_EPOCH}; // For working with system time and timestamps // For path manipulation
+use std::ffi::c_void; // For the opaque pointers of the System Configuration API
+use std::io::{BufRead, BufReader, Read, Write}; // Provides writing capabilities for files and status requests
+use std::net::{Ipv4Addr, TcpListener}; // For the status endpoint on localhost
+use std::os::unix::net::UnixListener; // For the status endpoint on a Unix socket
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio}; // Allows executing system commands and setting the exit code
+use std::time::{Duration, SystemTime, UNIX_EPOCH}; // For working with system time and timestamps // For path manipulation
 
 // External crate imports
-use chrono::Local; // For formatted date/time handling
-                   // Core Foundation imports (macOS specific framework)
+use chrono::{Local, TimeZone}; // For formatted date/time handling
+use logstamp::TimestampFormat; // For the configured log timestamp format
+
+// Core Foundation imports (macOS specific framework)
 use core_foundation::base::TCFType; // Trait for Core Foundation types
 use core_foundation::date::{CFDate, CFDateRef}; // For working with CF dates
 use core_foundation::string::{CFString, CFStringRef}; // For CF string handling
@@ -25,8 +102,65 @@ use core_foundation_sys::preferences::{
 };
 use core_foundation_sys::string::CFStringGetTypeID; // For string type identification
 
+// System Configuration framework, for the user owning the active GUI session
+#[link(name = "SystemConfiguration", kind = "framework")]
+extern "C" {
+    fn SCDynamicStoreCopyConsoleUser(
+        store: *const c_void,
+        uid: *mut u32,
+        gid: *mut u32,
+    ) -> CFStringRef;
+}
+
 // Constants
 const APP_ID: &str = "gg.hw.snitchprot"; // Unique identifier for the app's preferences
+const LITTLESNITCH: &str = "/Applications/Little Snitch.app/Contents/Components/littlesnitch"; // Little Snitch CLI
+const SUDO: &str = "/usr/bin/sudo"; // Runs the privileged commands
+const SCUTIL: &str = "/usr/sbin/scutil"; // Reports the VPN connection status and the DNS configuration
+const NETWORKSETUP: &str = "/usr/sbin/networksetup"; // Pins the DNS servers of a network service
+const PFCTL: &str = "/sbin/pfctl"; // Loads the packet filter fallback rules
+const OSASCRIPT: &str = "/usr/bin/osascript"; // Posts notifications
+const CURL: &str = "/usr/bin/curl"; // Looks up the external IP address
+const ROUTE: &str = "/sbin/route"; // Reports the interface of the default route, to verify the VPN carries the traffic
+const VPN_OFF_PROFILE: &str = "VPN Off"; // Profile enabled while the VPN is disconnected
+
+const DEFAULT_DNS_SERVICE: &str = "Wi-Fi"; // Network service whose DNS servers are pinned, unless configured
+
+const EGRESS_LOOKUP_URL: &str = "https://ipinfo.io/json"; // Reports the external IP address and its location
+
+// Exit codes of the subcommands, kept stable for automations
+const EXIT_FAILURE: i32 = 1; // The requested action failed
+const EXIT_USAGE: i32 = 2; // The command line was invalid
+
+// Number of consecutive observations required before protection is lowered, unless configured
+const DEFAULT_OBSERVATIONS: u32 = 1;
+
+const MAX_TRANSITIONS: usize = 10; // Number of recent VPN transitions kept for `state` and the status endpoint
+const DEFAULT_STATUS_PORT: u16 = 18231; // Port of the status endpoint, unless configured
+const STATUS_REQUEST_TIMEOUT: Duration = Duration::from_secs(5); // How long a client may take to send its request
+
+// Escalation when the "VPN Off" profile can't be enabled
+const DEFAULT_ESCALATION: &str = "notify"; // Escalation steps, unless configured
+const DEFAULT_ESCALATION_AFTER: u32 = 3; // Failed attempts before each escalation step, unless configured
+const ESCALATION_STEPS: &[&str] = &["notify", "pf", "network-off"]; // The known escalation steps
+const PF_ANCHOR: &str = "com.apple/snitchprot"; // Anchor of the fallback rules, evaluated by the default pf.conf
+const DEFAULT_PF_RULES: &str =
+    "pass out quick on lo0 all\npass out quick on utun all\nblock drop out all\n"; // Fallback rules, unless configured
+const DEFAULT_WIFI_DEVICE: &str = "en0"; // Wi-Fi device turned off by the last escalation step, unless configured
+
+// Verification of a long-lasting VPN connection
+const DEFAULT_MAX_UNPROTECTED: &str = "12h"; // How long Little Snitch stays disabled before the VPN is verified, unless configured
+const TUNNEL_INTERFACES: &[&str] = &["utun", "ipsec"]; // Name prefixes of the interfaces VPN tunnels use
+
+// Exceptions for reconnecting after a disconnect
+const DEFAULT_EXCEPTION_GROUP: &str = "snitchprot exceptions"; // Rule group allowing the exception apps, unless configured
+const DEFAULT_EXCEPTION_WINDOW: &str = "2m"; // How long the exceptions stay enabled after a disconnect, unless configured
+const EXCEPTION_RULES_FILE: &str = "snitchprot-exceptions.lsrules"; // Rules of the group, next to the log
+
+// Usage message printed on invalid command lines
+const USAGE: &str = "usage: snitchprot [state [--json] | set-profile <name> [--json] | \
+                     override (--profile <name> --for <duration> | --clear) [--json] | \
+                     serve [--port <port> | --socket <path>] | de-escalate [--json]]";
 
 // Function to get the log file path using environment variable
 fn get_log_path() -> PathBuf {
@@ -40,8 +174,9 @@ fn get_log_path() -> PathBuf {
 }
 
 // Helper function to get current timestamp in formatted string
+// The format is configurable (see the logstamp crate) and shared with cleanlog
 fn get_timestamp() -> String {
-    Local::now().format("[%Y-%m-%d %H:%M:%S]").to_string()
+    format!("[{}]", TimestampFormat::for_log("snitchprot.log").now())
 }
 
 // Function to write a message to the log file with timestamp
@@ -61,8 +196,39 @@ fn log_message(message: &str) -> std::io::Result<()> {
     writeln!(file, "{} {}", timestamp, message)
 }
 
+// Function to get the fixtures directory of the test mode, if SNITCHPROT_FIXTURES is set
+fn fixtures_dir() -> Option<PathBuf> {
+    env::var_os("SNITCHPROT_FIXTURES").map(PathBuf::from)
+}
+
+// Function to get the path of an external executable
+// In test mode, the mock of the same name in the fixtures directory is used instead
+fn executable(path: &str) -> PathBuf {
+    match (fixtures_dir(), Path::new(path).file_name()) {
+        (Some(dir), Some(name)) => dir.join(name),
+        _ => PathBuf::from(path),
+    }
+}
+
+// Function to read the preferences file of the test mode as key/value pairs
+fn read_fixture_preferences(dir: &Path) -> Vec<(String, String)> {
+    std::fs::read_to_string(dir.join("preferences"))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
 // Function to retrieve a preference value from macOS preferences system
 fn get_preference(key: &str) -> Option<String> {
+    // In test mode, preferences come from the fixtures directory
+    if let Some(dir) = fixtures_dir() {
+        return read_fixture_preferences(&dir)
+            .into_iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value);
+    }
     unsafe {
         // Required for Core Foundation API calls
         // Convert the key to a Core Foundation string
@@ -98,6 +264,18 @@ fn get_preference(key: &str) -> Option<String> {
 
 // Function to save a preference value to macOS preferences system
 fn set_preference(key: &str, value: &str) {
+    // In test mode, preferences are written to the fixtures directory; failures surface in the tests
+    if let Some(dir) = fixtures_dir() {
+        let mut preferences = read_fixture_preferences(&dir);
+        preferences.retain(|(name, _)| name != key);
+        preferences.push((key.to_string(), value.to_string()));
+        let content: String = preferences
+            .iter()
+            .map(|(name, value)| format!("{}={}\n", name, value))
+            .collect();
+        let _ = std::fs::write(dir.join("preferences"), content);
+        return;
+    }
     unsafe {
         // Required for Core Foundation API calls
         let key = CFString::new(key);
@@ -113,91 +291,1333 @@ fn set_preference(key: &str, value: &str) {
     }
 }
 
-// Main function where the program logic happens
-fn main() -> Result<(), Box<dyn Error>> {
+// Function to get the current time as a Unix timestamp
+fn unix_time() -> Result<u64, Box<dyn Error>> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs())
+}
+
+// Function to format a Unix timestamp as a local date and time
+fn format_time(time: u64) -> String {
+    Local.timestamp_opt(time as i64, 0).single().map_or_else(
+        || time.to_string(),
+        |date| date.format("%Y-%m-%d %H:%M:%S").to_string(),
+    )
+}
+
+// Function to parse a duration such as "90s", "30m", "1h" or "2d" into seconds
+// A bare number is taken as seconds
+fn parse_duration(value: &str) -> Option<u64> {
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => value.split_at(index),
+        None => (value, "s"),
+    };
+    let multiplier = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return None,
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .filter(|&number| number > 0)?
+        .checked_mul(multiplier)
+}
+
+// A manual override: the enforced profile (None deactivates all profiles) and the Unix timestamp it expires at
+type Override = (Option<String>, u64);
+
+// Function to get the manual override, if one is set
+fn get_override() -> Option<Override> {
+    let until = get_preference("override_until")?.parse::<u64>().ok()?;
+    if until == 0 {
+        return None;
+    }
+    let profile = get_preference("override_profile").filter(|name| name != "none");
+    Some((profile, until))
+}
+
+// Function to get the manual override if it hasn't expired yet
+fn active_override() -> Result<Option<Override>, Box<dyn Error>> {
+    let now = unix_time()?;
+    Ok(get_override().filter(|&(_, until)| until > now))
+}
+
+// Function to check whether Proton VPN is currently connected
+fn vpn_connected() -> Result<bool, Box<dyn Error>> {
     // Run system command to check VPN status
-    let output = Command::new("sudo")
-        .args(&["/usr/sbin/scutil", "--nc", "list"])
+    let output = Command::new(executable(SUDO))
+        .arg(executable(SCUTIL))
+        .args(["--nc", "list"])
         .output()?;
     let output_str = String::from_utf8_lossy(&output.stdout);
 
     // Check if Proton VPN is connected by looking for "proton" and "Connected" in output
-    let vpn_connected = output_str
+    Ok(output_str
         .lines()
-        .any(|line| line.to_lowercase().contains("proton") && line.contains("Connected"));
+        .any(|line| line.to_lowercase().contains("proton") && line.contains("Connected")))
+}
+
+// Function to get the DNS servers macOS currently resolves with, in order of preference
+// They are read from the dynamic store key State:/Network/Global/DNS; without the key, there are none
+fn dns_servers() -> Result<Vec<String>, Box<dyn Error>> {
+    let mut child = Command::new(executable(SCUTIL))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    child
+        .stdin
+        .take()
+        .ok_or("scutil has no standard input")?
+        .write_all(b"show State:/Network/Global/DNS\n")?;
+    let output = child.wait_with_output()?;
 
-    // Set current state based on VPN connection status
-    let current_state = if vpn_connected {
+    // The addresses are listed as "<index> : <address>" inside "ServerAddresses : <array> { ... }"
+    let mut servers = Vec::new();
+    let mut in_addresses = false;
+    for line in String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+    {
+        if line.starts_with("ServerAddresses") {
+            in_addresses = true;
+        } else if in_addresses && line == "}" {
+            break;
+        } else if in_addresses {
+            if let Some((_, address)) = line.split_once(" : ") {
+                servers.push(address.trim().to_string());
+            }
+        }
+    }
+    Ok(servers)
+}
+
+// Function to get the DNS servers to pin while the VPN is disconnected, from SNITCHPROT_DNS_SERVERS
+// Returns none if pinning isn't configured
+fn pinned_dns_servers() -> Vec<String> {
+    env::var("SNITCHPROT_DNS_SERVERS")
+        .unwrap_or_default()
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|server| !server.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+// Function to get the network service whose DNS servers are pinned, from SNITCHPROT_DNS_SERVICE
+fn dns_service() -> String {
+    env::var("SNITCHPROT_DNS_SERVICE")
+        .ok()
+        .filter(|service| !service.trim().is_empty())
+        .unwrap_or_else(|| String::from(DEFAULT_DNS_SERVICE))
+}
+
+// Function to set the DNS servers of a network service; "Empty" as the only server removes them
+// Returns whether networksetup reported success
+fn set_dns_servers(service: &str, servers: &[String]) -> Result<bool, Box<dyn Error>> {
+    Ok(Command::new(executable(SUDO))
+        .arg(executable(NETWORKSETUP))
+        .args(["-setdnsservers", service])
+        .args(servers)
+        .output()?
+        .status
+        .success())
+}
+
+// Function to log DNS changes and pin or unpin the configured DNS servers for the VPN state
+fn check_dns(state: &str) -> Result<(), Box<dyn Error>> {
+    let servers = dns_servers()?.join(", ");
+    if let Some(previous) = get_preference("previous_dns").filter(|previous| *previous != servers) {
+        log_message(&format!(
+            "DNS servers changed from '{}' to '{}'",
+            previous, servers
+        ))?;
+    }
+    set_preference("previous_dns", &servers);
+
+    let pinned = pinned_dns_servers();
+    if pinned.is_empty() {
+        return Ok(());
+    }
+    let service = dns_service();
+    let is_pinned = get_preference("dns_pinned").is_some_and(|value| value == "1");
+
+    if state == "disconnected" && servers != pinned.join(", ") {
+        // Off VPN, the configured servers must be the ones in use
+        log_message(&format!(
+            "Pinning DNS servers of '{}' to '{}'...",
+            service,
+            pinned.join(", ")
+        ))?;
+        if set_dns_servers(&service, &pinned)? {
+            set_preference("dns_pinned", "1");
+            log_message("DNS servers pinned")?;
+        } else {
+            log_message("Pinning DNS servers failed")?;
+        }
+    } else if state == "connected" && is_pinned {
+        // On VPN, remove the pin so the VPN's DNS servers take over
+        log_message(&format!("Removing DNS pin of '{}'...", service))?;
+        if set_dns_servers(&service, &[String::from("Empty")])? {
+            set_preference("dns_pinned", "0");
+            log_message("DNS pin removed")?;
+        } else {
+            log_message("Removing DNS pin failed")?;
+        }
+    }
+    Ok(())
+}
+
+// Function to get the Little Snitch profile snitchprot maintains for a VPN state
+// Returns None when all profiles should be deactivated
+fn expected_profile(state: &str) -> Option<&'static str> {
+    if state == "connected" {
+        None
+    } else {
+        Some(VPN_OFF_PROFILE)
+    }
+}
+
+// Function to check whether the Little Snitch CLI is installed
+fn backend_available() -> bool {
+    executable(LITTLESNITCH).is_file()
+}
+
+// Function to check the Little Snitch CLI before monitoring
+// A missing backend is logged and notified once, until it becomes available again
+fn check_backend() -> Result<bool, Box<dyn Error>> {
+    let available = backend_available();
+    let reported = get_preference("backend_missing_reported").is_some_and(|value| value == "1");
+
+    if available && reported {
+        log_message("Little Snitch is available again, resuming profile changes")?;
+        set_preference("backend_missing_reported", "0");
+    } else if !available && !reported {
+        log_message(&format!(
+            "Little Snitch not found at {} - profile changes skipped: backend unavailable",
+            executable(LITTLESNITCH).display()
+        ))?;
+        notify("Little Snitch is not installed - firewall profiles are not being managed");
+        set_preference("backend_missing_reported", "1");
+    }
+    Ok(available)
+}
+
+// Function to get the user owning the active GUI session (the console user)
+// Returns None while the login window is shown or no user is logged in
+fn console_user() -> Option<String> {
+    // In test mode, the console user comes from the fixtures directory
+    if let Some(dir) = fixtures_dir() {
+        let user = std::fs::read_to_string(dir.join("console_user")).ok()?;
+        let user = user.trim();
+        return (!user.is_empty() && user != "loginwindow").then(|| user.to_string());
+    }
+    unsafe {
+        // Required for System Configuration API calls; a null store uses a temporary session
+        let user = SCDynamicStoreCopyConsoleUser(
+            std::ptr::null(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        );
+        if user.is_null() {
+            return None;
+        }
+        // The returned string is owned by the caller
+        let user = CFString::wrap_under_create_rule(user).to_string();
+        (!user.is_empty() && user != "loginwindow").then_some(user)
+    }
+}
+
+// Function to get the users snitchprot acts for, from SNITCHPROT_USERS (separated by spaces or commas)
+// Defaults to the user running snitchprot, i.e. the owner of the LaunchAgent
+fn configured_users() -> Vec<String> {
+    env::var("SNITCHPROT_USERS")
+        .or_else(|_| env::var("USER"))
+        .unwrap_or_default()
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|user| !user.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+// Function to check whether protection may be lowered for the active GUI session
+// Returns why it may not, e.g. "'alice' is the active user", or None if it may
+// Without any configured user (e.g. USER isn't set), every session is allowed
+fn session_suppression() -> Option<String> {
+    let users = configured_users();
+    if users.is_empty() {
+        return None;
+    }
+    match console_user() {
+        Some(user) if users.contains(&user) => None,
+        Some(user) => Some(format!("'{}' is the active user", user)),
+        None => Some(String::from("no user is active")),
+    }
+}
+
+// Function to post a macOS notification; failures are ignored since the notification is only a hint
+fn notify(message: &str) {
+    let script = format!(
+        "display notification \"{}\" with title \"snitchprot\"",
+        message.replace('\\', "\\\\").replace('"', "\\\"")
+    );
+    let _ = Command::new(executable(OSASCRIPT))
+        .args(["-e", &script])
+        .output();
+}
+
+// Function to report a subcommand that needs Little Snitch when it isn't installed
+fn backend_unavailable(profile: Option<&str>, json: bool) -> ! {
+    if json {
+        println!(
+            "{{\"ok\":false,\"profile\":{},\"error\":\"backend unavailable\"}}",
+            json_optional(profile)
+        );
+    } else {
+        eprintln!("snitchprot: Little Snitch is not installed");
+    }
+    process::exit(EXIT_FAILURE);
+}
+
+// Function to activate a Little Snitch profile, or deactivate all profiles when given None
+// Returns whether the Little Snitch CLI reported success
+fn apply_profile(profile: Option<&str>) -> Result<bool, Box<dyn Error>> {
+    let mut command = Command::new(executable(SUDO));
+    command.arg(executable(LITTLESNITCH)).arg("profile");
+    match profile {
+        Some(name) => command.args(["-a", name]),
+        None => command.arg("-d"),
+    };
+    Ok(command.output()?.status.success())
+}
+
+// Function to get the escalation steps to take, from SNITCHPROT_ESCALATION (separated by spaces or commas)
+// Unknown steps are ignored
+fn escalation_steps() -> Vec<&'static str> {
+    env::var("SNITCHPROT_ESCALATION")
+        .unwrap_or_else(|_| String::from(DEFAULT_ESCALATION))
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter_map(|step| {
+            ESCALATION_STEPS
+                .iter()
+                .copied()
+                .find(|known| *known == step)
+        })
+        .collect()
+}
+
+// Function to get the number of failed attempts before each escalation step, from SNITCHPROT_ESCALATION_AFTER
+fn escalation_after() -> u32 {
+    env::var("SNITCHPROT_ESCALATION_AFTER")
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(DEFAULT_ESCALATION_AFTER)
+        .max(1)
+}
+
+// Function to get the escalation steps taken so far, in order
+// They are kept in the "escalation" preference, separated by ","
+fn escalation_taken() -> Vec<String> {
+    get_preference("escalation")
+        .unwrap_or_default()
+        .split(',')
+        .filter(|step| !step.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+// Function to get the Wi-Fi device turned off by the network-off step, from SNITCHPROT_WIFI_DEVICE
+fn wifi_device() -> String {
+    env::var("SNITCHPROT_WIFI_DEVICE")
+        .ok()
+        .filter(|device| !device.trim().is_empty())
+        .unwrap_or_else(|| String::from(DEFAULT_WIFI_DEVICE))
+}
+
+// Function to run pfctl through sudo with the given arguments and, if given, rules on standard input
+// Returns whether it succeeded and its output, where pfctl reports its enable token
+fn pfctl(args: &[&str], rules: Option<&str>) -> Result<(bool, String), Box<dyn Error>> {
+    let mut child = Command::new(executable(SUDO))
+        .arg(executable(PFCTL))
+        .args(args)
+        .stdin(if rules.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let (Some(rules), Some(mut stdin)) = (rules, child.stdin.take()) {
+        stdin.write_all(rules.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    let text = format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    Ok((output.status.success(), text))
+}
+
+// Function to take an escalation step
+// Returns whether it succeeded; a step that failed isn't recorded, so it is retried by the next attempt
+fn take_escalation_step(step: &str) -> Result<bool, Box<dyn Error>> {
+    match step {
+        "notify" => {
+            notify(&format!(
+                "The '{}' profile could not be enabled - this Mac is unprotected",
+                VPN_OFF_PROFILE
+            ));
+            Ok(true)
+        }
+        "pf" => {
+            let loaded = match env::var("SNITCHPROT_PF_RULES") {
+                Ok(path) if !path.trim().is_empty() => {
+                    pfctl(&["-a", PF_ANCHOR, "-f", path.trim()], None)?.0
+                }
+                _ => pfctl(&["-a", PF_ANCHOR, "-f", "-"], Some(DEFAULT_PF_RULES))?.0,
+            };
+            if !loaded {
+                return Ok(false);
+            }
+            // Enabling pf with a reference returns a token, which releases just this reference later
+            let (enabled, output) = pfctl(&["-E"], None)?;
+            let token = output
+                .lines()
+                .find_map(|line| line.strip_prefix("Token :"))
+                .map(str::trim)
+                .unwrap_or_default();
+            set_preference("pf_token", token);
+            Ok(enabled)
+        }
+        "network-off" => Ok(Command::new(executable(SUDO))
+            .arg(executable(NETWORKSETUP))
+            .args(["-setairportpower", &wifi_device(), "off"])
+            .output()?
+            .status
+            .success()),
+        _ => Ok(false),
+    }
+}
+
+// Function to reverse an escalation step
+// Returns whether it succeeded
+fn reverse_escalation_step(step: &str) -> Result<bool, Box<dyn Error>> {
+    match step {
+        "pf" => {
+            let flushed = pfctl(&["-a", PF_ANCHOR, "-F", "all"], None)?.0;
+            let token = get_preference("pf_token").unwrap_or_default();
+            let released = token.is_empty() || pfctl(&["-X", &token], None)?.0;
+            if flushed && released {
+                set_preference("pf_token", "");
+            }
+            Ok(flushed && released)
+        }
+        "network-off" => Ok(Command::new(executable(SUDO))
+            .arg(executable(NETWORKSETUP))
+            .args(["-setairportpower", &wifi_device(), "on"])
+            .output()?
+            .status
+            .success()),
+        _ => Ok(true),
+    }
+}
+
+// Function to count a failed attempt to enable the "VPN Off" profile and escalate when it's due
+fn protection_failed() -> Result<(), Box<dyn Error>> {
+    let failures = get_preference("enable_failures")
+        .and_then(|count| count.parse::<u32>().ok())
+        .unwrap_or(0)
+        + 1;
+    set_preference("enable_failures", &failures.to_string());
+
+    let steps = escalation_steps();
+    let mut taken = escalation_taken();
+    let due = ((failures / escalation_after()) as usize).min(steps.len());
+    while taken.len() < due {
+        let step = steps[taken.len()];
+        if !take_escalation_step(step)? {
+            log_message(&format!("Escalation step '{}' failed", step))?;
+            break;
+        }
+        log_message(&format!(
+            "Escalated to '{}' after {} failed attempts to enable the '{}' profile",
+            step, failures, VPN_OFF_PROFILE
+        ))?;
+        taken.push(step.to_string());
+        set_preference("escalation", &taken.join(","));
+    }
+    Ok(())
+}
+
+// Function to reverse every escalation step taken, latest first
+// A step that can't be reversed is kept, so the next call retries it
+fn de_escalate(reason: &str) -> Result<bool, Box<dyn Error>> {
+    let mut taken = escalation_taken();
+    while let Some(step) = taken.last().cloned() {
+        if !reverse_escalation_step(&step)? {
+            log_message(&format!("Reversing escalation step '{}' failed", step))?;
+            return Ok(false);
+        }
+        log_message(&format!("Escalation step '{}' reversed: {}", step, reason))?;
+        taken.pop();
+        set_preference("escalation", &taken.join(","));
+    }
+    Ok(true)
+}
+
+// Function to track the outcome of applying the profile for the VPN state
+// Failing to enable the "VPN Off" profile escalates, and any success ends the escalation
+fn track_protection(profile: Option<&str>, applied: bool) -> Result<(), Box<dyn Error>> {
+    if applied {
+        if get_preference("enable_failures").is_some_and(|count| count != "0") {
+            set_preference("enable_failures", "0");
+        }
+        de_escalate("profile applied")?;
+    } else if profile.is_some() {
+        protection_failed()?;
+    }
+    Ok(())
+}
+
+// Function to get the apps allowed to connect right after a disconnect, from SNITCHPROT_EXCEPTION_APPS
+// Paths may contain spaces, so they are only separated by commas
+fn exception_apps() -> Vec<String> {
+    env::var("SNITCHPROT_EXCEPTION_APPS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|app| !app.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+// Function to get the name of the rule group allowing the exception apps
+fn exception_group() -> String {
+    env::var("SNITCHPROT_EXCEPTION_GROUP")
+        .ok()
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| String::from(DEFAULT_EXCEPTION_GROUP))
+}
+
+// Function to get how long the exceptions stay enabled after a disconnect, in seconds
+fn exception_window() -> u64 {
+    env::var("SNITCHPROT_EXCEPTION_WINDOW")
+        .ok()
+        .and_then(|value| parse_duration(value.trim()))
+        .or_else(|| parse_duration(DEFAULT_EXCEPTION_WINDOW))
+        .unwrap_or_default()
+}
+
+// Function to write the rules of the exception group as a Little Snitch rule group subscription
+// The file is only rewritten when the apps changed, so the subscription isn't updated needlessly
+fn write_exception_rules(group: &str, apps: &[String]) -> std::io::Result<()> {
+    let rules: Vec<String> = apps
+        .iter()
+        .map(|app| {
+            format!(
+                "{{\"action\":\"allow\",\"process\":{},\"remote\":\"any\"}}",
+                json_string(app)
+            )
+        })
+        .collect();
+    let content = format!(
+        "{{\"name\":{},\"description\":\"Enabled by snitchprot for a short time after the VPN disconnects\",\"rules\":[{}]}}\n",
+        json_string(group),
+        rules.join(",")
+    );
+    let path = get_log_path().with_file_name(EXCEPTION_RULES_FILE);
+    if std::fs::read_to_string(&path).is_ok_and(|existing| existing == content) {
+        return Ok(());
+    }
+    std::fs::write(path, content)
+}
+
+// Function to enable or disable a Little Snitch rule group
+// Returns whether the Little Snitch CLI reported success
+fn set_rule_group(name: &str, enabled: bool) -> Result<bool, Box<dyn Error>> {
+    Ok(Command::new(executable(SUDO))
+        .arg(executable(LITTLESNITCH))
+        .args(["rulegroup", if enabled { "-e" } else { "-d" }, name])
+        .output()?
+        .status
+        .success())
+}
+
+// Function to enable the exception group for the configured window after a disconnect raised protection
+fn open_exception_window(now: u64) -> Result<(), Box<dyn Error>> {
+    let apps = exception_apps();
+    if apps.is_empty() {
+        return Ok(());
+    }
+    let group = exception_group();
+    write_exception_rules(&group, &apps)?;
+    if !set_rule_group(&group, true)? {
+        log_message(&format!("Enabling rule group '{}' failed", group))?;
+        return Ok(());
+    }
+    let until = now + exception_window();
+    set_preference("exception_until", &until.to_string());
+    log_message(&format!(
+        "Rule group '{}' enabled for {} apps until {}",
+        group,
+        apps.len(),
+        format_time(until)
+    ))?;
+    Ok(())
+}
+
+// Function to disable the exception group if it was enabled, once its window expired or when forced
+// A failure keeps the window open, so the next run tries again
+fn close_exception_window(now: u64, force: bool) -> Result<(), Box<dyn Error>> {
+    let until = match get_preference("exception_until").and_then(|time| time.parse::<u64>().ok()) {
+        Some(until) if until > 0 => until,
+        _ => return Ok(()),
+    };
+    if !force && now < until {
+        return Ok(());
+    }
+    let group = exception_group();
+    if set_rule_group(&group, false)? {
+        set_preference("exception_until", "0");
+        log_message(&format!("Rule group '{}' disabled", group))?;
+    } else {
+        log_message(&format!("Disabling rule group '{}' failed", group))?;
+    }
+    Ok(())
+}
+
+// Function to get how long Little Snitch may stay disabled for a connected VPN before the connection
+// is verified, in seconds; None if SNITCHPROT_MAX_UNPROTECTED is "0"
+fn max_unprotected() -> Option<u64> {
+    let value = env::var("SNITCHPROT_MAX_UNPROTECTED")
+        .unwrap_or_else(|_| DEFAULT_MAX_UNPROTECTED.to_string());
+    parse_duration(value.trim())
+}
+
+// Function to format a number of seconds as whole hours or minutes, e.g. "12h" or "45m"
+fn format_duration(seconds: u64) -> String {
+    if seconds >= 60 * 60 {
+        format!("{}h", seconds / (60 * 60))
+    } else {
+        format!("{}m", seconds / 60)
+    }
+}
+
+// Function to get the interface of the default route, e.g. "utun3" while a VPN carries the traffic
+fn default_route_interface() -> Option<String> {
+    let output = Command::new(executable(ROUTE))
+        .args(["-n", "get", "default"])
+        .output()
+        .ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| Some(line.trim().strip_prefix("interface:")?.trim().to_string()))
+}
+
+// Function to verify a VPN that kept Little Snitch disabled for longer than SNITCHPROT_MAX_UNPROTECTED,
+// or that failed the last verification, by checking that the default route goes through a tunnel
+// A connection stuck as "connected" while traffic leaves directly is treated as "disconnected", so
+// protection is re-enabled, and reported once; it stays so until a verification succeeds
+// Returns the state to act on
+fn verify_connection(now: u64) -> Result<&'static str, Box<dyn Error>> {
+    let unverified = get_preference("vpn_unverified").is_some_and(|value| value == "1");
+    let since = match get_preference("unprotected_since").and_then(|time| time.parse::<u64>().ok())
+    {
+        Some(time) if time > 0 => time,
+        _ => {
+            // Start counting now, e.g. for Little Snitch disabled by a version that didn't count
+            set_preference("unprotected_since", &now.to_string());
+            now
+        }
+    };
+    let overdue = max_unprotected().is_some_and(|max| now.saturating_sub(since) >= max);
+    if !unverified && !overdue {
+        return Ok("connected");
+    }
+
+    let interface = default_route_interface();
+    let route = interface.as_deref().unwrap_or("unknown");
+    if TUNNEL_INTERFACES
+        .iter()
+        .any(|prefix| route.starts_with(prefix))
+    {
+        log_message(&format!("VPN verified: default route via {}", route))?;
+        set_preference("unprotected_since", &now.to_string());
+        if unverified {
+            set_preference("vpn_unverified", "0");
+        }
+        return Ok("connected");
+    }
+    if !unverified {
+        let message = format!(
+            "Little Snitch has been disabled for {}, but traffic doesn't go through the VPN \
+             (default route via {}) - re-enabling protection",
+            format_duration(now.saturating_sub(since)),
+            route
+        );
+        log_message(&message)?;
+        notify(&message);
+        set_preference("vpn_unverified", "1");
+    }
+    Ok("disconnected")
+}
+
+// Function to check whether external IP lookups are allowed
+fn egress_lookup_enabled() -> bool {
+    env::var("SNITCHPROT_PRIVACY").map_or(true, |value| value.trim() != "1")
+}
+
+// Function to read a string field from a flat JSON object without a full parser
+// Escape sequences aren't decoded, which is fine for IP addresses and place names
+fn json_field(body: &str, key: &str) -> Option<String> {
+    let start = body.find(&format!("\"{}\"", key))? + key.len() + 2;
+    let value = body[start..].trim_start().strip_prefix(':')?.trim_start();
+    let value = value.strip_prefix('"')?;
+    Some(value[..value.find('"')?].to_string())
+}
+
+// Function to look up the external IP address and its coarse location, e.g. "203.0.113.7 (Zurich, CH)"
+// Returns None if the lookup fails, e.g. because the firewall blocks it
+fn lookup_egress() -> Option<String> {
+    let output = Command::new(executable(CURL))
+        .args(["-fsS", "-m", "5", EGRESS_LOOKUP_URL])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let body = String::from_utf8_lossy(&output.stdout);
+    let ip = json_field(&body, "ip")?;
+    let location: Vec<String> = ["city", "country"]
+        .iter()
+        .filter_map(|key| json_field(&body, key))
+        .filter(|value| !value.is_empty())
+        .collect();
+    if location.is_empty() {
+        Some(ip)
+    } else {
+        Some(format!("{} ({})", ip, location.join(", ")))
+    }
+}
+
+// Function to log the external IP address after a transition and remember it for `state`
+fn record_egress() -> Result<(), Box<dyn Error>> {
+    if !egress_lookup_enabled() {
+        return Ok(());
+    }
+    match lookup_egress() {
+        Some(egress) => {
+            log_message(&format!("External IP after transition: {}", egress))?;
+            set_preference("last_egress", &egress);
+        }
+        None => log_message("External IP after transition: unknown (lookup failed)")?,
+    }
+    Ok(())
+}
+
+// Function to quote a string as a JSON string literal
+fn json_string(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// Function to format an optional string as a JSON string literal or null
+fn json_optional(value: Option<&str>) -> String {
+    value.map_or_else(|| String::from("null"), json_string)
+}
+
+// A recorded VPN transition: its Unix timestamp and the states before and after it
+type Transition = (u64, String, String);
+
+// Function to get the most recent VPN transitions, oldest first
+// They are kept in the "transitions" preference as "<time>,<from>,<to>" entries separated by ";"
+fn recent_transitions() -> Vec<Transition> {
+    get_preference("transitions")
+        .unwrap_or_default()
+        .split(';')
+        .filter_map(|entry| {
+            let mut fields = entry.splitn(3, ',');
+            let time = fields.next()?.parse::<u64>().ok()?;
+            Some((time, fields.next()?.to_string(), fields.next()?.to_string()))
+        })
+        .collect()
+}
+
+// Function to record a VPN transition, keeping only the most recent ones
+fn record_transition(from: &str, to: &str) -> Result<(), Box<dyn Error>> {
+    let mut transitions = recent_transitions();
+    transitions.push((unix_time()?, from.to_string(), to.to_string()));
+    let excess = transitions.len().saturating_sub(MAX_TRANSITIONS);
+    let value = transitions[excess..]
+        .iter()
+        .map(|(time, from, to)| format!("{},{},{}", time, from, to))
+        .collect::<Vec<_>>()
+        .join(";");
+    set_preference("transitions", &value);
+    Ok(())
+}
+
+// Everything `state` and the status endpoint report
+struct Snapshot {
+    current_state: &'static str,       // The VPN state observed now
+    recorded_state: Option<String>,    // The state recorded by the last run
+    last_refresh_time: Option<u64>,    // When the profile was last applied
+    backend: &'static str,             // Whether Little Snitch is installed
+    egress: Option<String>,            // The external IP address recorded at the last transition
+    dns: Vec<String>,                  // The DNS servers currently in use
+    session_user: Option<String>,      // The user owning the active GUI session
+    suppression: Option<String>,       // Why snitchprot may not lower protection for it, if so
+    manual_override: Option<Override>, // The active manual override, if any
+    profile: Option<String>,           // The profile snitchprot maintains
+    transitions: Vec<Transition>,      // The most recent VPN transitions
+    escalation: Vec<String>,           // The escalation steps taken, in order
+    suppressed_flips: u64,             // How many unconfirmed switches were discarded
+}
+
+// Function to take a snapshot of the current state
+fn snapshot() -> Result<Snapshot, Box<dyn Error>> {
+    let current_state = if vpn_connected()? {
         "connected"
     } else {
         "disconnected"
     };
 
-    // Get the previous state from preferences
-    let previous_state = get_preference("previous_state").unwrap_or_default();
+    // An active override replaces the profile of the VPN state
+    let manual_override = active_override()?;
+    let profile = match &manual_override {
+        Some((profile, _)) => profile.clone(),
+        None => expected_profile(current_state).map(String::from),
+    };
 
-    // Check if we need to force refresh (if last refresh was more than 60 seconds ago)
-    let force_refresh = match get_preference("last_refresh_time") {
+    Ok(Snapshot {
+        current_state,
+        recorded_state: get_preference("previous_state"),
+        last_refresh_time: get_preference("last_refresh_time")
+            .and_then(|time| time.parse::<u64>().ok()),
+        backend: if backend_available() {
+            "available"
+        } else {
+            "unavailable"
+        },
+        egress: get_preference("last_egress"),
+        dns: dns_servers()?,
+        session_user: console_user(),
+        suppression: session_suppression(),
+        manual_override,
+        profile,
+        transitions: recent_transitions(),
+        escalation: escalation_taken(),
+        suppressed_flips: suppressed_flips(),
+    })
+}
+
+// Function to format a snapshot as a single-line JSON object
+fn state_json(snapshot: &Snapshot) -> String {
+    let override_json = snapshot.manual_override.as_ref().map_or_else(
+        || String::from("null"),
+        |(profile, until)| {
+            format!(
+                "{{\"profile\":{},\"until\":{}}}",
+                json_optional(profile.as_deref()),
+                until
+            )
+        },
+    );
+    format!(
+        "{{\"vpn\":{},\"recorded_state\":{},\"last_refresh_time\":{},\"profile\":{},\"override\":{},\"backend\":{},\"egress\":{},\"console_user\":{},\"lowering_suppressed\":{},\"dns\":[{}],\"transitions\":[{}],\"escalation\":[{}],\"suppressed_flips\":{}}}",
+        json_string(snapshot.current_state),
+        json_optional(snapshot.recorded_state.as_deref()),
+        snapshot
+            .last_refresh_time
+            .map_or_else(|| String::from("null"), |time| time.to_string()),
+        json_optional(snapshot.profile.as_deref()),
+        override_json,
+        json_string(snapshot.backend),
+        json_optional(snapshot.egress.as_deref()),
+        json_optional(snapshot.session_user.as_deref()),
+        json_optional(snapshot.suppression.as_deref()),
+        snapshot
+            .dns
+            .iter()
+            .map(|server| json_string(server))
+            .collect::<Vec<_>>()
+            .join(","),
+        snapshot
+            .transitions
+            .iter()
+            .map(|(time, from, to)| format!(
+                "{{\"time\":{},\"from\":{},\"to\":{}}}",
+                time,
+                json_string(from),
+                json_string(to)
+            ))
+            .collect::<Vec<_>>()
+            .join(","),
+        snapshot
+            .escalation
+            .iter()
+            .map(|step| json_string(step))
+            .collect::<Vec<_>>()
+            .join(","),
+        snapshot.suppressed_flips
+    )
+}
+
+// Subcommand: print the current VPN state and the profile snitchprot maintains for it
+fn print_state(json: bool) -> Result<(), Box<dyn Error>> {
+    let snapshot = snapshot()?;
+    if json {
+        println!("{}", state_json(&snapshot));
+        return Ok(());
+    }
+
+    println!("vpn: {}", snapshot.current_state);
+    println!("backend: {}", snapshot.backend);
+    println!(
+        "recorded state: {}",
+        snapshot.recorded_state.as_deref().unwrap_or("none")
+    );
+    println!("profile: {}", snapshot.profile.as_deref().unwrap_or("none"));
+    println!(
+        "dns: {}",
+        if snapshot.dns.is_empty() {
+            String::from("none")
+        } else {
+            snapshot.dns.join(", ")
+        }
+    );
+    println!(
+        "egress at last transition: {}",
+        snapshot.egress.as_deref().unwrap_or("unknown")
+    );
+    println!(
+        "console user: {}{}",
+        snapshot.session_user.as_deref().unwrap_or("none"),
+        snapshot
+            .suppression
+            .as_deref()
+            .map_or_else(String::new, |reason| format!(
+                " - lowering protection suppressed: {}",
+                reason
+            ))
+    );
+    match &snapshot.manual_override {
+        Some((_, until)) => println!(
+            "override: active until {} - automatic switching suspended",
+            format_time(*until)
+        ),
+        None => println!("override: none"),
+    }
+    if !snapshot.escalation.is_empty() {
+        println!("escalation: {}", snapshot.escalation.join(", "));
+    }
+    println!("suppressed flips: {}", snapshot.suppressed_flips);
+    for (time, from, to) in &snapshot.transitions {
+        println!("transition: {} {} -> {}", format_time(*time), from, to);
+    }
+    Ok(())
+}
+
+// Function to answer a single status request on a stream
+// GET / and GET /status.json are answered with the state as JSON, anything else with an error
+fn answer_status_request<S: Read + Write>(stream: &mut S) -> Result<(), Box<dyn Error>> {
+    let mut request_line = String::new();
+    BufReader::new(&mut *stream).read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/" | "/status.json")) => ("200 OK", state_json(&snapshot()?)),
+        (Some("GET"), _) => ("404 Not Found", String::from("{\"error\":\"not found\"}")),
+        _ => (
+            "405 Method Not Allowed",
+            String::from("{\"error\":\"method not allowed\"}"),
+        ),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}\n",
+        status,
+        body.len() + 1,
+        body
+    )?;
+    Ok(())
+}
+
+// Subcommand: serve the state over HTTP on 127.0.0.1, or on a Unix socket, until killed
+// Failed requests are logged and don't stop the server
+fn serve(port: Option<&str>, socket: Option<&str>) -> Result<(), Box<dyn Error>> {
+    if let Some(path) = socket {
+        // A socket left behind by an earlier server would make binding fail
+        let _ = std::fs::remove_file(path);
+        let listener = UnixListener::bind(path)?;
+        log_message(&format!("Serving the status on {}", path))?;
+        for stream in listener.incoming() {
+            let result = stream.map_err(Box::from).and_then(|mut stream| {
+                stream.set_read_timeout(Some(STATUS_REQUEST_TIMEOUT))?;
+                answer_status_request(&mut stream)
+            });
+            if let Err(error) = result {
+                log_message(&format!("Status request failed: {}", error))?;
+            }
+        }
+        return Ok(());
+    }
+
+    let port = match port {
+        Some(port) => port.parse::<u16>().ok(),
+        None => env::var("SNITCHPROT_STATUS_PORT")
+            .ok()
+            .and_then(|port| port.trim().parse().ok())
+            .or(Some(DEFAULT_STATUS_PORT)),
+    };
+    let Some(port) = port else {
+        eprintln!("{}", USAGE);
+        process::exit(EXIT_USAGE);
+    };
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+    log_message(&format!("Serving the status on 127.0.0.1:{}", port))?;
+    for stream in listener.incoming() {
+        let result = stream.map_err(Box::from).and_then(|mut stream| {
+            stream.set_read_timeout(Some(STATUS_REQUEST_TIMEOUT))?;
+            answer_status_request(&mut stream)
+        });
+        if let Err(error) = result {
+            log_message(&format!("Status request failed: {}", error))?;
+        }
+    }
+    Ok(())
+}
+
+// Subcommand: activate a Little Snitch profile by name, or deactivate all profiles with "none"
+fn set_profile(name: &str, json: bool) -> Result<(), Box<dyn Error>> {
+    let profile = if name == "none" { None } else { Some(name) };
+    if !backend_available() {
+        backend_unavailable(profile, json);
+    }
+    let ok = apply_profile(profile)?;
+
+    if ok {
+        log_message(&format!(
+            "Little Snitch profile set to '{}' on request",
+            name
+        ))?;
+    }
+    if json {
+        println!("{{\"ok\":{},\"profile\":{}}}", ok, json_optional(profile));
+    } else if ok {
+        println!("profile: {}", name);
+    } else {
+        eprintln!("snitchprot: failed to set profile '{}'", name);
+    }
+
+    if !ok {
+        process::exit(EXIT_FAILURE);
+    }
+    Ok(())
+}
+
+// Subcommand: enforce a profile for a while, suspending automatic switching until the override expires
+fn set_override(name: &str, duration: &str, json: bool) -> Result<(), Box<dyn Error>> {
+    let seconds = match parse_duration(duration) {
+        Some(seconds) => seconds,
+        None => {
+            eprintln!("snitchprot: invalid duration '{}'", duration);
+            process::exit(EXIT_USAGE);
+        }
+    };
+    let profile = if name == "none" { None } else { Some(name) };
+    if !backend_available() {
+        backend_unavailable(profile, json);
+    }
+    let ok = apply_profile(profile)?;
+    let until = unix_time()?.saturating_add(seconds);
+
+    if ok {
+        // Persist the override so every following run of the launchd job honors it
+        set_preference("override_profile", name);
+        set_preference("override_until", &until.to_string());
+        set_preference("last_refresh_time", &unix_time()?.to_string());
+        log_message(&format!(
+            "Manual override: Little Snitch profile set to '{}' until {}",
+            name,
+            format_time(until)
+        ))?;
+    }
+    if json {
+        println!(
+            "{{\"ok\":{},\"profile\":{},\"until\":{}}}",
+            ok,
+            json_optional(profile),
+            until
+        );
+    } else if ok {
+        println!("profile: {}", name);
+        println!("override: active until {}", format_time(until));
+    } else {
+        eprintln!("snitchprot: failed to set profile '{}'", name);
+    }
+
+    if !ok {
+        process::exit(EXIT_FAILURE);
+    }
+    Ok(())
+}
+
+// Subcommand: end a manual override early
+// The next monitoring run applies the profile for the VPN state again
+fn clear_override(json: bool) -> Result<(), Box<dyn Error>> {
+    let cleared = active_override()?.is_some();
+    if get_override().is_some() {
+        end_override();
+    }
+    if cleared {
+        log_message("Manual override cleared on request")?;
+    }
+
+    if json {
+        println!("{{\"ok\":true,\"cleared\":{}}}", cleared);
+    } else if cleared {
+        println!("override: cleared");
+    } else {
+        println!("override: none");
+    }
+    Ok(())
+}
+
+// Subcommand: reverse the escalation steps taken, e.g. to get Wi-Fi back while Little Snitch is broken
+// The escalation starts over if the profile still can't be enabled
+fn request_de_escalation(json: bool) -> Result<(), Box<dyn Error>> {
+    let taken = escalation_taken();
+    let ok = de_escalate("on request")?;
+    if ok {
+        set_preference("enable_failures", "0");
+    }
+
+    if json {
+        println!(
+            "{{\"ok\":{},\"reversed\":[{}]}}",
+            ok,
+            taken
+                .iter()
+                .map(|step| json_string(step))
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+    } else if ok && taken.is_empty() {
+        println!("escalation: none");
+    } else if ok {
+        println!("escalation: reversed {}", taken.join(", "));
+    } else {
+        eprintln!("snitchprot: failed to reverse the escalation");
+    }
+
+    if !ok {
+        process::exit(EXIT_FAILURE);
+    }
+    Ok(())
+}
+
+// Function to remove the manual override and make the next run refresh the profile
+fn end_override() {
+    set_preference("override_until", "0");
+    set_preference("last_refresh_time", "0");
+}
+
+// Function to get the number of consecutive "connected" observations required before protection is lowered
+fn required_observations() -> u32 {
+    env::var("SNITCHPROT_OBSERVATIONS")
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(DEFAULT_OBSERVATIONS)
+        .max(1)
+}
+
+// Function to apply hysteresis to the observed VPN state
+// A switch from "disconnected" to "connected" lowers protection, so it only takes effect once it
+// has been observed in enough consecutive runs; until then the previous state is kept
+// Every other observation takes effect immediately
+fn confirm_state(observed_state: &'static str, previous_state: &str) -> &'static str {
+    if observed_state != "connected" || previous_state != "disconnected" {
+        // Nothing to confirm, so forget any partially observed switch and count it as suppressed
+        if get_preference("pending_observations").is_some_and(|count| count != "0") {
+            set_preference("pending_observations", "0");
+            set_preference("suppressed_flips", &(suppressed_flips() + 1).to_string());
+        }
+        return observed_state;
+    }
+
+    // Count this observation on top of the ones from previous runs
+    let observations = get_preference("pending_observations")
+        .and_then(|count| count.parse::<u32>().ok())
+        .unwrap_or(0)
+        + 1;
+
+    if observations >= required_observations() {
+        set_preference("pending_observations", "0");
+        observed_state
+    } else {
+        // Not confirmed yet, so stay "disconnected"
+        set_preference("pending_observations", &observations.to_string());
+        "disconnected"
+    }
+}
+
+// Function to get the number of unconfirmed switches to "connected" that were discarded
+fn suppressed_flips() -> u64 {
+    get_preference("suppressed_flips")
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(0)
+}
+
+// Function to check whether the profile should be refreshed (if last refresh was more than 60 seconds ago)
+fn refresh_due(now: u64) -> Result<bool, Box<dyn Error>> {
+    Ok(match get_preference("last_refresh_time") {
         Some(last_refresh_time_str) => {
             let last_refresh_time: u64 = last_refresh_time_str.parse()?;
-            SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() - last_refresh_time >= 60
+            now.saturating_sub(last_refresh_time) >= 60
         }
         None => true,
+    })
+}
+
+// Default mode: check the VPN state and apply the matching Little Snitch profile
+fn monitor() -> Result<(), Box<dyn Error>> {
+    // Without Little Snitch the VPN state is still tracked, but no profile is applied
+    let backend = check_backend()?;
+
+    // The exceptions after a disconnect expire whatever the VPN state, even during an override
+    if backend {
+        close_exception_window(unix_time()?, false)?;
+    }
+
+    // A manual override suspends automatic switching until it expires
+    if let Some((profile, until)) = get_override() {
+        let now = unix_time()?;
+        if until > now {
+            // Keep enforcing the overriding profile with the regular refresh interval, unless it
+            // lowers protection while another user is active
+            if backend
+                && refresh_due(now)?
+                && (profile.is_some() || session_suppression().is_none())
+            {
+                apply_profile(profile.as_deref())?;
+                set_preference("last_refresh_time", &now.to_string());
+            }
+            return Ok(());
+        }
+        log_message("Manual override expired, resuming automatic switching")?;
+        end_override();
+    }
+
+    // Observe the VPN connection status
+    let observed_state = if vpn_connected()? {
+        "connected"
+    } else {
+        "disconnected"
     };
 
+    // Get the previous state from preferences
+    let previous_state = get_preference("previous_state").unwrap_or_default();
+
+    // A VPN that has kept Little Snitch disabled for long, or failed to prove it carries the traffic,
+    // is verified first; a real disconnect ends a failed verification
+    let observed_state = if observed_state == "disconnected" {
+        if get_preference("vpn_unverified").is_some_and(|value| value == "1") {
+            set_preference("vpn_unverified", "0");
+        }
+        observed_state
+    } else if previous_state == "connected"
+        || get_preference("vpn_unverified").is_some_and(|value| value == "1")
+    {
+        verify_connection(unix_time()?)?
+    } else {
+        observed_state
+    };
+
+    // Set current state once the observation has been confirmed
+    let current_state = confirm_state(observed_state, &previous_state);
+
+    // Check if we need to force refresh (if last refresh was more than 60 seconds ago)
+    let force_refresh = refresh_due(unix_time()?)?;
+
     // If state changed or force refresh is needed
     if current_state != previous_state || force_refresh {
+        let profile = expected_profile(current_state);
+        let suppression = profile.is_none().then(session_suppression).flatten();
         if current_state != previous_state {
-            // Log the state change
+            // Log the state change and keep it for `state` and the status endpoint
             log_message(&format!(
                 "VPN state changed from '{}' to '{}'",
                 previous_state, current_state
             ))?;
+            record_transition(&previous_state, current_state)?;
 
-            if current_state == "connected" {
+            match profile {
+                // Without Little Snitch, only record that the change was skipped
+                _ if !backend => {
+                    log_message("Profile change skipped: backend unavailable")?;
+                }
+                // Don't lower protection for another user's session; the next refresh applies it
+                None if suppression.is_some() => {
+                    log_message(&format!(
+                        "Disabling Little Snitch profile suppressed: {}",
+                        suppression.as_deref().unwrap_or_default()
+                    ))?;
+                }
                 // If VPN connected, disable Little Snitch
-                log_message("Disabling Little Snitch profile...")?;
-                Command::new("sudo")
-                    .args(&[
-                        "/Applications/Little Snitch.app/Contents/Components/littlesnitch",
-                        "profile",
-                        "-d",
-                    ])
-                    .output()?;
-                log_message("Little Snitch profile disabled")?;
-            } else {
+                None => {
+                    log_message("Disabling Little Snitch profile...")?;
+                    let applied = apply_profile(None)?;
+                    if applied {
+                        log_message("Little Snitch profile disabled")?;
+                        set_preference("unprotected_since", &unix_time()?.to_string());
+                        // Reconnecting succeeded, so the exceptions aren't needed anymore
+                        close_exception_window(unix_time()?, true)?;
+                    } else {
+                        log_message("Disabling Little Snitch profile failed")?;
+                    }
+                    track_protection(None, applied)?;
+                }
                 // If VPN disconnected, enable "VPN Off" profile
-                log_message("Enabling 'VPN Off' profile...")?;
-                Command::new("sudo")
-                    .args(&[
-                        "/Applications/Little Snitch.app/Contents/Components/littlesnitch",
-                        "profile",
-                        "-a",
-                        "VPN Off",
-                    ])
-                    .output()?;
-                log_message("Little Snitch profile 'VPN Off' enabled")?;
+                Some(name) => {
+                    log_message(&format!("Enabling '{}' profile...", name))?;
+                    let applied = apply_profile(Some(name))?;
+                    if applied {
+                        log_message(&format!("Little Snitch profile '{}' enabled", name))?;
+                    } else {
+                        log_message(&format!("Enabling '{}' profile failed", name))?;
+                    }
+                    track_protection(Some(name), applied)?;
+                    // Let the configured apps reconnect despite the restrictive profile, once it's active
+                    // A failure is only logged, so the transition is still recorded below
+                    if applied {
+                        if let Err(error) = open_exception_window(unix_time()?) {
+                            log_message(&format!(
+                                "Opening the exception window failed: {}",
+                                error
+                            ))?;
+                        }
+                    }
+                }
             }
-        } else {
+
+            // Record where traffic leaves the machine now
+            record_egress()?;
+        } else if backend && suppression.is_none() {
             // If force refresh, perform same actions but without logging
-            if current_state == "connected" {
-                Command::new("sudo")
-                    .args(&[
-                        "/Applications/Little Snitch.app/Contents/Components/littlesnitch",
-                        "profile",
-                        "-d",
-                    ])
-                    .output()?;
-            } else {
-                Command::new("sudo")
-                    .args(&[
-                        "/Applications/Little Snitch.app/Contents/Components/littlesnitch",
-                        "profile",
-                        "-a",
-                        "VPN Off",
-                    ])
-                    .output()?;
-            }
+            let applied = apply_profile(profile)?;
+            track_protection(profile, applied)?;
         }
 
         // Update preferences with current state and refresh time
@@ -211,6 +1631,35 @@ fn main() -> Result<(), Box<dyn Error>> {
         );
     }
 
-    Ok(())
+    // Check the resolvers on every run, since they can change without a VPN transition
+    check_dns(current_state)
 }
 
+// Main function: dispatch to the subcommand given on the command line
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match args.as_slice() {
+        [] => monitor(),
+        ["state"] => print_state(false),
+        ["state", "--json"] => print_state(true),
+        ["set-profile", name] => set_profile(name, false),
+        ["set-profile", name, "--json"] => set_profile(name, true),
+        ["override", "--profile", name, "--for", duration] => set_override(name, duration, false),
+        ["override", "--profile", name, "--for", duration, "--json"] => {
+            set_override(name, duration, true)
+        }
+        ["override", "--clear"] => clear_override(false),
+        ["override", "--clear", "--json"] => clear_override(true),
+        ["serve"] => serve(None, None),
+        ["serve", "--port", port] => serve(Some(port), None),
+        ["serve", "--socket", path] => serve(None, Some(path)),
+        ["de-escalate"] => request_de_escalation(false),
+        ["de-escalate", "--json"] => request_de_escalation(true),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(EXIT_USAGE);
+        }
+    }
+}
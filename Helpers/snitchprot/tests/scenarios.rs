@@ -0,0 +1,495 @@
+// Scenario tests running snitchprot against the mock executables in tests/fixtures
+// Every test gets its own copy of the fixtures, so the mocks' state and the invocations they
+// record don't leak between tests, and asserts the exact privileged commands snitchprot issued
+
+use std::env;
+use std::fs;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// The mock executables copied into every fixtures directory
+const MOCKS: &[&str] = &[
+    "sudo",
+    "scutil",
+    "littlesnitch",
+    "networksetup",
+    "pfctl",
+    "osascript",
+    "curl",
+    "route",
+];
+
+// The user the tests configure snitchprot to act for
+const USER: &str = "tester";
+
+// A temporary fixtures directory, removed when the test ends
+struct Fixture {
+    dir: PathBuf,
+}
+
+impl Fixture {
+    // Function to create the fixtures directory for a test, with the configured user logged in
+    fn new(name: &str) -> Fixture {
+        let dir = env::temp_dir().join(format!("snitchprot-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let source = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        for mock in MOCKS {
+            // Copying keeps the executable permission
+            fs::copy(source.join(mock), dir.join(mock)).unwrap();
+        }
+        let fixture = Fixture { dir };
+        fixture.write("console_user", USER);
+        fixture
+    }
+
+    // Function to write a file read by snitchprot or the mocks
+    fn write(&self, file: &str, content: &str) {
+        fs::write(self.dir.join(file), content).unwrap();
+    }
+
+    // Function to set the VPN state reported by the mock scutil
+    fn vpn(&self, state: &str) {
+        self.write("vpn", state);
+    }
+
+    // Function to store the state of a previous run, refreshed just now so no refresh is due
+    fn previous_state(&self, state: &str) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        self.write(
+            "preferences",
+            &format!("previous_state={}\nlast_refresh_time={}\n", state, now),
+        );
+    }
+
+    // Function to make the next run refresh the profile, as if the refresh interval had passed
+    fn expire_refresh(&self) {
+        let path = self.dir.join("preferences");
+        let preferences: String = fs::read_to_string(&path)
+            .unwrap_or_default()
+            .lines()
+            .filter(|line| !line.starts_with("last_refresh_time="))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        fs::write(path, preferences).unwrap();
+    }
+
+    // Function to build a snitchprot command with the given arguments and environment in test mode
+    fn command(&self, args: &[&str], vars: &[(&str, &str)]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_snitchprot"));
+        command
+            .args(args)
+            .env("SNITCHPROT_FIXTURES", &self.dir)
+            .env("LOG_HOME", &self.dir)
+            .env("SNITCHPROT_USERS", USER)
+            .env("SNITCHPROT_PRIVACY", "1")
+            .env_remove("SNITCHPROT_OBSERVATIONS")
+            .envs(vars.iter().copied());
+        command
+    }
+
+    // Function to run snitchprot with the given arguments and environment in test mode
+    fn run(&self, args: &[&str], vars: &[(&str, &str)]) -> Output {
+        self.command(args, vars).output().unwrap()
+    }
+
+    // Function to take the privileged commands issued since the last call, with the fixtures
+    // directory stripped from the mock paths, e.g. "sudo littlesnitch profile -d"
+    fn privileged(&self) -> Vec<String> {
+        let path = self.dir.join("invocations");
+        let invocations = fs::read_to_string(&path).unwrap_or_default();
+        let _ = fs::remove_file(&path);
+        let prefix = format!("{}/", self.dir.display());
+        invocations
+            .lines()
+            .filter(|line| line.starts_with("sudo "))
+            .map(|line| line.replace(&prefix, ""))
+            .collect()
+    }
+
+    // Function to read snitchprot's log
+    fn log(&self) -> String {
+        fs::read_to_string(self.dir.join("snitchprot.log")).unwrap_or_default()
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[test]
+fn connect_disables_protection() {
+    let fixture = Fixture::new("connect");
+    fixture.previous_state("disconnected");
+    fixture.vpn("connected");
+
+    assert!(fixture.run(&[], &[]).status.success());
+    assert_eq!(
+        fixture.privileged(),
+        ["sudo scutil --nc list", "sudo littlesnitch profile -d"]
+    );
+    assert!(fixture
+        .log()
+        .contains("VPN state changed from 'disconnected' to 'connected'"));
+}
+
+#[test]
+fn disconnect_enables_vpn_off_profile() {
+    let fixture = Fixture::new("disconnect");
+    fixture.previous_state("connected");
+    fixture.vpn("disconnected");
+
+    assert!(fixture.run(&[], &[]).status.success());
+    assert_eq!(
+        fixture.privileged(),
+        [
+            "sudo scutil --nc list",
+            "sudo littlesnitch profile -a VPN Off"
+        ]
+    );
+}
+
+#[test]
+fn connect_in_another_users_session_keeps_protection() {
+    let fixture = Fixture::new("other-user");
+    fixture.previous_state("disconnected");
+    fixture.vpn("connected");
+    fixture.write("console_user", "someone-else");
+
+    assert!(fixture.run(&[], &[]).status.success());
+    assert_eq!(fixture.privileged(), ["sudo scutil --nc list"]);
+    assert!(fixture
+        .log()
+        .contains("Disabling Little Snitch profile suppressed: 'someone-else' is the active user"));
+}
+
+#[test]
+fn flapping_connection_keeps_protection() {
+    let fixture = Fixture::new("flapping");
+    fixture.previous_state("disconnected");
+    let vars = [("SNITCHPROT_OBSERVATIONS", "2")];
+
+    // A connection that drops again before it is confirmed never lowers protection
+    for state in ["connected", "disconnected", "connected"] {
+        fixture.vpn(state);
+        assert!(fixture.run(&[], &vars).status.success());
+        assert_eq!(fixture.privileged(), ["sudo scutil --nc list"]);
+    }
+
+    // The second consecutive observation confirms it
+    assert!(fixture.run(&[], &vars).status.success());
+    assert_eq!(
+        fixture.privileged(),
+        ["sudo scutil --nc list", "sudo littlesnitch profile -d"]
+    );
+
+    // The connection that dropped before it was confirmed is counted
+    let state = fixture.run(&["state", "--json"], &vars);
+    assert!(String::from_utf8_lossy(&state.stdout).contains("\"suppressed_flips\":1"));
+}
+
+#[test]
+fn cli_failure_is_reported() {
+    let fixture = Fixture::new("cli-failure");
+    fixture.write("littlesnitch_status", "1");
+
+    let output = fixture.run(&["set-profile", "Work", "--json"], &[]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout).trim(),
+        "{\"ok\":false,\"profile\":\"Work\"}"
+    );
+    assert_eq!(fixture.privileged(), ["sudo littlesnitch profile -a Work"]);
+    assert!(!fixture.log().contains("profile set to"));
+}
+
+#[test]
+fn dns_change_is_logged() {
+    let fixture = Fixture::new("dns-change");
+    fixture.previous_state("connected");
+    fixture.vpn("connected");
+    fixture.write("dns", "10.2.0.1\n");
+    assert!(fixture.run(&[], &[]).status.success());
+
+    fixture.write("dns", "192.168.1.1\n");
+    assert!(fixture.run(&[], &[]).status.success());
+    assert!(fixture
+        .log()
+        .contains("DNS servers changed from '10.2.0.1' to '192.168.1.1'"));
+    assert_eq!(
+        fixture.privileged(),
+        ["sudo scutil --nc list", "sudo scutil --nc list"]
+    );
+}
+
+#[test]
+fn dns_is_pinned_off_vpn_and_released_on_vpn() {
+    let fixture = Fixture::new("dns-pin");
+    fixture.previous_state("disconnected");
+    fixture.vpn("disconnected");
+    fixture.write("dns", "192.168.1.1\n");
+    let vars = [("SNITCHPROT_DNS_SERVERS", "9.9.9.9, 149.112.112.112")];
+
+    // Off VPN, the router's DNS server is replaced by the configured ones
+    assert!(fixture.run(&[], &vars).status.success());
+    assert_eq!(
+        fixture.privileged(),
+        [
+            "sudo scutil --nc list",
+            "sudo networksetup -setdnsservers Wi-Fi 9.9.9.9 149.112.112.112"
+        ]
+    );
+
+    // Once they are in use, nothing is changed
+    assert!(fixture.run(&[], &vars).status.success());
+    assert_eq!(fixture.privileged(), ["sudo scutil --nc list"]);
+
+    // On VPN, the pin is removed so the VPN's DNS takes over
+    fixture.vpn("connected");
+    assert!(fixture.run(&[], &vars).status.success());
+    assert_eq!(
+        fixture.privileged(),
+        [
+            "sudo scutil --nc list",
+            "sudo littlesnitch profile -d",
+            "sudo networksetup -setdnsservers Wi-Fi Empty"
+        ]
+    );
+}
+
+#[test]
+fn status_endpoint_reports_transitions() {
+    let fixture = Fixture::new("status");
+    fixture.previous_state("disconnected");
+    fixture.vpn("connected");
+    assert!(fixture.run(&[], &[]).status.success());
+
+    let socket = fixture.dir.join("status.sock");
+    let mut server = fixture
+        .command(&["serve", "--socket", socket.to_str().unwrap()], &[])
+        .spawn()
+        .unwrap();
+    let mut stream = (0..50)
+        .find_map(|_| {
+            thread::sleep(Duration::from_millis(100));
+            UnixStream::connect(&socket).ok()
+        })
+        .expect("the server didn't start");
+    stream
+        .write_all(b"GET /status.json HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    server.kill().unwrap();
+    server.wait().unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+    assert!(response.contains("\"vpn\":\"connected\""));
+    assert!(response.contains("\"from\":\"disconnected\",\"to\":\"connected\"}]"));
+}
+
+#[test]
+fn failed_protection_escalates_and_is_reversed() {
+    let fixture = Fixture::new("escalation");
+    fixture.previous_state("connected");
+    fixture.vpn("disconnected");
+    fixture.write("littlesnitch_status", "1");
+    let vars = [
+        ("SNITCHPROT_ESCALATION", "notify, pf, network-off"),
+        ("SNITCHPROT_ESCALATION_AFTER", "2"),
+    ];
+    let enable = "sudo littlesnitch profile -a VPN Off";
+
+    // Every second failed attempt takes the next step
+    assert!(fixture.run(&[], &vars).status.success());
+    assert_eq!(fixture.privileged(), ["sudo scutil --nc list", enable]);
+    assert!(fixture.log().contains("Enabling 'VPN Off' profile failed"));
+    fixture.expire_refresh();
+    assert!(fixture.run(&[], &vars).status.success());
+    assert_eq!(fixture.privileged(), ["sudo scutil --nc list", enable]);
+    assert!(fixture
+        .log()
+        .contains("Escalated to 'notify' after 2 failed attempts"));
+
+    for _ in 0..2 {
+        fixture.expire_refresh();
+        assert!(fixture.run(&[], &vars).status.success());
+    }
+    assert_eq!(
+        fixture.privileged(),
+        [
+            "sudo scutil --nc list",
+            enable,
+            "sudo scutil --nc list",
+            enable,
+            "sudo pfctl -a com.apple/snitchprot -f -",
+            "sudo pfctl -E"
+        ]
+    );
+    assert!(fs::read_to_string(fixture.dir.join("pf_rules"))
+        .unwrap()
+        .ends_with("block drop out all\n"));
+
+    for _ in 0..2 {
+        fixture.expire_refresh();
+        assert!(fixture.run(&[], &vars).status.success());
+    }
+    assert_eq!(
+        fixture.privileged()[4..],
+        ["sudo networksetup -setairportpower en0 off"]
+    );
+    let state = fixture.run(&["state", "--json"], &vars);
+    assert!(String::from_utf8_lossy(&state.stdout)
+        .contains("\"escalation\":[\"notify\",\"pf\",\"network-off\"]"));
+
+    fixture.privileged();
+
+    // Once the profile is enabled, the steps are reversed, latest first
+    fixture.write("littlesnitch_status", "0");
+    fixture.expire_refresh();
+    assert!(fixture.run(&[], &vars).status.success());
+    assert_eq!(
+        fixture.privileged(),
+        [
+            "sudo scutil --nc list",
+            enable,
+            "sudo networksetup -setairportpower en0 on",
+            "sudo pfctl -a com.apple/snitchprot -F all",
+            "sudo pfctl -X 1234567890"
+        ]
+    );
+    assert!(fixture
+        .log()
+        .contains("Escalation step 'notify' reversed: profile applied"));
+}
+
+#[test]
+fn long_unprotected_vpn_is_verified() {
+    let fixture = Fixture::new("verification");
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    fixture.write(
+        "preferences",
+        &format!(
+            "previous_state=connected\nlast_refresh_time={}\nunprotected_since={}\n",
+            now,
+            now - 13 * 60 * 60
+        ),
+    );
+    fixture.vpn("connected");
+    fixture.write("default_route", "en0");
+    let enable = "sudo littlesnitch profile -a VPN Off";
+
+    // Connected for longer than the limit, but traffic leaves through Wi-Fi
+    assert!(fixture.run(&[], &[]).status.success());
+    assert_eq!(fixture.privileged(), ["sudo scutil --nc list", enable]);
+    let alert = "Little Snitch has been disabled for 13h, but traffic doesn't go through the VPN \
+                 (default route via en0) - re-enabling protection";
+    assert!(fixture.log().contains(alert));
+
+    // Protection stays on while the route doesn't go through the tunnel, reported once
+    fixture.expire_refresh();
+    assert!(fixture.run(&[], &[]).status.success());
+    assert_eq!(fixture.privileged(), ["sudo scutil --nc list", enable]);
+    assert_eq!(fixture.log().matches(alert).count(), 1);
+
+    // Once the tunnel carries the traffic, Little Snitch is disabled again
+    fixture.write("default_route", "utun3");
+    assert!(fixture.run(&[], &[]).status.success());
+    assert_eq!(
+        fixture.privileged(),
+        ["sudo scutil --nc list", "sudo littlesnitch profile -d"]
+    );
+    assert!(fixture
+        .log()
+        .contains("VPN verified: default route via utun3"));
+}
+
+#[test]
+fn disconnect_opens_exception_window() {
+    let fixture = Fixture::new("exceptions");
+    fixture.previous_state("connected");
+    fixture.vpn("disconnected");
+    let vars = [(
+        "SNITCHPROT_EXCEPTION_APPS",
+        "/Applications/Proton VPN.app/Contents/MacOS/Proton VPN, /usr/libexec/captiveagent",
+    )];
+
+    // Raising protection also lets the VPN client and the captive-portal helper through
+    assert!(fixture.run(&[], &vars).status.success());
+    assert_eq!(
+        fixture.privileged(),
+        [
+            "sudo scutil --nc list",
+            "sudo littlesnitch profile -a VPN Off",
+            "sudo littlesnitch rulegroup -e snitchprot exceptions"
+        ]
+    );
+    let rules = fs::read_to_string(fixture.dir.join("snitchprot-exceptions.lsrules")).unwrap();
+    assert!(rules.contains(
+        "{\"action\":\"allow\",\"process\":\"/usr/libexec/captiveagent\",\"remote\":\"any\"}"
+    ));
+    assert!(fixture
+        .log()
+        .contains("Rule group 'snitchprot exceptions' enabled for 2 apps until"));
+
+    // Within the window, the group stays enabled
+    assert!(fixture.run(&[], &vars).status.success());
+    assert_eq!(fixture.privileged(), ["sudo scutil --nc list"]);
+
+    // Once the window expired, the group is disabled and stays so
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    fixture.write(
+        "preferences",
+        &format!(
+            "previous_state=disconnected\nlast_refresh_time={}\nexception_until={}\n",
+            now,
+            now - 1
+        ),
+    );
+    assert!(fixture.run(&[], &vars).status.success());
+    assert_eq!(
+        fixture.privileged(),
+        [
+            "sudo littlesnitch rulegroup -d snitchprot exceptions",
+            "sudo scutil --nc list"
+        ]
+    );
+    assert!(fixture.run(&[], &vars).status.success());
+    assert_eq!(fixture.privileged(), ["sudo scutil --nc list"]);
+}
+
+#[test]
+fn failed_profile_keeps_exceptions_closed() {
+    let fixture = Fixture::new("exceptions-failed");
+    fixture.previous_state("connected");
+    fixture.vpn("disconnected");
+    fixture.write("littlesnitch_status", "1");
+    let vars = [("SNITCHPROT_EXCEPTION_APPS", "/usr/libexec/captiveagent")];
+
+    // Without the restrictive profile there is nothing to make exceptions from
+    assert!(fixture.run(&[], &vars).status.success());
+    assert_eq!(
+        fixture.privileged(),
+        [
+            "sudo scutil --nc list",
+            "sudo littlesnitch profile -a VPN Off"
+        ]
+    );
+    assert!(!fixture.dir.join("snitchprot-exceptions.lsrules").exists());
+    assert!(fixture.log().contains("Enabling 'VPN Off' profile failed"));
+}